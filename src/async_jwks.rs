@@ -0,0 +1,195 @@
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{Algorithm, DecodedJwt, JwkSet, JwksClient, Jwt, JwtErr, ValidationOptions};
+
+/// Non-blocking flavour of `JwksClient` for async request handlers
+///
+/// Tokens whose key is already cached are verified inline. Fetches run on a short lived helper
+/// thread and the returned futures wake the task once they finish, so the client works with any
+/// executor, tokio included, without blocking its worker threads.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, AsyncJwksClient};
+///
+/// async fn handle(client: &AsyncJwksClient, token: &str) {
+///     let decoded = client.verify(token, &[Algorithm::RS256]).await.unwrap();
+///     println!("{}", decoded.claims());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AsyncJwksClient {
+    inner: Arc<JwksClient>,
+}
+
+impl AsyncJwksClient {
+    pub fn new(url: &str) -> Self {
+        Self::from_blocking(JwksClient::new(url))
+    }
+
+    /// Wraps a blocking client, e.g. one with a custom timeout, sharing its cache
+    pub fn from_blocking(client: JwksClient) -> Self {
+        AsyncJwksClient {
+            inner: Arc::new(client),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        self.inner.url()
+    }
+
+    pub async fn key_set(&self) -> Result<Arc<JwkSet>, JwtErr> {
+        if let Some(set) = self.inner.cached_with(None) {
+            return Ok(set);
+        }
+        let inner = self.inner.clone();
+        unblock(move || inner.key_set()).await
+    }
+
+    pub async fn refresh(&self) -> Result<Arc<JwkSet>, JwtErr> {
+        let inner = self.inner.clone();
+        unblock(move || inner.refresh()).await
+    }
+
+    /// Same checks as `JwksClient::verify`
+    pub async fn verify(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        self.verify_with(token, &ValidationOptions::new(algorithms))
+            .await
+    }
+
+    /// Same checks as `JwksClient::verify_with`
+    pub async fn verify_with(
+        &self,
+        token: &str,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, _) = Jwt::decode_unverified(token)?;
+        if let Some(set) = self.inner.cached_with(header.kid()) {
+            return set.verify_with(token, options);
+        }
+        let inner = self.inner.clone();
+        let token = token.to_string();
+        let options = options.clone();
+        unblock(move || inner.verify_with(&token, &options)).await
+    }
+
+    /// Same checks as `verify`, deserializing the claims into `T`
+    pub async fn verify_into<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<T, JwtErr> {
+        Ok(serde_json::from_value(
+            self.verify(token, algorithms).await?.into_claims(),
+        )?)
+    }
+}
+
+pub(crate) struct Shared<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Future resolved by a helper thread running a blocking call
+pub(crate) struct Unblock<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub(crate) fn unblock<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Unblock<T> {
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let handle = shared.clone();
+    thread::spawn(move || {
+        let result = f();
+        let mut shared = handle.lock().unwrap_or_else(|e| e.into_inner());
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    Unblock { shared }
+}
+
+impl<T> Future for Unblock<T> {
+    type Output = T;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Minimal executor for the tests, parks the thread until the future wakes it
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(thread::Thread);
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn test_async_jwks_client() {
+    use crate::{http, EcKey};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let jwks = serde_json::json!({
+        "keys": [serde_json::from_str::<serde_json::Value>(
+            &key.to_public_jwk().unwrap().to_json().unwrap()
+        ).unwrap()]
+    });
+    let (url, hits) = http::serve(Arc::new(Mutex::new(jwks.to_string())));
+    let client =
+        AsyncJwksClient::from_blocking(JwksClient::new(&url).with_timeout(Duration::from_secs(5)));
+    assert_eq!(client.url(), url);
+
+    #[derive(Deserialize)]
+    struct Claims {
+        sub: String,
+    }
+    let token = Jwt::new(serde_json::json!({"sub": "me"}), key, None)
+        .finalize()
+        .unwrap();
+    let claims: Claims = block_on(client.verify_into(&token, &[Algorithm::ES256])).unwrap();
+    assert_eq!(claims.sub, "me");
+    // Served from the cache the second time
+    assert!(block_on(client.clone().verify(&token, &[Algorithm::ES256])).is_ok());
+    assert_eq!(block_on(client.key_set()).unwrap().len(), 1);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert_eq!(block_on(client.refresh()).unwrap().len(), 1);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let unreachable = AsyncJwksClient::new("http://example.com/jwks");
+    assert!(block_on(unreachable.verify(&token, &[Algorithm::ES256])).is_err());
+}
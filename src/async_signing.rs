@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::{Algorithm, Jwt, JwtErr};
+
+/// Signature future returned by `AsyncSigningKey::sign_async`
+pub type SignFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, JwtErr>> + Send + 'a>>;
+
+/// Non-blocking counterpart of `SigningKey`, for keys living behind a network call
+///
+/// `Jwt::finalize_async` awaits the signature instead of blocking the calling thread inside
+/// `sign`. The KMS and Vault keys implement both traits, keys that can only sign asynchronously
+/// implement this one alone and are wrapped with `Jwt::new_async`.
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{Algorithm, AsyncSigningKey, Jwt, SignFuture};
+///
+/// struct RemoteKey;
+///
+/// impl AsyncSigningKey for RemoteKey {
+///     fn sign_async<'a>(&'a self, _algo: &'a Algorithm, input: &'a [u8]) -> SignFuture<'a> {
+///         // Await the remote signing service here
+///         Box::pin(async move { Ok(input.to_vec()) })
+///     }
+///
+///     fn default_algorithm(&self) -> Algorithm {
+///         Algorithm::ES256
+///     }
+/// }
+///
+/// async fn issue() -> String {
+///     Jwt::new_async("claims", RemoteKey, None).finalize_async().await.unwrap()
+/// }
+/// ```
+pub trait AsyncSigningKey {
+    /// Signs `input` using `algo`, failing if the algorithm does not fit the key
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> SignFuture<'a>;
+
+    /// Algorithm a `Jwt` is signed with when `Jwt::new_async` is not given one
+    fn default_algorithm(&self) -> Algorithm;
+
+    /// Identifier of the key, if it has one
+    fn kid(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<K: AsyncSigningKey + ?Sized> AsyncSigningKey for &K {
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> SignFuture<'a> {
+        (**self).sign_async(algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        (**self).default_algorithm()
+    }
+
+    fn kid(&self) -> Option<&str> {
+        (**self).kid()
+    }
+}
+
+impl<T, K> Jwt<T, K>
+where
+    T: Serialize,
+    K: AsyncSigningKey,
+{
+    /// Like `Jwt::new` for keys implementing only `AsyncSigningKey`
+    pub fn new_async(body: T, jwt_key: K, algo: Option<Algorithm>) -> Jwt<T, K> {
+        let algo = algo.unwrap_or_else(|| jwt_key.default_algorithm());
+        Jwt::with_algorithm(body, jwt_key, algo)
+    }
+}
+
+impl<T, K, H> Jwt<T, K, H>
+where
+    T: Serialize,
+    K: AsyncSigningKey,
+    H: Serialize,
+{
+    /// Like `finalize`, awaiting the key's `sign_async`
+    pub async fn finalize_async(&self) -> Result<String, JwtErr> {
+        let input = self.input(self.pkey.kid(), None)?;
+        self.check_compact(&input)?;
+        let signature = self.pkey.sign_async(&self.algo, input.as_bytes()).await?;
+        let signature = self.encoder.encode_segment(&signature);
+        self.check_conformance(format!("{}.{}", input, signature))
+    }
+}
+
+#[test]
+fn test_finalize_async() {
+    use crate::async_jwks::block_on;
+    use crate::{EcKey, SigningKey};
+    use std::sync::Arc;
+
+    struct Remote(Arc<EcKey>);
+
+    impl AsyncSigningKey for Remote {
+        fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> SignFuture<'a> {
+            let key = self.0.clone();
+            let (algo, input) = (*algo, input.to_vec());
+            Box::pin(crate::async_jwks::unblock(move || key.sign(&algo, &input)))
+        }
+
+        fn default_algorithm(&self) -> Algorithm {
+            Algorithm::ES256
+        }
+
+        fn kid(&self) -> Option<&str> {
+            Some("remote")
+        }
+    }
+
+    let key = || EcKey::from_pem("random_ec_for_testing").unwrap();
+    let remote = Remote(Arc::new(key()));
+    let jwt = Jwt::new_async(serde_json::json!({"sub": "me"}), &remote, None);
+    let token = block_on(jwt.finalize_async()).unwrap();
+    let decoded = Jwt::verify(&token, &key(), &[Algorithm::ES256]).unwrap();
+    assert_eq!(decoded.header().alg(), "ES256");
+    assert_eq!(decoded.header().kid(), Some("remote"));
+    assert_eq!(decoded.claims()["sub"], "me");
+
+    let jwt = Jwt::new_async("claims", Remote(Arc::new(key())), Some(Algorithm::RS256));
+    assert!(block_on(jwt.finalize_async()).is_err());
+}
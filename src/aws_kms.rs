@@ -0,0 +1,402 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use std::env;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+use crate::backend::{digest, hmac, sha256};
+use crate::secret::{wipe_string, Secret};
+use crate::{ec, http, Algorithm, JwtErr, SigningKey};
+
+/// Access key an `AwsKmsKey` signs its requests with
+#[derive(Clone)]
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: &str, secret_access_key: &str) -> Self {
+        AwsCredentials {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: None,
+        }
+    }
+
+    /// Session token of temporary credentials, e.g. from an assumed role
+    pub fn with_session_token(mut self, token: &str) -> Self {
+        self.session_token = Some(token.to_string());
+        self
+    }
+
+    /// Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and the optional `AWS_SESSION_TOKEN`
+    pub fn from_env() -> Result<Self, JwtErr> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                JwtErr::from(
+                    format!(
+                        "Environment variable {} is not set or not valid unicode",
+                        name
+                    )
+                    .as_str(),
+                )
+            })
+        };
+        let credentials = Self::new(&var("AWS_ACCESS_KEY_ID")?, &var("AWS_SECRET_ACCESS_KEY")?);
+        Ok(match env::var("AWS_SESSION_TOKEN") {
+            Ok(token) => credentials.with_session_token(&token),
+            Err(_) => credentials,
+        })
+    }
+}
+
+impl Drop for AwsCredentials {
+    fn drop(&mut self) {
+        wipe_string(&mut self.secret_access_key);
+    }
+}
+
+/// Asymmetric AWS KMS key, every signature is a `kms:Sign` call so the private key never leaves KMS
+///
+/// The input is hashed locally and sent as a digest. `sign` blocks for the duration of the
+/// request, with the `async` feature `sign_async` runs it off the calling task instead. The key
+/// ARN is reported as the `kid`.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, AwsCredentials, AwsKmsKey, Jwt};
+///
+/// let key = AwsKmsKey::new(
+///     "arn:aws:kms:eu-west-1:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab",
+///     Algorithm::ES256,
+///     AwsCredentials::from_env().unwrap(),
+/// )
+/// .unwrap();
+/// let token = Jwt::new("claims", key, None).finalize().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AwsKmsKey {
+    key_id: String,
+    algorithm: Algorithm,
+    region: String,
+    endpoint: String,
+    credentials: AwsCredentials,
+    timeout: Duration,
+}
+
+impl AwsKmsKey {
+    /// `key_arn` is the ARN of the key or an alias, the region is taken from it
+    ///
+    /// `algorithm` has to fit the key spec, `RS*` and `PS*` for RSA keys and the curve's `ES*`
+    /// algorithm for ECC keys.
+    pub fn new(
+        key_arn: &str,
+        algorithm: Algorithm,
+        credentials: AwsCredentials,
+    ) -> Result<Self, JwtErr> {
+        signing_algorithm(&algorithm)?;
+        let parts: Vec<&str> = key_arn.split(':').collect();
+        if parts.len() < 6 || parts[0] != "arn" || parts[2] != "kms" || parts[3].is_empty() {
+            return Err(JwtErr::from(
+                format!("Expected a KMS key ARN, got {}", key_arn).as_str(),
+            ));
+        }
+        let domain = match parts[1] {
+            "aws-cn" => "amazonaws.com.cn",
+            _ => "amazonaws.com",
+        };
+        Ok(AwsKmsKey {
+            key_id: key_arn.to_string(),
+            algorithm,
+            region: parts[3].to_string(),
+            endpoint: format!("https://kms.{}.{}/", parts[3], domain),
+            credentials,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Sends requests to `url` instead of the regional endpoint, e.g. a VPC endpoint
+    pub fn with_endpoint(mut self, url: &str) -> Self {
+        self.endpoint = url.to_string();
+        self
+    }
+
+    /// Connect, read and write timeout for each `kms:Sign` call, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn key_arn(&self) -> &str {
+        &self.key_id
+    }
+
+    fn call_sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let body = serde_json::json!({
+            "KeyId": self.key_id,
+            "Message": STANDARD.encode(digest(algo, input)?),
+            "MessageType": "DIGEST",
+            "SigningAlgorithm": signing_algorithm(algo)?,
+        })
+        .to_string();
+
+        let now = OffsetDateTime::now_utc();
+        let amz_date = format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+        let url = http::Url::parse(&self.endpoint)?;
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", url.authority()),
+            ("x-amz-date", amz_date.clone()),
+            ("x-amz-target", "TrentService.Sign".to_string()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sigv4(
+            &self.credentials,
+            &self.region,
+            "kms",
+            &amz_date,
+            "POST",
+            &url.path,
+            &headers,
+            body.as_bytes(),
+        )?;
+
+        let mut sent: Vec<(&str, &str)> = headers
+            .iter()
+            .filter(|(name, _)| *name != "host")
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        sent.push(("authorization", &authorization));
+        let response = http::request("POST", &self.endpoint, &sent, body.as_bytes(), self.timeout)?;
+        if response.status != 200 {
+            // Gateways in front of KMS may answer with a non JSON error page
+            let json: serde_json::Value =
+                serde_json::from_slice(&response.body).unwrap_or_default();
+            return Err(JwtErr::from(
+                format!(
+                    "kms:Sign failed with status {}: {} {}",
+                    response.status,
+                    json["__type"].as_str().unwrap_or_default(),
+                    json["message"]
+                        .as_str()
+                        .or_else(|| json["Message"].as_str())
+                        .unwrap_or_default()
+                )
+                .as_str(),
+            ));
+        }
+        let json: serde_json::Value = serde_json::from_slice(&response.body)?;
+        let signature = json["Signature"]
+            .as_str()
+            .ok_or_else(|| JwtErr::from("kms:Sign response holds no Signature"))?;
+        Ok(STANDARD.decode(signature)?)
+    }
+}
+
+impl SigningKey for AwsKmsKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let signature = self.call_sign(algo, input)?;
+        match *algo {
+            // KMS returns ECDSA signatures DER encoded
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512 | Algorithm::ES256K => {
+                ec::der_to_jws(&signature, algo)
+            }
+            _ => Ok(signature),
+        }
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.key_id)
+    }
+}
+
+/// Runs `sign` on a helper thread so async tasks are not blocked
+#[cfg(feature = "async")]
+impl crate::AsyncSigningKey for AwsKmsKey {
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> crate::SignFuture<'a> {
+        let key = self.clone();
+        let (algo, input) = (*algo, input.to_vec());
+        Box::pin(crate::async_jwks::unblock(move || key.sign(&algo, &input)))
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        SigningKey::kid(self)
+    }
+}
+
+/// KMS `SigningAlgorithm` implementing `algo`, ES256K is ECDSA_SHA_256 on an ECC_SECG_P256K1 key
+fn signing_algorithm(algo: &Algorithm) -> Result<&'static str, JwtErr> {
+    Ok(match *algo {
+        Algorithm::RS256 => "RSASSA_PKCS1_V1_5_SHA_256",
+        Algorithm::RS384 => "RSASSA_PKCS1_V1_5_SHA_384",
+        Algorithm::RS512 => "RSASSA_PKCS1_V1_5_SHA_512",
+        Algorithm::PS256 => "RSASSA_PSS_SHA_256",
+        Algorithm::PS384 => "RSASSA_PSS_SHA_384",
+        Algorithm::PS512 => "RSASSA_PSS_SHA_512",
+        Algorithm::ES256 | Algorithm::ES256K => "ECDSA_SHA_256",
+        Algorithm::ES384 => "ECDSA_SHA_384",
+        Algorithm::ES512 => "ECDSA_SHA_512",
+        _ => {
+            return Err(JwtErr::from(
+                format!("{} is not supported by AWS KMS", algo).as_str(),
+            ))
+        }
+    })
+}
+
+/// `Authorization` header for an AWS Signature Version 4 request, `headers` have lowercase names
+#[allow(clippy::too_many_arguments)]
+fn sigv4(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<String, JwtErr> {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let mut headers = headers.to_vec();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        query,
+        canonical_headers,
+        signed_headers,
+        hex(&sha256(body)?)
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", &amz_date[..8], region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&sha256(canonical_request.as_bytes())?)
+    );
+    let mac = |key: &[u8], data: &str| hmac(&Algorithm::HS256, key, data.as_bytes());
+    let secret = Secret::from(format!("AWS4{}", credentials.secret_access_key));
+    let key = mac(&secret, &amz_date[..8])?;
+    let key = mac(&key, region)?;
+    let key = mac(&key, service)?;
+    let key = mac(&key, "aws4_request")?;
+    Ok(format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        hex(&mac(&key, &string_to_sign)?)
+    ))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn test_sigv4() {
+    // get-vanilla from the AWS Signature Version 4 test suite
+    let credentials =
+        AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+    let authorization = sigv4(
+        &credentials,
+        "us-east-1",
+        "service",
+        "20150830T123600Z",
+        "GET",
+        "/",
+        &[
+            ("host", "example.amazonaws.com".to_string()),
+            ("x-amz-date", "20150830T123600Z".to_string()),
+        ],
+        b"",
+    )
+    .unwrap();
+    assert_eq!(
+        authorization,
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+         SignedHeaders=host;x-amz-date, \
+         Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+    );
+}
+
+#[test]
+fn test_aws_kms_key() {
+    use crate::{EcKey, VerifyingKey};
+    use openssl::sign::Signer;
+    use std::sync::{Arc, Mutex};
+
+    let arn = "arn:aws:kms:eu-west-1:111122223333:key/1234abcd";
+    let credentials = || AwsCredentials::new("AKIDEXAMPLE", "secret").with_session_token("token");
+    assert!(AwsKmsKey::new("1234abcd", Algorithm::ES256, credentials()).is_err());
+    assert!(AwsKmsKey::new(arn, Algorithm::EdDSA, credentials()).is_err());
+    assert!(AwsKmsKey::new(arn, Algorithm::HS256, credentials()).is_err());
+    let key = AwsKmsKey::new(arn, Algorithm::ES256, credentials()).unwrap();
+    assert_eq!(key.endpoint, "https://kms.eu-west-1.amazonaws.com/");
+    assert_eq!(key.kid(), Some(arn));
+    let cn = "arn:aws-cn:kms:cn-north-1:111122223333:alias/signing";
+    let key = AwsKmsKey::new(cn, Algorithm::PS256, credentials()).unwrap();
+    assert_eq!(key.endpoint, "https://kms.cn-north-1.amazonaws.com.cn/");
+    assert_eq!(key.default_algorithm(), Algorithm::PS256);
+
+    // The stand in for KMS answers with a DER signature made by a local key
+    let local = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let pkey = crate::read_keyfile("random_ec_for_testing").unwrap();
+    let mut signer = Signer::new(openssl::hash::MessageDigest::sha256(), &pkey).unwrap();
+    signer.update(b"input").unwrap();
+    let der = signer.sign_to_vec().unwrap();
+    let body = Arc::new(Mutex::new(
+        serde_json::json!({"KeyId": arn, "Signature": STANDARD.encode(der)}).to_string(),
+    ));
+    let (url, hits) = http::serve(body.clone());
+    let key = AwsKmsKey::new(arn, Algorithm::ES256, credentials())
+        .unwrap()
+        .with_endpoint(&url);
+    let signature = key.sign(&Algorithm::ES256, b"input").unwrap();
+    assert_eq!(signature.len(), 64);
+    assert!(local
+        .verify(&Algorithm::ES256, b"input", &signature)
+        .unwrap());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    *body.lock().unwrap() = r#"{"KeyId": "x"}"#.to_string();
+    assert!(key.sign(&Algorithm::ES256, b"input").is_err());
+
+    let page = Arc::new(Mutex::new("<html>Bad Gateway</html>".to_string()));
+    let (url, _) = http::serve_status("502 Bad Gateway", page);
+    let key = key.with_endpoint(&url);
+    let err = key.sign(&Algorithm::ES256, b"input").unwrap_err();
+    assert!(err.to_string().contains("status 502"), "{}", err);
+}
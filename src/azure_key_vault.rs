@@ -0,0 +1,212 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::digest;
+use crate::{http, Algorithm, JwtErr, SigningKey, URL_SAFE_LENIENT};
+
+type TokenSource = Arc<dyn Fn() -> Result<String, JwtErr> + Send + Sync>;
+
+/// Azure Key Vault or Managed HSM key, every signature is a call to the vault's `sign` operation
+///
+/// The input is hashed locally and sent as a digest. `access_token` is asked for an Entra ID
+/// token for `https://vault.azure.net` before each call. Key Vault signs in the JWS format
+/// already, and the key identifier is reported as the `kid`.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, AzureKeyVaultKey, Jwt};
+///
+/// let key = AzureKeyVaultKey::new(
+///     "https://contoso.vault.azure.net/keys/signing/4f8d2a6b1c3e4f5a9b7c8d6e5f4a3b2c",
+///     Algorithm::PS256,
+///     || Ok(std::env::var("ACCESS_TOKEN").unwrap()),
+/// )
+/// .unwrap();
+/// let token = Jwt::new("claims", key, None).finalize().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AzureKeyVaultKey {
+    key_id: String,
+    algorithm: Algorithm,
+    access_token: TokenSource,
+    timeout: Duration,
+}
+
+impl AzureKeyVaultKey {
+    /// `key_id` is the key identifier URL, `https://{vault}.vault.azure.net/keys/{name}/{version}`,
+    /// pinning the version keeps the `kid` stable when the key is rotated
+    pub fn new<F>(key_id: &str, algorithm: Algorithm, access_token: F) -> Result<Self, JwtErr>
+    where
+        F: Fn() -> Result<String, JwtErr> + Send + Sync + 'static,
+    {
+        check_algorithm(&algorithm)?;
+        let url = http::Url::parse(key_id)?;
+        if !url.path.contains("/keys/") || url.path.contains('?') {
+            return Err(JwtErr::from(
+                format!("Expected a Key Vault key identifier, got {}", key_id).as_str(),
+            ));
+        }
+        Ok(AzureKeyVaultKey {
+            key_id: key_id.trim_end_matches('/').to_string(),
+            algorithm,
+            access_token: Arc::new(access_token),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Connect, read and write timeout for each `sign` call, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
+impl SigningKey for AzureKeyVaultKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        check_algorithm(algo)?;
+        let body = serde_json::json!({
+            "alg": algo.to_string(),
+            "value": URL_SAFE_NO_PAD.encode(digest(algo, input)?),
+        })
+        .to_string();
+        let authorization = format!("Bearer {}", (self.access_token)()?);
+        let response = http::request(
+            "POST",
+            &format!("{}/sign?api-version=7.4", self.key_id),
+            &[
+                ("Authorization", &authorization),
+                ("Content-Type", "application/json"),
+            ],
+            body.as_bytes(),
+            self.timeout,
+        )?;
+        if response.status != 200 {
+            let json: serde_json::Value =
+                serde_json::from_slice(&response.body).unwrap_or_default();
+            return Err(JwtErr::from(
+                format!(
+                    "Key Vault sign failed with status {}: {}",
+                    response.status,
+                    json["error"]["message"].as_str().unwrap_or_default()
+                )
+                .as_str(),
+            ));
+        }
+        let json: serde_json::Value = serde_json::from_slice(&response.body)?;
+        let signature = json["value"]
+            .as_str()
+            .ok_or_else(|| JwtErr::from("Key Vault sign response holds no value"))?;
+        Ok(URL_SAFE_LENIENT.decode(signature)?)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.key_id)
+    }
+}
+
+/// Runs `sign` on a helper thread so async tasks are not blocked
+#[cfg(feature = "async")]
+impl crate::AsyncSigningKey for AzureKeyVaultKey {
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> crate::SignFuture<'a> {
+        let key = self.clone();
+        let (algo, input) = (*algo, input.to_vec());
+        Box::pin(crate::async_jwks::unblock(move || key.sign(&algo, &input)))
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        SigningKey::kid(self)
+    }
+}
+
+/// Key Vault takes the JWS names of the RSA and ECDSA algorithms as they are
+fn check_algorithm(algo: &Algorithm) -> Result<(), JwtErr> {
+    match *algo {
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512
+        | Algorithm::ES256
+        | Algorithm::ES384
+        | Algorithm::ES512
+        | Algorithm::ES256K => Ok(()),
+        _ => Err(JwtErr::from(
+            format!("{} is not supported by Key Vault", algo).as_str(),
+        )),
+    }
+}
+
+#[test]
+fn test_azure_key_vault_key() {
+    use crate::{EcKey, VerifyingKey};
+    use std::sync::Mutex;
+
+    let token = || Ok("token".to_string());
+    let key_id = "https://contoso.vault.azure.net/keys/signing/0123";
+    assert!(AzureKeyVaultKey::new(key_id, Algorithm::EdDSA, token).is_err());
+    assert!(AzureKeyVaultKey::new(key_id, Algorithm::HS256, token).is_err());
+    assert!(AzureKeyVaultKey::new(
+        "https://contoso.vault.azure.net/secrets/signing",
+        Algorithm::RS256,
+        token
+    )
+    .is_err());
+    assert!(AzureKeyVaultKey::new(
+        "http://contoso.vault.azure.net/keys/x",
+        Algorithm::RS256,
+        token
+    )
+    .is_err());
+    let key = AzureKeyVaultKey::new(key_id, Algorithm::RS256, token).unwrap();
+    assert_eq!(key.kid(), Some(key_id));
+
+    // The stand in for Key Vault answers with a JWS signature made by a local key
+    let local = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let signature = local.sign(&Algorithm::ES256, b"input").unwrap();
+    let body = Arc::new(Mutex::new(
+        serde_json::json!({"kid": key_id, "value": URL_SAFE_NO_PAD.encode(&signature)}).to_string(),
+    ));
+    let (url, hits) = http::serve(body.clone());
+    let vault = url.trim_end_matches("/jwks");
+    let key = AzureKeyVaultKey::new(
+        &format!("{}/keys/signing/0123", vault),
+        Algorithm::ES256,
+        token,
+    )
+    .unwrap();
+    let signed = key.sign(&Algorithm::ES256, b"input").unwrap();
+    assert_eq!(signed, signature);
+    assert!(local.verify(&Algorithm::ES256, b"input", &signed).unwrap());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    *body.lock().unwrap() = r#"{"kid": "x"}"#.to_string();
+    assert!(key.sign(&Algorithm::ES256, b"input").is_err());
+
+    let page = Arc::new(Mutex::new("<html>Bad Gateway</html>".to_string()));
+    let (url, _) = http::serve_status("502 Bad Gateway", page);
+    let vault = url.trim_end_matches("/jwks");
+    let key = AzureKeyVaultKey::new(
+        &format!("{}/keys/signing/0123", vault),
+        Algorithm::ES256,
+        token,
+    )
+    .unwrap();
+    let err = key.sign(&Algorithm::ES256, b"input").unwrap_err();
+    assert!(err.to_string().contains("status 502"), "{}", err);
+}
@@ -0,0 +1,282 @@
+#[cfg(feature = "openssl")]
+use openssl::{
+    aes,
+    hash::{hash, Hasher, MessageDigest},
+    memcmp,
+    pkey::PKey,
+    sign::Signer,
+    symm::{self, Cipher},
+};
+
+#[cfg(feature = "openssl")]
+use std::io;
+
+#[cfg(feature = "openssl")]
+use crate::secret::wipe;
+#[cfg(feature = "openssl")]
+use crate::Algorithm;
+use crate::JwtErr;
+
+// Internal routing of the primitives the token formats need whatever the key: digests, HMAC,
+// AES-GCM, AES key wrap, random bytes and constant time comparison. This is not a pluggable
+// backend. Other crypto libraries plug in as `SigningKey` and `VerifyingKey` implementations,
+// which `Jwt` signs and verifies through. The built in key types sign with their OpenSSL key
+// objects themselves, the remaining OpenSSL calls are kept here rather than spread over the crate.
+//
+// Only random ids are needed without the `openssl` feature, and fail for lack of a generator.
+
+/// Digest an algorithm hashes its input with, `null` for `EdDSA` which hashes internally
+#[cfg(feature = "openssl")]
+pub(crate) fn message_digest(algo: &Algorithm) -> MessageDigest {
+    match *algo {
+        Algorithm::HS256
+        | Algorithm::RS256
+        | Algorithm::ES256
+        | Algorithm::ES256K
+        | Algorithm::PS256 => MessageDigest::sha256(),
+        Algorithm::HS384 | Algorithm::RS384 | Algorithm::ES384 | Algorithm::PS384 => {
+            MessageDigest::sha384()
+        }
+        Algorithm::HS512 | Algorithm::RS512 | Algorithm::ES512 | Algorithm::PS512 => {
+            MessageDigest::sha512()
+        }
+        Algorithm::EdDSA | Algorithm::None => MessageDigest::null(),
+    }
+}
+
+/// Output length of the algorithm's digest in bytes, 0 for `EdDSA` and `none`
+#[cfg(feature = "openssl")]
+pub(crate) fn digest_len(algo: &Algorithm) -> usize {
+    message_digest(algo).size()
+}
+
+#[cfg(feature = "openssl")]
+pub(crate) fn sha256(data: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    Ok(hash(MessageDigest::sha256(), data)?.to_vec())
+}
+
+/// Hash of `input` with the digest of `algo`, what remote signers taking a digest expect
+#[cfg(any(feature = "kms-aws", feature = "kms-azure", feature = "kms-gcp"))]
+pub(crate) fn digest(algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    Ok(hash(message_digest(algo), input)?.to_vec())
+}
+
+/// HMAC of `input` with the digest of an `HS*` algorithm
+#[cfg(feature = "openssl")]
+pub(crate) fn hmac(algo: &Algorithm, secret: &[u8], input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    let pkey = PKey::hmac(secret)?;
+    let mut signer = Signer::new(message_digest(algo), &pkey)?;
+    signer.update(input)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+/// HMAC over input written in pieces, RFC 2104 on top of two running digests
+#[cfg(feature = "openssl")]
+#[derive(Clone)]
+pub(crate) struct HmacWriter {
+    inner: Hasher,
+    outer: Hasher,
+}
+
+#[cfg(feature = "openssl")]
+impl HmacWriter {
+    pub(crate) fn new(algo: &Algorithm, secret: &[u8]) -> Result<Self, JwtErr> {
+        let digest = message_digest(algo);
+        let mut block = if secret.len() > digest.block_size() {
+            hash(digest, secret)?.to_vec()
+        } else {
+            secret.to_vec()
+        };
+        block.resize(digest.block_size(), 0);
+        let (mut inner, mut outer) = (Hasher::new(digest)?, Hasher::new(digest)?);
+        for byte in block.iter_mut() {
+            *byte ^= 0x36;
+        }
+        inner.update(&block)?;
+        for byte in block.iter_mut() {
+            *byte ^= 0x36 ^ 0x5c;
+        }
+        outer.update(&block)?;
+        // The block is the secret in disguise, wiped like the one `HmacKey` holds
+        wipe(&mut block);
+        Ok(HmacWriter { inner, outer })
+    }
+
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>, JwtErr> {
+        let inner = self.inner.finish()?;
+        self.outer.update(&inner)?;
+        Ok(self.outer.finish()?.to_vec())
+    }
+
+    /// MAC of `input` alone, leaving this writer as it was for the next one
+    pub(crate) fn mac(&self, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let mut writer = self.clone();
+        writer.inner.update(input)?;
+        writer.finish()
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl io::Write for HmacWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compares in time depending only on the lengths, which are public for MACs
+///
+/// `CRYPTO_memcmp` looks at every byte whatever the first mismatch. Signatures and MACs are never
+/// compared with `==` on slices, which returns at the first byte that differs.
+#[cfg(feature = "openssl")]
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && memcmp::eq(a, b)
+}
+
+/// AES-GCM encryption with a 128 bit tag, the key length picks AES-128, AES-192 or AES-256
+#[cfg(feature = "openssl")]
+pub(crate) fn aes_gcm_encrypt(
+    key: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+    let mut tag = vec![0; 16];
+    let ciphertext = symm::encrypt_aead(gcm_cipher(key)?, key, Some(iv), aad, plaintext, &mut tag)?;
+    Ok((ciphertext, tag))
+}
+
+/// Fails alike for a wrong key, a tampered ciphertext and a tampered tag
+#[cfg(feature = "openssl")]
+pub(crate) fn aes_gcm_decrypt(
+    key: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>, JwtErr> {
+    if tag.len() != 16 {
+        return Err(JwtErr::from("Decryption failed"));
+    }
+    symm::decrypt_aead(gcm_cipher(key)?, key, Some(iv), aad, ciphertext, tag)
+        .map_err(|_| JwtErr::from("Decryption failed"))
+}
+
+#[cfg(feature = "openssl")]
+fn gcm_cipher(key: &[u8]) -> Result<Cipher, JwtErr> {
+    match key.len() {
+        16 => Ok(Cipher::aes_128_gcm()),
+        24 => Ok(Cipher::aes_192_gcm()),
+        32 => Ok(Cipher::aes_256_gcm()),
+        _ => Err(JwtErr::from("AES keys must be 128, 192 or 256 bits long")),
+    }
+}
+
+/// RFC 3394 AES key wrap of `key` under `kek`, the key length of `kek` picks the AES variant
+#[cfg(feature = "openssl")]
+pub(crate) fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    if key.len() < 16 || !key.len().is_multiple_of(8) {
+        return Err(JwtErr::from("Wrapped keys must be a multiple of 64 bits"));
+    }
+    let kek = aes::AesKey::new_encrypt(kek)
+        .map_err(|_| JwtErr::from("AES keys must be 128, 192 or 256 bits long"))?;
+    let mut wrapped = vec![0; key.len() + 8];
+    aes::wrap_key(&kek, None, &mut wrapped, key).map_err(|_| JwtErr::from("Key wrap failed"))?;
+    Ok(wrapped)
+}
+
+/// Fails alike for a wrong key encryption key and a tampered wrapped key
+#[cfg(feature = "openssl")]
+pub(crate) fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    if wrapped.len() < 24 || !wrapped.len().is_multiple_of(8) {
+        return Err(JwtErr::from("Decryption failed"));
+    }
+    let kek = aes::AesKey::new_decrypt(kek).map_err(|_| JwtErr::from("Decryption failed"))?;
+    let mut key = vec![0; wrapped.len() - 8];
+    aes::unwrap_key(&kek, None, &mut key, wrapped)
+        .map_err(|_| JwtErr::from("Decryption failed"))?;
+    Ok(key)
+}
+
+/// Fills `buf` from a cryptographically secure random generator
+#[cfg(feature = "openssl")]
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<(), JwtErr> {
+    Ok(openssl::rand::rand_bytes(buf)?)
+}
+
+#[cfg(not(feature = "openssl"))]
+pub(crate) fn random_bytes(_buf: &mut [u8]) -> Result<(), JwtErr> {
+    Err(JwtErr::from(
+        "Generating random ids requires the openssl feature",
+    ))
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_backend_primitives() {
+    assert_eq!(digest_len(&Algorithm::HS256), 32);
+    assert_eq!(digest_len(&Algorithm::PS384), 48);
+    assert_eq!(digest_len(&Algorithm::ES512), 64);
+    assert_eq!(digest_len(&Algorithm::EdDSA), 0);
+
+    // RFC 4231 test case 2
+    let mac = hmac(&Algorithm::HS256, b"Jefe", b"what do ya want for nothing?").unwrap();
+    assert_eq!(
+        mac.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert!(constant_time_eq(&mac, &mac.clone()));
+    for (algo, secret) in [
+        (Algorithm::HS256, &b"Jefe"[..]),
+        (Algorithm::HS384, &[0xaa; 131][..]),
+        (Algorithm::HS512, &[0x0b; 128][..]),
+    ] {
+        let input = b"what do ya want for nothing?";
+        let mut writer = HmacWriter::new(&algo, secret).unwrap();
+        for piece in input.chunks(5) {
+            io::Write::write_all(&mut writer, piece).unwrap();
+        }
+        assert_eq!(
+            writer.finish().unwrap(),
+            hmac(&algo, secret, input).unwrap()
+        );
+    }
+    assert!(!constant_time_eq(&mac, &mac[1..]));
+
+    let empty = sha256(b"").unwrap();
+    assert_eq!(empty[..4], [0xe3, 0xb0, 0xc4, 0x42]);
+
+    let (mut a, mut b) = ([0; 16], [0; 16]);
+    random_bytes(&mut a).unwrap();
+    random_bytes(&mut b).unwrap();
+    assert_ne!(a, b);
+
+    let (ciphertext, tag) = aes_gcm_encrypt(&a, &b[..12], b"aad", b"plaintext").unwrap();
+    assert_eq!(ciphertext.len(), 9);
+    assert_eq!(
+        aes_gcm_decrypt(&a, &b[..12], b"aad", &ciphertext, &tag).unwrap(),
+        b"plaintext"
+    );
+    assert!(aes_gcm_decrypt(&a, &b[..12], b"other", &ciphertext, &tag).is_err());
+    assert!(aes_gcm_decrypt(&a, &b[..12], b"aad", &ciphertext, &tag[..8]).is_err());
+    assert!(aes_gcm_encrypt(&a[..15], &b[..12], b"aad", b"plaintext").is_err());
+
+    // RFC 3394 4.1, 128 bits of key data with a 128 bit KEK
+    let kek: Vec<u8> = (0..16).collect();
+    let key: Vec<u8> = (0..16).map(|i| i * 0x11).collect();
+    let wrapped = aes_key_wrap(&kek, &key).unwrap();
+    assert_eq!(
+        wrapped
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>(),
+        "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"
+    );
+    assert_eq!(aes_key_unwrap(&kek, &wrapped).unwrap(), key);
+    assert!(aes_key_unwrap(&a, &wrapped).is_err());
+    assert!(aes_key_unwrap(&kek, &wrapped[..20]).is_err());
+    assert!(aes_key_wrap(&kek, &key[..12]).is_err());
+}
@@ -0,0 +1,217 @@
+use core::time::Duration;
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::{Algorithm, Audience, ClaimsBuilder, Clock, Jwt, JwtErr, SigningKey};
+
+/// Fluent construction of a signed token, from claims to compact string in one expression
+///
+/// The registered claims are stamped as by a `ClaimsBuilder` and flattened next to `claims`,
+/// which can not repeat any of them that is set, `iat` always is.
+/// Started by `Jwt::builder`, build a `Jwt` directly for headers other than `kid` and `typ`.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, EcKey, Jwt};
+/// use core::time::Duration;
+///
+/// let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+/// let token = Jwt::builder()
+///     .claims(serde_json::json!({"scope": "read"}))
+///     .subject("me")
+///     .kid("v2")
+///     .algorithm(Algorithm::ES256)
+///     .expires_in(Duration::from_secs(3600))
+///     .sign(&key)
+///     .unwrap();
+///
+/// let decoded = Jwt::verify(&token, &key, &[Algorithm::ES256]).unwrap();
+/// assert_eq!(decoded.header().kid(), Some("v2"));
+/// assert_eq!(decoded.claims()["scope"], "read");
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct JwtBuilder<T = serde_json::Value> {
+    claims: T,
+    registered: ClaimsBuilder,
+    algorithm: Option<Algorithm>,
+    kid: Option<String>,
+    typ: Option<String>,
+}
+
+impl Jwt<serde_json::Value> {
+    /// Starts a `JwtBuilder` with no claims of the application's own
+    pub fn builder() -> JwtBuilder {
+        JwtBuilder {
+            claims: serde_json::json!({}),
+            registered: ClaimsBuilder::new(),
+            algorithm: None,
+            kid: None,
+            typ: None,
+        }
+    }
+}
+
+impl<T: Serialize> JwtBuilder<T> {
+    /// The application's claims, a type serializing to a JSON object
+    pub fn claims<C: Serialize>(self, claims: C) -> JwtBuilder<C> {
+        JwtBuilder {
+            claims,
+            registered: self.registered,
+            algorithm: self.algorithm,
+            kid: self.kid,
+            typ: self.typ,
+        }
+    }
+
+    /// Signs with `algorithm` instead of the key's default
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Sets the `kid` header, overriding the one reported by the key
+    pub fn kid(mut self, kid: &str) -> Self {
+        self.kid = Some(kid.to_string());
+        self
+    }
+
+    /// Replaces the default `typ: "JWT"`
+    pub fn typ(mut self, typ: &str) -> Self {
+        self.typ = Some(typ.to_string());
+        self
+    }
+
+    pub fn issuer(mut self, iss: &str) -> Self {
+        self.registered = self.registered.issuer(iss);
+        self
+    }
+
+    pub fn subject(mut self, sub: &str) -> Self {
+        self.registered = self.registered.subject(sub);
+        self
+    }
+
+    pub fn audience<A: Into<Audience>>(mut self, aud: A) -> Self {
+        self.registered = self.registered.audience(aud);
+        self
+    }
+
+    /// Sets `exp` to `ttl` after `iat`
+    pub fn expires_in(mut self, ttl: Duration) -> Self {
+        self.registered = self.registered.expires_in(ttl);
+        self
+    }
+
+    /// Sets `nbf` to `delay` after `iat`
+    pub fn not_before(mut self, delay: Duration) -> Self {
+        self.registered = self.registered.not_before(delay);
+        self
+    }
+
+    /// Sets `jti` to 128 random bits
+    pub fn generate_jti(mut self) -> Self {
+        self.registered = self.registered.generate_jti();
+        self
+    }
+
+    /// Takes `iat` from `clock` instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.registered = self.registered.with_clock(clock);
+        self
+    }
+
+    /// Stamps the claims and signs them with `key`, returning the compact token
+    pub fn sign<K: SigningKey>(self, key: K) -> Result<String, JwtErr> {
+        let claims = self.registered.build(self.claims)?;
+        let mut jwt = Jwt::new(claims, key, self.algorithm);
+        if let Some(kid) = &self.kid {
+            jwt = jwt.with_kid(kid);
+        }
+        if let Some(typ) = &self.typ {
+            jwt = jwt.with_typ(typ);
+        }
+        jwt.finalize()
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_jwt_builder() {
+    use crate::{FixedClock, HmacKey, RSAKey};
+
+    #[derive(Serialize)]
+    struct Custom {
+        role: &'static str,
+    }
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::builder()
+        .claims(Custom { role: "admin" })
+        .issuer("auth.example.com")
+        .audience("api")
+        .typ("at+jwt")
+        .with_clock(FixedClock::from_unix(4_000_000_000))
+        .expires_in(Duration::from_secs(60))
+        .not_before(Duration::ZERO)
+        .generate_jti()
+        .sign(key())
+        .unwrap();
+    let (header, claims) = Jwt::decode_unverified(&token).unwrap();
+    assert_eq!(header.alg(), "HS256");
+    assert_eq!(header.typ(), Some("at+jwt"));
+    assert_eq!(header.kid(), None);
+    assert_eq!(claims["role"], "admin");
+    assert_eq!(claims["iss"], "auth.example.com");
+    assert_eq!(claims["aud"], "api");
+    assert_eq!(claims["iat"], 4_000_000_000u64);
+    assert_eq!(claims["exp"], 4_000_000_060u64);
+    assert_eq!(claims["nbf"], 4_000_000_000u64);
+    assert!(claims["jti"].is_string());
+
+    // Without claims of its own only the registered ones are sent
+    let token = Jwt::builder().subject("me").sign(key()).unwrap();
+    let decoded = Jwt::verify(&token, &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded.claims()["sub"], "me");
+    assert!(decoded.claims()["exp"].is_null());
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let token = Jwt::builder()
+        .algorithm(Algorithm::PS384)
+        .kid("rsa-2")
+        .sign(&rsa)
+        .unwrap();
+    let decoded = Jwt::verify(&token, &rsa, &[Algorithm::PS384]).unwrap();
+    assert_eq!(decoded.header().alg(), "PS384");
+    assert_eq!(decoded.header().kid(), Some("rsa-2"));
+
+    assert!(Jwt::builder()
+        .algorithm(Algorithm::ES256)
+        .sign(key())
+        .is_err());
+    assert!(Jwt::builder()
+        .claims(vec!["not", "an", "object"])
+        .sign(key())
+        .is_err());
+
+    // A custom claim repeating a registered one is not sent twice
+    assert!(Jwt::builder()
+        .claims(serde_json::json!({"sub": "x"}))
+        .subject("me")
+        .sign(key())
+        .is_err());
+    assert!(Jwt::builder()
+        .claims(serde_json::json!({"iat": 1}))
+        .sign(key())
+        .is_err());
+    let token = Jwt::builder()
+        .claims(serde_json::json!({"sub": "x"}))
+        .sign(key())
+        .unwrap();
+    assert_eq!(Jwt::decode_unverified(&token).unwrap().1["sub"], "x");
+}
@@ -0,0 +1,446 @@
+use alloc::sync::Arc;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use core::convert::TryFrom;
+use core::time::Duration;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::clock::{default_clock, no_clock, Clock};
+use crate::prelude::*;
+use crate::JwtErr;
+
+/// The registered claims of RFC 7519 4.1, meant to be flattened into an application's claims
+///
+/// Absent claims are skipped when serializing. Dates are NumericDates, seconds since the epoch,
+/// fractional values sent by other issuers are truncated.
+///
+/// ### Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use smpl_jwt::RegisteredClaims;
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Claims {
+///   #[serde(flatten)]
+///   registered: RegisteredClaims,
+///   department: String,
+/// }
+///
+/// fn main() {
+///   let claims = Claims {
+///     registered: RegisteredClaims {
+///       sub: Some("me".to_string()),
+///       exp: Some(1_700_000_000),
+///       ..Default::default()
+///     },
+///     department: "accounting".to_string(),
+///   };
+///   assert_eq!(
+///     serde_json::to_string(&claims).unwrap(),
+///     r#"{"sub":"me","exp":1700000000,"department":"accounting"}"#
+///   );
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisteredClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Audience>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "numeric_date"
+    )]
+    pub exp: Option<i64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "numeric_date"
+    )]
+    pub nbf: Option<i64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "numeric_date"
+    )]
+    pub iat: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+/// The `aud` claim, which RFC 7519 allows to be a single string or an array of them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        let audiences = match self {
+            Audience::Single(aud) => core::slice::from_ref(aud),
+            Audience::Multiple(auds) => auds.as_slice(),
+        };
+        audiences.iter().map(String::as_str)
+    }
+
+    pub fn contains(&self, aud: &str) -> bool {
+        self.iter().any(|candidate| candidate == aud)
+    }
+}
+
+impl From<&str> for Audience {
+    fn from(aud: &str) -> Self {
+        Audience::Single(aud.to_string())
+    }
+}
+
+impl From<Vec<String>> for Audience {
+    fn from(auds: Vec<String>) -> Self {
+        Audience::Multiple(auds)
+    }
+}
+
+/// Registered claims next to the application's own, both flattened into one JSON object
+///
+/// A custom claim named like a registered claim that is set fails to serialize, the object would
+/// hold the name twice and parsers disagree on which value wins.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Claims<T> {
+    #[serde(flatten)]
+    pub registered: RegisteredClaims,
+    #[serde(flatten)]
+    pub custom: T,
+}
+
+impl<T: Serialize> Serialize for Claims<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Flattened<'a, T> {
+            #[serde(flatten)]
+            registered: &'a RegisteredClaims,
+            #[serde(flatten)]
+            custom: &'a T,
+        }
+
+        let registered = serde_json::to_value(&self.registered).map_err(S::Error::custom)?;
+        let custom = serde_json::to_value(&self.custom).map_err(S::Error::custom)?;
+        if let (Some(registered), Some(custom)) = (registered.as_object(), custom.as_object()) {
+            if let Some(name) = custom.keys().find(|name| registered.contains_key(*name)) {
+                return Err(S::Error::custom(format!(
+                    "Claim {} is set both as a registered and a custom claim",
+                    name
+                )));
+            }
+        }
+        Flattened {
+            registered: &self.registered,
+            custom: &self.custom,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Builds `Claims` with `iat` and `exp` stamped from the current time, see `Clock`
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{ClaimsBuilder, HmacKey, Jwt};
+/// use core::time::Duration;
+///
+/// let claims = ClaimsBuilder::new()
+///     .issuer("auth.example.com")
+///     .expires_in(Duration::from_secs(3600))
+///     .generate_jti()
+///     .build(serde_json::json!({"department": "accounting"}))
+///     .unwrap();
+/// let jti = claims.registered.jti.clone().unwrap();
+///
+/// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let token = Jwt::new(claims, key, None).finalize().unwrap();
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClaimsBuilder {
+    registered: RegisteredClaims,
+    expires_in: Option<Duration>,
+    not_before: Option<Duration>,
+    generate_jti: bool,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl Default for ClaimsBuilder {
+    fn default() -> Self {
+        ClaimsBuilder {
+            registered: RegisteredClaims::default(),
+            expires_in: None,
+            not_before: None,
+            generate_jti: false,
+            clock: default_clock(),
+        }
+    }
+}
+
+impl ClaimsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes `iat` from `clock` instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    pub fn issuer(mut self, iss: &str) -> Self {
+        self.registered.iss = Some(iss.to_string());
+        self
+    }
+
+    pub fn subject(mut self, sub: &str) -> Self {
+        self.registered.sub = Some(sub.to_string());
+        self
+    }
+
+    pub fn audience<A: Into<Audience>>(mut self, aud: A) -> Self {
+        self.registered.aud = Some(aud.into());
+        self
+    }
+
+    /// Sets `exp` to `ttl` after `iat`
+    pub fn expires_in(mut self, ttl: Duration) -> Self {
+        self.expires_in = Some(ttl);
+        self
+    }
+
+    /// Sets `nbf` to `delay` after `iat`, `Duration::ZERO` for tokens valid right away
+    pub fn not_before(mut self, delay: Duration) -> Self {
+        self.not_before = Some(delay);
+        self
+    }
+
+    /// Sets `jti` to 128 random bits, base64url encoded
+    pub fn generate_jti(mut self) -> Self {
+        self.generate_jti = true;
+        self
+    }
+
+    /// Sets `jti` to the given id instead of generating one
+    pub fn jti(mut self, jti: &str) -> Self {
+        self.registered.jti = Some(jti.to_string());
+        self.generate_jti = false;
+        self
+    }
+
+    /// Stamps the time based claims and merges them with `custom`
+    pub fn build<T>(self, custom: T) -> Result<Claims<T>, JwtErr> {
+        Ok(Claims {
+            registered: self.build_registered()?,
+            custom,
+        })
+    }
+
+    /// Stamps the time based claims, for claim types flattening `RegisteredClaims` themselves
+    pub fn build_registered(self) -> Result<RegisteredClaims, JwtErr> {
+        let mut registered = self.registered;
+        let now = self.clock.as_ref().ok_or_else(no_clock)?.unix_now();
+        registered.iat = Some(now);
+        registered.exp = self.expires_in.map(|ttl| offset(now, ttl));
+        registered.nbf = self.not_before.map(|delay| offset(now, delay));
+        if self.generate_jti {
+            registered.jti = Some(random_id()?);
+        }
+        Ok(registered)
+    }
+}
+
+fn offset(now: i64, by: Duration) -> i64 {
+    now.saturating_add(i64::try_from(by.as_secs()).unwrap_or(i64::MAX))
+}
+
+/// 128 random bits from a CSPRNG, base64url encoded
+pub(crate) fn random_id() -> Result<String, JwtErr> {
+    let mut bytes = [0; 16];
+    crate::backend::random_bytes(&mut bytes)?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+// Integers are taken as they are, `f64` would round those past 2^53. Only fractional dates, and
+// integers too large for an `i64`, are truncated and saturated from their `f64` value.
+fn numeric_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    let value = Option::<serde_json::Number>::deserialize(deserializer)?;
+    Ok(value.map(|date| match date.as_i64() {
+        Some(date) => date,
+        None => date.as_f64().unwrap_or_default() as i64,
+    }))
+}
+
+#[test]
+fn test_registered_claims_serde() {
+    let claims = RegisteredClaims {
+        iss: Some("issuer".to_string()),
+        aud: Some("api".into()),
+        iat: Some(1_600_000_000),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_string(&claims).unwrap(),
+        r#"{"iss":"issuer","aud":"api","iat":1600000000}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<RegisteredClaims>("{}").unwrap(),
+        Default::default()
+    );
+
+    let claims: RegisteredClaims = serde_json::from_str(
+        r#"{"aud":["a","b"],"exp":1600000000.75,"nbf":null,"jti":"id","extra":true}"#,
+    )
+    .unwrap();
+    assert_eq!(claims.exp, Some(1_600_000_000));
+    assert_eq!(claims.nbf, None);
+    assert_eq!(claims.jti.as_deref(), Some("id"));
+    let aud = claims.aud.unwrap();
+    assert!(aud.contains("b"));
+    assert!(!aud.contains("c"));
+    assert_eq!(aud.iter().collect::<Vec<_>>(), vec!["a", "b"]);
+
+    let date = |json: &str| serde_json::from_str::<RegisteredClaims>(json).unwrap().exp;
+    assert_eq!(
+        date(r#"{"exp":9007199254740993}"#),
+        Some(9_007_199_254_740_993)
+    );
+    assert_eq!(date(r#"{"exp":-1.5}"#), Some(-1));
+    assert_eq!(date(r#"{"exp":18446744073709551615}"#), Some(i64::MAX));
+
+    assert!(serde_json::from_str::<RegisteredClaims>(r#"{"exp":"tomorrow"}"#).is_err());
+    assert!(serde_json::from_str::<RegisteredClaims>(r#"{"aud":1}"#).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_registered_claims_flattened() {
+    use crate::{Algorithm, HmacKey, Jwt};
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        #[serde(flatten)]
+        registered: RegisteredClaims,
+        department: String,
+    }
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let claims = Claims {
+        registered: RegisteredClaims {
+            sub: Some("me".to_string()),
+            exp: Some(i64::MAX / 2),
+            ..Default::default()
+        },
+        department: "accounting".to_string(),
+    };
+    let token = Jwt::new(claims, key(), None).finalize().unwrap();
+    let claims: Claims = Jwt::verify_into(&token, &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(claims.registered.sub.as_deref(), Some("me"));
+    assert_eq!(claims.registered.exp, Some(i64::MAX / 2));
+    assert_eq!(claims.department, "accounting");
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_claims_builder() {
+    use crate::{Algorithm, FixedClock, HmacKey, Jwt};
+    use time::OffsetDateTime;
+
+    let before = OffsetDateTime::now_utc().unix_timestamp();
+    let claims = ClaimsBuilder::new()
+        .issuer("issuer")
+        .subject("me")
+        .audience(vec!["a".to_string(), "b".to_string()])
+        .expires_in(Duration::from_secs(3600))
+        .not_before(Duration::ZERO)
+        .generate_jti()
+        .build(serde_json::json!({"department": "accounting"}))
+        .unwrap();
+    let iat = claims.registered.iat.unwrap();
+    assert!(iat >= before && iat <= before + 5);
+    assert_eq!(claims.registered.exp, Some(iat + 3600));
+    assert_eq!(claims.registered.nbf, Some(iat));
+    assert_eq!(claims.registered.jti.as_ref().unwrap().len(), 22);
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(&claims, key(), None).finalize().unwrap();
+    let decoded: Claims<serde_json::Value> =
+        Jwt::verify_into(&token, &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded, claims);
+
+    // Custom claims may not repeat the registered ones that are set, `iat` always is
+    let clock = || FixedClock::from_unix(1_000);
+    let build = |custom| {
+        ClaimsBuilder::new()
+            .with_clock(clock())
+            .subject("me")
+            .build(custom)
+    };
+    let err = serde_json::to_string(&build(serde_json::json!({"sub": "x"})).unwrap()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Claim sub is set both as a registered and a custom claim"
+    );
+    assert!(serde_json::to_string(&build(serde_json::json!({"iat": 1})).unwrap()).is_err());
+    assert_eq!(
+        serde_json::to_string(&build(serde_json::json!({"exp": 2})).unwrap()).unwrap(),
+        r#"{"sub":"me","iat":1000,"exp":2}"#
+    );
+    let duplicated = Jwt::new(build(serde_json::json!({"sub": "x"})).unwrap(), key(), None);
+    assert!(duplicated.finalize().is_err());
+    let raw = Jwt::verify(&token, &key(), &[Algorithm::HS256])
+        .unwrap()
+        .into_claims();
+    assert_eq!(raw["department"], "accounting");
+    assert_eq!(raw["aud"][1], "b");
+
+    let registered = ClaimsBuilder::new()
+        .jti("fixed")
+        .build_registered()
+        .unwrap();
+    assert_eq!(registered.jti.as_deref(), Some("fixed"));
+    assert!(registered.exp.is_none());
+    assert!(registered.nbf.is_none());
+    let ids = (
+        ClaimsBuilder::new()
+            .generate_jti()
+            .build_registered()
+            .unwrap()
+            .jti,
+        ClaimsBuilder::new()
+            .generate_jti()
+            .build_registered()
+            .unwrap()
+            .jti,
+    );
+    assert_ne!(ids.0, ids.1);
+
+    let forever = ClaimsBuilder::new()
+        .expires_in(Duration::MAX)
+        .build_registered()
+        .unwrap();
+    assert_eq!(forever.exp, Some(i64::MAX));
+
+    let frozen = ClaimsBuilder::new()
+        .with_clock(FixedClock::from_unix(1_000))
+        .not_before(Duration::from_secs(10));
+    assert_eq!(frozen.clone().build_registered().unwrap().iat, Some(1_000));
+    assert_eq!(frozen.build_registered().unwrap().nbf, Some(1_010));
+}
@@ -0,0 +1,129 @@
+use alloc::sync::Arc;
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime};
+#[cfg(feature = "std")]
+use time::OffsetDateTime;
+
+use crate::JwtErr;
+
+/// Source of the current time for `ValidationOptions` and `ClaimsBuilder`
+///
+/// `SystemClock` is used unless another one is passed to `with_clock`, tests can freeze time
+/// with a `FixedClock` and devices without a system clock can read their RTC instead.
+///
+/// Implementations provide `now` or `unix_now`, each defaults to the other. Without the `std`
+/// feature there is neither `SystemTime` nor `SystemClock`, `unix_now` has to be implemented and
+/// a clock set wherever time claims are checked or set.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # fn main() {
+/// use smpl_jwt::{Clock, ClaimsBuilder, FixedClock};
+/// use std::time::Duration;
+///
+/// let claims = ClaimsBuilder::new()
+///     .with_clock(FixedClock::from_unix(1_700_000_000))
+///     .expires_in(Duration::from_secs(60))
+///     .build_registered()
+///     .unwrap();
+/// assert_eq!(claims.iat, Some(1_700_000_000));
+/// assert_eq!(claims.exp, Some(1_700_000_060));
+/// # }
+/// # #[cfg(not(feature = "std"))]
+/// # fn main() {}
+/// ```
+pub trait Clock: Debug + Send + Sync {
+    #[cfg(feature = "std")]
+    fn now(&self) -> SystemTime {
+        let seconds = self.unix_now();
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        if seconds < 0 {
+            SystemTime::UNIX_EPOCH - offset
+        } else {
+            SystemTime::UNIX_EPOCH + offset
+        }
+    }
+
+    /// Seconds since the epoch, negative before it
+    #[cfg(feature = "std")]
+    fn unix_now(&self) -> i64 {
+        OffsetDateTime::from(self.now()).unix_timestamp()
+    }
+
+    /// Seconds since the epoch, negative before it
+    #[cfg(not(feature = "std"))]
+    fn unix_now(&self) -> i64;
+}
+
+/// `SystemTime::now()`
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Always tells the same time
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub SystemTime);
+
+#[cfg(feature = "std")]
+impl FixedClock {
+    /// Frozen at `seconds` since the epoch
+    pub fn from_unix(seconds: u64) -> Self {
+        FixedClock(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// The clock options start out with, none without `std`
+#[cfg(feature = "std")]
+pub(crate) fn default_clock() -> Option<Arc<dyn Clock>> {
+    Some(Arc::new(SystemClock))
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn default_clock() -> Option<Arc<dyn Clock>> {
+    None
+}
+
+/// Checking or stamping time claims without a clock to tell the time
+pub(crate) fn no_clock() -> JwtErr {
+    JwtErr::from("Time claims need a clock without the std feature, set one with with_clock")
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_clocks() {
+    #[derive(Debug)]
+    struct Rtc(i64);
+    impl Clock for Rtc {
+        fn unix_now(&self) -> i64 {
+            self.0
+        }
+    }
+
+    assert_eq!(
+        FixedClock::from_unix(1_700_000_000).unix_now(),
+        1_700_000_000
+    );
+    let before = SystemTime::UNIX_EPOCH - Duration::from_secs(60);
+    assert_eq!(FixedClock(before).unix_now(), -60);
+    assert_eq!(Rtc(-60).now(), before);
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    assert!((SystemClock.unix_now() - now).abs() <= 5);
+}
@@ -0,0 +1,429 @@
+use crate::JwtErr;
+
+// Raw DEFLATE (RFC 1951) for the JWE `zip: "DEF"` header. Compression is LZ77 with the fixed
+// Huffman codes, which is most of the gain on the repetitive JSON tokens carry, decompression
+// understands every block type and stops at a caller given output size.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths of a dynamic block are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Compresses `data` into a single raw DEFLATE block
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::default();
+    // BFINAL, then BTYPE 01 for the fixed Huffman codes
+    out.put(1, 1);
+    out.put(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = longest_match(data, pos, &head, &prev);
+        let step = if len >= MIN_MATCH {
+            write_match(&mut out, len, dist);
+            len
+        } else {
+            write_literal_length(&mut out, data[pos] as u16);
+            1
+        };
+        for i in pos..pos + step {
+            if i + MIN_MATCH <= data.len() {
+                let h = hash(&data[i..]);
+                prev[i] = head[h];
+                head[h] = i;
+            }
+        }
+        pos += step;
+    }
+    write_literal_length(&mut out, 256);
+    out.finish()
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max = (data.len() - pos).min(MAX_MATCH);
+    let (mut best_len, mut best_dist) = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    let mut chain = 0;
+    while candidate != usize::MAX && pos - candidate <= WINDOW && chain < MAX_CHAIN {
+        let len = data[candidate..]
+            .iter()
+            .zip(&data[pos..pos + max])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best_len {
+            best_len = len;
+            best_dist = pos - candidate;
+            if len == max {
+                break;
+            }
+        }
+        candidate = prev[candidate];
+        chain += 1;
+    }
+    (best_len, best_dist)
+}
+
+/// Fixed Huffman code of a literal/length symbol, RFC 1951 3.2.6
+fn write_literal_length(out: &mut BitWriter, symbol: u16) {
+    let (code, len) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    };
+    out.put_code(code, len);
+}
+
+fn write_match(out: &mut BitWriter, len: usize, dist: usize) {
+    let i = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= len)
+        .unwrap();
+    write_literal_length(out, 257 + i as u16);
+    out.put(len as u32 - LENGTH_BASE[i] as u32, LENGTH_EXTRA[i] as u32);
+    let i = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= dist)
+        .unwrap();
+    out.put_code(i as u16, 5);
+    out.put(
+        dist as u32 - DISTANCE_BASE[i] as u32,
+        DISTANCE_EXTRA[i] as u32,
+    );
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buf: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `n` bits of `value`, least significant first
+    fn put(&mut self, value: u32, n: u32) {
+        self.buf |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit
+    fn put_code(&mut self, code: u16, len: u32) {
+        let reversed = (code.reverse_bits() >> (16 - len)) as u32;
+        self.put(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buf as u8);
+        }
+        self.out
+    }
+}
+
+/// Decompresses raw DEFLATE data, failing once the output would exceed `limit` bytes
+pub(crate) fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, JwtErr> {
+    let mut input = BitReader {
+        data,
+        pos: 0,
+        buf: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored_block(&mut input, &mut out, limit)?,
+            1 => {
+                let (literal_length, distance) = fixed_codes();
+                huffman_block(&mut input, &mut out, limit, &literal_length, &distance)?
+            }
+            2 => {
+                let (literal_length, distance) = dynamic_codes(&mut input)?;
+                huffman_block(&mut input, &mut out, limit, &literal_length, &distance)?
+            }
+            _ => return Err(JwtErr::from("Invalid DEFLATE block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, JwtErr> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| JwtErr::from("Truncated DEFLATE data"))?;
+            self.buf |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+}
+
+/// Canonical Huffman code given by its code lengths, decoded one bit at a time
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, JwtErr> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(JwtErr::from("Over-subscribed DEFLATE Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, JwtErr> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(JwtErr::from("Invalid DEFLATE Huffman code"))
+    }
+}
+
+fn stored_block(input: &mut BitReader, out: &mut Vec<u8>, limit: usize) -> Result<(), JwtErr> {
+    // Stored data starts at the next byte boundary
+    input.buf = 0;
+    input.count = 0;
+    let header = input
+        .data
+        .get(input.pos..input.pos + 4)
+        .ok_or_else(|| JwtErr::from("Truncated DEFLATE data"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    if len != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(JwtErr::from("Corrupt DEFLATE stored block"));
+    }
+    input.pos += 4;
+    let bytes = input
+        .data
+        .get(input.pos..input.pos + len as usize)
+        .ok_or_else(|| JwtErr::from("Truncated DEFLATE data"))?;
+    check_limit(out.len() + bytes.len(), limit)?;
+    out.extend_from_slice(bytes);
+    input.pos += len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // Neither table is over-subscribed
+    let literal_length = Huffman::new(&lengths).unwrap();
+    let distance = Huffman::new(&[5; 30]).unwrap();
+    (literal_length, distance)
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), JwtErr> {
+    let literal_lengths = input.bits(5)? as usize + 257;
+    let distances = input.bits(5)? as usize + 1;
+    let code_lengths = input.bits(4)? as usize + 4;
+    if literal_lengths > 286 || distances > 30 {
+        return Err(JwtErr::from("Too many DEFLATE Huffman codes"));
+    }
+
+    let mut lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = input.bits(3)? as u8;
+    }
+    let code_length = Huffman::new(&lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_lengths + distances);
+    while lengths.len() < literal_lengths + distances {
+        let (value, repeat) = match code_length.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| JwtErr::from("DEFLATE length repeat without a length"))?;
+                (previous, 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_lengths + distances {
+            return Err(JwtErr::from("Too many DEFLATE code lengths"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths[256] == 0 {
+        return Err(JwtErr::from("DEFLATE block has no end code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_lengths])?,
+        Huffman::new(&lengths[literal_lengths..])?,
+    ))
+}
+
+fn huffman_block(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literal_length: &Huffman,
+    distance: &Huffman,
+) -> Result<(), JwtErr> {
+    loop {
+        let symbol = literal_length.decode(input)? as usize;
+        if symbol < 256 {
+            check_limit(out.len() + 1, limit)?;
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(JwtErr::from("Invalid DEFLATE length code"));
+        }
+        let len = LENGTH_BASE[i] as usize + input.bits(LENGTH_EXTRA[i] as u32)? as usize;
+        let i = distance.decode(input)? as usize;
+        if i >= DISTANCE_BASE.len() {
+            return Err(JwtErr::from("Invalid DEFLATE distance code"));
+        }
+        let dist = DISTANCE_BASE[i] as usize + input.bits(DISTANCE_EXTRA[i] as u32)? as usize;
+        if dist > out.len() {
+            return Err(JwtErr::from("DEFLATE distance reaches before the output"));
+        }
+        check_limit(out.len() + len, limit)?;
+        // Copied one byte at a time, a match may overlap the bytes it produces
+        let start = out.len() - dist;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+fn check_limit(len: usize, limit: usize) -> Result<(), JwtErr> {
+    if len > limit {
+        return Err(JwtErr::from(
+            format!("Decompressed data exceeds {} bytes", limit).as_str(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_deflate() {
+    let entitlements = (0..40)
+        .map(|i| format!("\"group-{}:read\"", i))
+        .collect::<Vec<_>>()
+        .join(",");
+    let json = format!("{{\"entitlements\":[{}]}}", entitlements);
+
+    for data in [
+        Vec::new(),
+        b"a".to_vec(),
+        b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_vec(),
+        json.as_bytes().to_vec(),
+        (0..=255).cycle().take(70000).collect(),
+    ] {
+        let compressed = compress(&data);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+    assert!(compress(json.as_bytes()).len() < json.len() / 3);
+
+    // zlib at level 9, a dynamic Huffman block
+    let hex = "5dd1bb0ac2501045d17fb9b581cc39898ffc4ab0107211c1173156e2bf6b91667635ec6e71e653ea\
+               7db92cd77afbdf5719c6729e1fef67d30e733d4d65b366e4544ee7ec72f639b7397739f7390f6090\
+               05570016900568015b0017d00578019fe01377834ff0093ec127f8049fe0137c86cff0998f85cff\
+               0193ec367f80c9f57dff1fb03";
+    let bytes = |hex: &str| {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>()
+    };
+    let zlib = bytes(hex);
+    assert_eq!(decompress(&zlib, 1 << 20).unwrap(), json.as_bytes());
+    assert!(decompress(&zlib, json.len() - 1).is_err());
+    assert!(decompress(&zlib[..zlib.len() / 2], 1 << 20).is_err());
+    assert_eq!(
+        decompress(&bytes("010600f9ff73746f726564"), 6).unwrap(),
+        b"stored"
+    );
+    assert!(decompress(&bytes("010600f8ff73746f726564"), 6).is_err());
+    assert!(decompress(&[0x07], 1 << 20).is_err());
+    assert!(decompress(&[], 1 << 20).is_err());
+}
@@ -0,0 +1,528 @@
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcGroupRef};
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, PKey, PKeyRef, Private, Public};
+use openssl::sign::{Signer, Verifier};
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::backend::message_digest;
+use crate::stream::Incremental;
+use crate::{
+    pem_from_env, pkcs8_from_der, pkcs8_from_pem, read_all, read_file, read_keyfile, Algorithm,
+    IncrementalSigner, Jwk, JwtErr, SigningKey, VerifyingKey,
+};
+
+/// Curves supported by the `ES*` algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcCurve {
+    P256,
+    P384,
+    P521,
+    Secp256k1,
+}
+
+impl EcCurve {
+    /// The only algorithm allowed with keys on this curve
+    pub fn algorithm(&self) -> Algorithm {
+        match *self {
+            EcCurve::P256 => Algorithm::ES256,
+            EcCurve::P384 => Algorithm::ES384,
+            EcCurve::P521 => Algorithm::ES512,
+            EcCurve::Secp256k1 => Algorithm::ES256K,
+        }
+    }
+
+    fn from_algorithm(algo: &Algorithm) -> Option<EcCurve> {
+        match *algo {
+            Algorithm::ES256 => Some(EcCurve::P256),
+            Algorithm::ES384 => Some(EcCurve::P384),
+            Algorithm::ES512 => Some(EcCurve::P521),
+            Algorithm::ES256K => Some(EcCurve::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// Parses the JWK `crv` name, as produced by `Display`
+    pub(crate) fn from_name(name: &str) -> Option<EcCurve> {
+        match name {
+            "P-256" => Some(EcCurve::P256),
+            "P-384" => Some(EcCurve::P384),
+            "P-521" => Some(EcCurve::P521),
+            "secp256k1" => Some(EcCurve::Secp256k1),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn nid(&self) -> Nid {
+        match *self {
+            EcCurve::P256 => Nid::X9_62_PRIME256V1,
+            EcCurve::P384 => Nid::SECP384R1,
+            EcCurve::P521 => Nid::SECP521R1,
+            EcCurve::Secp256k1 => Nid::SECP256K1,
+        }
+    }
+
+    fn from_nid(nid: Nid) -> Option<EcCurve> {
+        match nid {
+            Nid::X9_62_PRIME256V1 => Some(EcCurve::P256),
+            Nid::SECP384R1 => Some(EcCurve::P384),
+            Nid::SECP521R1 => Some(EcCurve::P521),
+            Nid::SECP256K1 => Some(EcCurve::Secp256k1),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EcCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EcCurve::P256 => write!(f, "P-256"),
+            EcCurve::P384 => write!(f, "P-384"),
+            EcCurve::P521 => write!(f, "P-521"),
+            EcCurve::Secp256k1 => write!(f, "secp256k1"),
+        }
+    }
+}
+
+/// Elliptic curve private key used with the `ES*` algorithms
+pub struct EcKey {
+    pub(crate) key: PKey<Private>,
+    curve: EcCurve,
+}
+
+impl EcKey {
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(read_keyfile(filename)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Reads a PEM from the environment variable `name`, literal `\n` sequences are unescaped
+    pub fn from_env(name: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&pem_from_env(name)?)?)
+    }
+
+    /// Parses a DER encoded SEC1 `ECPrivateKey` or PKCS#8 structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_der(der)?)
+    }
+
+    /// Parses a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, failing on any other PEM format
+    pub fn from_pkcs8_pem(pem: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_pem(pem)?)
+    }
+
+    /// Parses a DER encoded PKCS#8 structure, failing on SEC1
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_der(der)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Private>) -> Result<Self, JwtErr> {
+        let curve = curve_of(&pkey)?;
+        Ok(EcKey { key: pkey, curve })
+    }
+
+    /// Generates a fresh key on `curve`
+    pub fn generate(curve: EcCurve) -> Result<Self, JwtErr> {
+        let group = EcGroup::from_curve_name(curve.nid())?;
+        Self::from_pkey(PKey::from_ec_key(openssl::ec::EcKey::generate(&group)?)?)
+    }
+
+    pub fn curve(&self) -> EcCurve {
+        self.curve
+    }
+
+    /// The ES* algorithm matching the key's curve
+    pub fn algorithm(&self) -> Algorithm {
+        self.curve.algorithm()
+    }
+
+    /// Public half of the key, for verifying the tokens it signs
+    pub fn public_key(&self) -> Result<EcPublicKey, JwtErr> {
+        EcPublicKey::from_der(&self.key.public_key_to_der()?)
+    }
+
+    /// Public half of the key as a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM
+    pub fn public_key_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_pem()?)
+    }
+
+    /// The key as a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, which `from_pkcs8_pem` reads back
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pem_pkcs8()?)
+    }
+
+    /// The key as a DER encoded PKCS#8 structure, which `from_pkcs8_der` reads back
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pkcs8()?)
+    }
+
+    /// The key as a JWK including the private parameters, keep it secret
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::ec_private(&self.key, self.curve)
+    }
+
+    /// Public half of the key as a JWK, ready to be published
+    pub fn to_public_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::ec_public(&self.key, self.curve)
+    }
+}
+
+impl SigningKey for EcKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        check_algorithm(self.curve, algo)?;
+        let mut signer = Signer::new(message_digest(algo), &self.key)?;
+        signer.update(input)?;
+        self.finish_signature(algo, signer)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm()
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        check_algorithm(self.curve, algo)?;
+        let algo = *algo;
+        Ok(Some(Box::new(Incremental::new(
+            Signer::new(message_digest(&algo), &self.key)?,
+            move |signer| self.finish_signature(&algo, signer),
+        ))))
+    }
+}
+
+impl EcKey {
+    fn finish_signature(&self, algo: &Algorithm, signer: Signer) -> Result<Vec<u8>, JwtErr> {
+        let mut sig = EcdsaSig::from_der(&signer.sign_to_vec()?)?;
+        if let Algorithm::ES256K = *algo {
+            sig = normalize_low_s(sig, self.key.ec_key()?.group())?;
+        }
+        to_fixed(&sig, coordinate_len(&self.key)?)
+    }
+}
+
+impl VerifyingKey for EcKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_ecdsa(&self.key, self.curve, algo, input, signature)
+    }
+}
+
+impl FromStr for EcKey {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(s.as_bytes())?)
+    }
+}
+
+/// Elliptic curve public key, enough to verify `ES*` signatures without holding private material
+pub struct EcPublicKey {
+    pub(crate) key: PKey<Public>,
+    curve: EcCurve,
+}
+
+impl EcPublicKey {
+    /// Reads a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM file
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_file(filename)?)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Parses a DER encoded SubjectPublicKeyInfo structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_der(der)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Public>) -> Result<Self, JwtErr> {
+        let curve = curve_of(&pkey)?;
+        Ok(EcPublicKey { key: pkey, curve })
+    }
+
+    /// The key as a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_pem()?)
+    }
+
+    /// The key as a DER encoded SubjectPublicKeyInfo structure
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_der()?)
+    }
+
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::ec_public(&self.key, self.curve)
+    }
+
+    pub fn curve(&self) -> EcCurve {
+        self.curve
+    }
+
+    /// The ES* algorithm matching the key's curve
+    pub fn algorithm(&self) -> Algorithm {
+        self.curve.algorithm()
+    }
+}
+
+impl VerifyingKey for EcPublicKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_ecdsa(&self.key, self.curve, algo, input, signature)
+    }
+}
+
+impl FromStr for EcPublicKey {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(s.as_bytes())?)
+    }
+}
+
+fn curve_of<T: HasPublic>(pkey: &PKeyRef<T>) -> Result<EcCurve, JwtErr> {
+    let nid = match pkey.ec_key() {
+        Ok(ec_key) => ec_key.group().curve_name(),
+        Err(_) => return Err(JwtErr::from("Key is not an EC key")),
+    };
+    nid.and_then(EcCurve::from_nid)
+        .ok_or_else(|| JwtErr::from("Unsupported EC curve"))
+}
+
+/// Fails unless `algo` is the ES* algorithm matching `curve`
+fn check_algorithm(curve: EcCurve, algo: &Algorithm) -> Result<(), JwtErr> {
+    match EcCurve::from_algorithm(algo) {
+        Some(expected) if expected == curve => Ok(()),
+        Some(expected) => Err(JwtErr::from(
+            format!("{} requires a key on curve {}", algo, expected).as_str(),
+        )),
+        None => Err(JwtErr::from(
+            format!("{} can not be used with an EC key", algo).as_str(),
+        )),
+    }
+}
+
+fn verify_ecdsa<T: HasPublic>(
+    key: &PKeyRef<T>,
+    curve: EcCurve,
+    algo: &Algorithm,
+    input: &[u8],
+    signature: &[u8],
+) -> Result<bool, JwtErr> {
+    check_algorithm(curve, algo)?;
+    if signature.len() != 2 * coordinate_len(key)? {
+        return Ok(false);
+    }
+    let mut verifier = Verifier::new(message_digest(algo), key)?;
+    verifier.update(input)?;
+    Ok(verifier.verify(&fixed_to_der(signature)?)?)
+}
+
+/// Byte length of a single signature coordinate (R or S) for the key's curve,
+/// 32 for P-256, 48 for P-384 and 66 for P-521 whose 521 bit order does not fill the last byte
+fn coordinate_len<T: HasPublic>(key: &PKeyRef<T>) -> Result<usize, JwtErr> {
+    let degree = key.ec_key()?.group().degree() as usize;
+    Ok(degree.div_ceil(8))
+}
+
+/// Both (R, S) and (R, n - S) are valid signatures, secp256k1 verifiers commonly only
+/// accept the variant with S in the lower half of the curve order
+fn normalize_low_s(sig: EcdsaSig, group: &EcGroupRef) -> Result<EcdsaSig, JwtErr> {
+    let mut ctx = BigNumContext::new()?;
+    let mut order = BigNum::new()?;
+    group.order(&mut order, &mut ctx)?;
+    let mut half = BigNum::new()?;
+    half.rshift1(&order)?;
+    if sig.s() <= &half {
+        return Ok(sig);
+    }
+    let mut s = BigNum::new()?;
+    s.checked_sub(&order, sig.s())?;
+    Ok(EcdsaSig::from_private_components(sig.r().to_owned()?, s)?)
+}
+
+/// OpenSSL emits ECDSA signatures DER encoded, JWS wants R and S concatenated as fixed width big endians
+fn to_fixed(sig: &EcdsaSig, len: usize) -> Result<Vec<u8>, JwtErr> {
+    let mut out = sig.r().to_vec_padded(len as i32)?;
+    out.extend(sig.s().to_vec_padded(len as i32)?);
+    Ok(out)
+}
+
+/// Converts a DER encoded ECDSA signature made elsewhere, e.g. by a KMS, into the JWS form
+#[cfg(any(feature = "kms-aws", feature = "kms-gcp"))]
+pub(crate) fn der_to_jws(der: &[u8], algo: &Algorithm) -> Result<Vec<u8>, JwtErr> {
+    let curve = EcCurve::from_algorithm(algo)
+        .ok_or_else(|| JwtErr::from(format!("{} is not an ECDSA algorithm", algo).as_str()))?;
+    let group = EcGroup::from_curve_name(curve.nid())?;
+    let mut sig = EcdsaSig::from_der(der)?;
+    if curve == EcCurve::Secp256k1 {
+        sig = normalize_low_s(sig, &group)?;
+    }
+    to_fixed(&sig, (group.degree() as usize).div_ceil(8))
+}
+
+fn fixed_to_der(fixed: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    let (r, s) = fixed.split_at(fixed.len() / 2);
+    let sig = EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+    Ok(sig.to_der()?)
+}
+
+#[test]
+fn test_sign_es256() {
+    use crate::Jwt;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use openssl::hash::MessageDigest;
+    use openssl::sign::Verifier;
+
+    let ec_key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let pkey = ec_key.key.clone();
+    let jwt = Jwt::new("body", ec_key, Some(Algorithm::ES256));
+    let token = jwt.finalize().unwrap();
+
+    let (input, signature) = token.rsplit_once('.').unwrap();
+    let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    assert_eq!(signature.len(), 64);
+
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey).unwrap();
+    verifier.update(input.as_bytes()).unwrap();
+    assert!(verifier.verify(&fixed_to_der(&signature).unwrap()).unwrap());
+}
+
+#[test]
+fn test_sign_es384_es512() {
+    use openssl::hash::MessageDigest;
+    use openssl::sign::Verifier;
+
+    for (algo, keyfile, digest, len) in [
+        (
+            Algorithm::ES384,
+            "random_ec384_for_testing",
+            MessageDigest::sha384(),
+            96,
+        ),
+        (
+            Algorithm::ES512,
+            "random_ec521_for_testing",
+            MessageDigest::sha512(),
+            132,
+        ),
+    ] {
+        let ec_key = EcKey::from_pem(keyfile).unwrap();
+        // Signatures with short R or S values must still be padded to full width
+        for i in 0..16u8 {
+            let input = [i; 8];
+            let signature = ec_key.sign(&algo, &input).unwrap();
+            assert_eq!(signature.len(), len);
+
+            let mut verifier = Verifier::new(digest, &ec_key.key).unwrap();
+            verifier.update(&input).unwrap();
+            assert!(verifier.verify(&fixed_to_der(&signature).unwrap()).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_sign_es256k_low_s() {
+    use openssl::hash::MessageDigest;
+    use openssl::sign::Verifier;
+
+    let ec_key = EcKey::from_pem("random_secp256k1_for_testing").unwrap();
+    let group = ec_key.key.ec_key().unwrap();
+    let mut order = BigNum::new().unwrap();
+    group
+        .group()
+        .order(&mut order, &mut BigNumContext::new().unwrap())
+        .unwrap();
+    let mut half = BigNum::new().unwrap();
+    half.rshift1(&order).unwrap();
+
+    for i in 0..32u8 {
+        let input = [i; 8];
+        let signature = ec_key.sign(&Algorithm::ES256K, &input).unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(BigNum::from_slice(&signature[32..]).unwrap() <= half);
+
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &ec_key.key).unwrap();
+        verifier.update(&input).unwrap();
+        assert!(verifier.verify(&fixed_to_der(&signature).unwrap()).unwrap());
+    }
+}
+
+#[test]
+fn test_ec_curve_introspection() {
+    for (keyfile, curve, algo) in [
+        ("random_ec_for_testing", EcCurve::P256, Algorithm::ES256),
+        ("random_ec384_for_testing", EcCurve::P384, Algorithm::ES384),
+        ("random_ec521_for_testing", EcCurve::P521, Algorithm::ES512),
+        (
+            "random_secp256k1_for_testing",
+            EcCurve::Secp256k1,
+            Algorithm::ES256K,
+        ),
+    ] {
+        let ec_key = EcKey::from_pem(keyfile).unwrap();
+        assert_eq!(ec_key.curve(), curve);
+        assert_eq!(ec_key.algorithm(), algo);
+        assert!(ec_key.sign(&ec_key.algorithm(), b"input").is_ok());
+    }
+    assert_eq!(EcCurve::P521.to_string(), "P-521");
+}
+
+#[test]
+fn test_ec_from_der_and_pkey() {
+    use openssl::ec::{EcGroup, EcKey as OpenSslEcKey};
+
+    let ec_key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let sec1 = ec_key.key.ec_key().unwrap().private_key_to_der().unwrap();
+    let pkcs8 = ec_key.key.private_key_to_pkcs8().unwrap();
+    for der in [sec1, pkcs8] {
+        let from_der = EcKey::from_der(&der).unwrap();
+        let signature = from_der.sign(&Algorithm::ES256, b"input").unwrap();
+        assert!(ec_key
+            .verify(&Algorithm::ES256, b"input", &signature)
+            .unwrap());
+    }
+    assert!(EcKey::from_der(b"garbage").is_err());
+
+    let group = EcGroup::from_curve_name(Nid::BRAINPOOL_P256R1).unwrap();
+    let brainpool = PKey::from_ec_key(OpenSslEcKey::generate(&group).unwrap()).unwrap();
+    assert!(EcKey::from_pkey(brainpool).is_err());
+}
+
+#[test]
+fn test_verify_with_ec_public_key() {
+    use crate::Jwt;
+
+    let ec_key = EcKey::from_pem("random_ec384_for_testing").unwrap();
+    let public_key = ec_key.public_key().unwrap();
+    assert_eq!(public_key.curve(), EcCurve::P384);
+    let pem = ec_key.public_key_pem().unwrap();
+    let from_pem = EcPublicKey::from_str(std::str::from_utf8(&pem).unwrap()).unwrap();
+
+    let token = Jwt::new("body", ec_key, None).finalize().unwrap();
+    assert!(Jwt::verify(&token, &public_key, &[Algorithm::ES384]).is_ok());
+    assert!(Jwt::verify(&token, &from_pem, &[Algorithm::ES384]).is_ok());
+
+    let other = EcKey::from_pem("random_ec_for_testing")
+        .unwrap()
+        .public_key()
+        .unwrap();
+    assert!(Jwt::verify(&token, &other, &[Algorithm::ES384]).is_err());
+    assert!(EcPublicKey::from_pem("random_rsa_pub_for_testing").is_err());
+}
+
+#[test]
+fn test_ec_rejects_other_algorithms() {
+    let ec_key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    assert!(ec_key.sign(&Algorithm::RS256, b"input").is_err());
+    assert!(ec_key.sign(&Algorithm::ES384, b"input").is_err());
+    let ec_key = EcKey::from_pem("random_ec521_for_testing").unwrap();
+    assert!(ec_key.sign(&Algorithm::ES256, b"input").is_err());
+    assert!(EcKey::from_pem("random_rsa_for_testing").is_err());
+}
@@ -0,0 +1,489 @@
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use openssl::sign::{Signer, Verifier};
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::{
+    pem_from_env, pkcs8_from_der, pkcs8_from_pem, read_all, read_file, read_keyfile, Algorithm,
+    Jwk, JwtErr, SigningKey, VerifyingKey,
+};
+
+/// Ed25519 private key used with the `EdDSA` algorithm
+pub struct Ed25519Key {
+    key: PKey<Private>,
+}
+
+impl Ed25519Key {
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(read_keyfile(filename)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Reads a PEM from the environment variable `name`, literal `\n` sequences are unescaped
+    pub fn from_env(name: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&pem_from_env(name)?)?)
+    }
+
+    /// Parses a DER encoded PKCS#8 structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_der(der)?)
+    }
+
+    /// Builds the key from the 32 byte private key as defined in RFC 8032
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_raw_bytes(bytes, Id::ED25519)?)
+    }
+
+    /// Builds the key from a 32 byte seed, the raw format most secret stores hand out
+    pub fn from_raw_seed(seed: [u8; 32]) -> Result<Self, JwtErr> {
+        Self::from_raw_bytes(&seed)
+    }
+
+    /// Parses a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, failing on any other PEM format
+    pub fn from_pkcs8_pem(pem: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_pem(pem)?)
+    }
+
+    /// Parses a DER encoded PKCS#8 structure
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_der(der)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Private>) -> Result<Self, JwtErr> {
+        Ok(Ed25519Key {
+            key: expect_curve(pkey, Id::ED25519)?,
+        })
+    }
+
+    /// Generates a fresh key
+    pub fn generate() -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::generate_ed25519()?)
+    }
+
+    /// Public half of the key, for verifying the tokens it signs
+    pub fn public_key(&self) -> Result<Ed25519PublicKey, JwtErr> {
+        Ed25519PublicKey::from_raw_bytes(&self.key.raw_public_key()?)
+    }
+
+    /// The key as a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, which `from_pkcs8_pem` reads back
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pem_pkcs8()?)
+    }
+
+    /// The key as a DER encoded PKCS#8 structure, which `from_pkcs8_der` reads back
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pkcs8()?)
+    }
+
+    /// The key as a JWK including the private parameters, keep it secret
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_private(&self.key, "Ed25519")
+    }
+
+    /// Public half of the key as a JWK, ready to be published
+    pub fn to_public_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_public(&self.key, "Ed25519")
+    }
+}
+
+impl SigningKey for Ed25519Key {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        sign_eddsa(&self.key, algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        Algorithm::EdDSA
+    }
+}
+
+impl VerifyingKey for Ed25519Key {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_eddsa(&self.key, algo, input, signature)
+    }
+}
+
+impl FromStr for Ed25519Key {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(s.as_bytes())?)
+    }
+}
+
+/// Ed448 private key used with the `EdDSA` algorithm
+pub struct Ed448Key {
+    key: PKey<Private>,
+}
+
+impl Ed448Key {
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(read_keyfile(filename)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Reads a PEM from the environment variable `name`, literal `\n` sequences are unescaped
+    pub fn from_env(name: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(&pem_from_env(name)?)?)
+    }
+
+    /// Parses a DER encoded PKCS#8 structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_der(der)?)
+    }
+
+    /// Builds the key from the 57 byte private key as defined in RFC 8032
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_raw_bytes(bytes, Id::ED448)?)
+    }
+
+    /// Parses a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, failing on any other PEM format
+    pub fn from_pkcs8_pem(pem: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_pem(pem)?)
+    }
+
+    /// Parses a DER encoded PKCS#8 structure
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(pkcs8_from_der(der)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Private>) -> Result<Self, JwtErr> {
+        Ok(Ed448Key {
+            key: expect_curve(pkey, Id::ED448)?,
+        })
+    }
+
+    /// Generates a fresh key
+    pub fn generate() -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::generate_ed448()?)
+    }
+
+    /// Public half of the key, for verifying the tokens it signs
+    pub fn public_key(&self) -> Result<Ed448PublicKey, JwtErr> {
+        Ed448PublicKey::from_raw_bytes(&self.key.raw_public_key()?)
+    }
+
+    /// The key as a PKCS#8 (`BEGIN PRIVATE KEY`) PEM, which `from_pkcs8_pem` reads back
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pem_pkcs8()?)
+    }
+
+    /// The key as a DER encoded PKCS#8 structure, which `from_pkcs8_der` reads back
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.private_key_to_pkcs8()?)
+    }
+
+    /// The key as a JWK including the private parameters, keep it secret
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_private(&self.key, "Ed448")
+    }
+
+    /// Public half of the key as a JWK, ready to be published
+    pub fn to_public_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_public(&self.key, "Ed448")
+    }
+}
+
+impl SigningKey for Ed448Key {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        sign_eddsa(&self.key, algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        Algorithm::EdDSA
+    }
+}
+
+impl VerifyingKey for Ed448Key {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_eddsa(&self.key, algo, input, signature)
+    }
+}
+
+impl FromStr for Ed448Key {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(s.as_bytes())?)
+    }
+}
+
+/// Ed25519 public key, enough to verify `EdDSA` signatures without holding private material
+pub struct Ed25519PublicKey {
+    key: PKey<Public>,
+}
+
+impl Ed25519PublicKey {
+    /// Reads a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM file
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_file(filename)?)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Parses a DER encoded SubjectPublicKeyInfo structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_der(der)?)
+    }
+
+    /// Builds the key from the 32 byte public key as defined in RFC 8032
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_raw_bytes(bytes, Id::ED25519)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Public>) -> Result<Self, JwtErr> {
+        Ok(Ed25519PublicKey {
+            key: expect_curve(pkey, Id::ED25519)?,
+        })
+    }
+
+    /// The key as a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_pem()?)
+    }
+
+    /// The key as a DER encoded SubjectPublicKeyInfo structure
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_der()?)
+    }
+
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_public(&self.key, "Ed25519")
+    }
+}
+
+impl VerifyingKey for Ed25519PublicKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_eddsa(&self.key, algo, input, signature)
+    }
+}
+
+impl FromStr for Ed25519PublicKey {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(s.as_bytes())?)
+    }
+}
+
+/// Ed448 public key, enough to verify `EdDSA` signatures without holding private material
+pub struct Ed448PublicKey {
+    key: PKey<Public>,
+}
+
+impl Ed448PublicKey {
+    /// Reads a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM file
+    pub fn from_pem(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_file(filename)?)?)
+    }
+
+    /// Like `from_pem`, reading the PEM from `reader` instead of a file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(&read_all(reader)?)?)
+    }
+
+    /// Parses a DER encoded SubjectPublicKeyInfo structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_der(der)?)
+    }
+
+    /// Builds the key from the 57 byte public key as defined in RFC 8032
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_raw_bytes(bytes, Id::ED448)?)
+    }
+
+    pub fn from_pkey(pkey: PKey<Public>) -> Result<Self, JwtErr> {
+        Ok(Ed448PublicKey {
+            key: expect_curve(pkey, Id::ED448)?,
+        })
+    }
+
+    /// The key as a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM
+    pub fn to_pem(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_pem()?)
+    }
+
+    /// The key as a DER encoded SubjectPublicKeyInfo structure
+    pub fn to_der(&self) -> Result<Vec<u8>, JwtErr> {
+        Ok(self.key.public_key_to_der()?)
+    }
+
+    pub fn to_jwk(&self) -> Result<Jwk, JwtErr> {
+        Jwk::okp_public(&self.key, "Ed448")
+    }
+}
+
+impl VerifyingKey for Ed448PublicKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        verify_eddsa(&self.key, algo, input, signature)
+    }
+}
+
+impl FromStr for Ed448PublicKey {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(s.as_bytes())?)
+    }
+}
+
+fn expect_curve<T>(key: PKey<T>, id: Id) -> Result<PKey<T>, JwtErr> {
+    if key.id() != id {
+        let curve = if id == Id::ED448 { "Ed448" } else { "Ed25519" };
+        return Err(JwtErr::from(
+            format!("Key is not an {} key", curve).as_str(),
+        ));
+    }
+    Ok(key)
+}
+
+/// The JWS `alg` is `EdDSA` for both curves, OpenSSL picks Ed25519 or Ed448 from the key itself
+fn sign_eddsa(key: &PKey<Private>, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+    match *algo {
+        Algorithm::EdDSA => {
+            let mut signer = Signer::new_without_digest(key)?;
+            Ok(signer.sign_oneshot_to_vec(input)?)
+        }
+        _ => Err(JwtErr::from(
+            format!("{} can not be used with an EdDSA key", algo).as_str(),
+        )),
+    }
+}
+
+fn verify_eddsa<T: HasPublic>(
+    key: &PKeyRef<T>,
+    algo: &Algorithm,
+    input: &[u8],
+    signature: &[u8],
+) -> Result<bool, JwtErr> {
+    match *algo {
+        Algorithm::EdDSA => {
+            let mut verifier = Verifier::new_without_digest(key)?;
+            Ok(verifier.verify_oneshot(signature, input)?)
+        }
+        _ => Err(JwtErr::from(
+            format!("{} can not be used with an EdDSA key", algo).as_str(),
+        )),
+    }
+}
+
+#[test]
+fn test_sign_eddsa() {
+    use crate::{Jwt, JwtHeader};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use openssl::sign::Verifier;
+
+    let ed_key = Ed25519Key::from_pem("random_ed25519_for_testing").unwrap();
+    let pkey = ed_key.key.clone();
+    let token = Jwt::new("body", ed_key, Some(Algorithm::EdDSA))
+        .finalize()
+        .unwrap();
+
+    let (input, signature) = token.rsplit_once('.').unwrap();
+    let (header, _) = input.split_once('.').unwrap();
+    let header: JwtHeader =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+    assert_eq!(header.algorithm(), Algorithm::EdDSA);
+
+    let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    assert_eq!(signature.len(), 64);
+    let mut verifier = Verifier::new_without_digest(&pkey).unwrap();
+    assert!(verifier
+        .verify_oneshot(&signature, input.as_bytes())
+        .unwrap());
+}
+
+#[test]
+fn test_ed25519_from_raw_bytes() {
+    let ed_key = Ed25519Key::from_pem("random_ed25519_for_testing").unwrap();
+    let raw = ed_key.key.raw_private_key().unwrap();
+    let from_raw = Ed25519Key::from_raw_bytes(&raw).unwrap();
+    // Ed25519 is deterministic so both keys must produce the same signature
+    assert_eq!(
+        ed_key.sign(&Algorithm::EdDSA, b"input").unwrap(),
+        from_raw.sign(&Algorithm::EdDSA, b"input").unwrap()
+    );
+    assert!(Ed25519Key::from_raw_bytes(&raw[1..]).is_err());
+    assert!(Ed25519Key::from_pem("random_rsa_for_testing").is_err());
+    assert!(from_raw.sign(&Algorithm::RS256, b"input").is_err());
+}
+
+#[test]
+fn test_ed25519_seed_and_der() {
+    // RFC 8032 7.1 TEST 1
+    let seed = [
+        0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec, 0x2c,
+        0xc4, 0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03, 0x1c, 0xae,
+        0x7f, 0x60,
+    ];
+    let ed_key = Ed25519Key::from_raw_seed(seed).unwrap();
+    let signature = ed_key.sign(&Algorithm::EdDSA, b"").unwrap();
+    assert_eq!(
+        signature[..8],
+        [0xe5, 0x56, 0x43, 0x00, 0xc3, 0x60, 0xac, 0x72]
+    );
+
+    let der = ed_key.key.private_key_to_pkcs8().unwrap();
+    let from_der = Ed25519Key::from_der(&der).unwrap();
+    assert_eq!(from_der.sign(&Algorithm::EdDSA, b"").unwrap(), signature);
+    assert!(Ed448Key::from_der(&der).is_err());
+}
+
+#[test]
+fn test_sign_ed448() {
+    use openssl::sign::Verifier;
+
+    let ed_key = Ed448Key::from_pem("random_ed448_for_testing").unwrap();
+    let signature = ed_key.sign(&Algorithm::EdDSA, b"input").unwrap();
+    assert_eq!(signature.len(), 114);
+
+    let mut verifier = Verifier::new_without_digest(&ed_key.key).unwrap();
+    assert!(verifier.verify_oneshot(&signature, b"input").unwrap());
+
+    let raw = ed_key.key.raw_private_key().unwrap();
+    assert_eq!(raw.len(), 57);
+    let from_raw = Ed448Key::from_raw_bytes(&raw).unwrap();
+    assert_eq!(
+        signature,
+        from_raw.sign(&Algorithm::EdDSA, b"input").unwrap()
+    );
+}
+
+#[test]
+fn test_verify_with_eddsa_public_keys() {
+    use crate::Jwt;
+
+    let ed_key = Ed25519Key::from_pem("random_ed25519_for_testing").unwrap();
+    let public_key = ed_key.public_key().unwrap();
+    let pem = ed_key.key.public_key_to_pem().unwrap();
+    let from_pem = Ed25519PublicKey::from_str(std::str::from_utf8(&pem).unwrap()).unwrap();
+    let token = Jwt::new("body", ed_key, None).finalize().unwrap();
+    assert!(Jwt::verify(&token, &public_key, &[Algorithm::EdDSA]).is_ok());
+    assert!(Jwt::verify(&token, &from_pem, &[Algorithm::EdDSA]).is_ok());
+
+    let ed_key = Ed448Key::from_pem("random_ed448_for_testing").unwrap();
+    let public_key = ed_key.public_key().unwrap();
+    let der = ed_key.key.public_key_to_der().unwrap();
+    assert!(Jwt::verify(&token, &public_key, &[Algorithm::EdDSA]).is_err());
+    let token = Jwt::new("body", ed_key, None).finalize().unwrap();
+    assert!(Jwt::verify(&token, &public_key, &[Algorithm::EdDSA]).is_ok());
+    assert!(Jwt::verify(
+        &token,
+        &Ed448PublicKey::from_der(&der).unwrap(),
+        &[Algorithm::EdDSA]
+    )
+    .is_ok());
+    assert!(Ed25519PublicKey::from_der(&der).is_err());
+}
+
+#[test]
+fn test_eddsa_curve_detection() {
+    assert!(Ed448Key::from_pem("random_ed25519_for_testing").is_err());
+    assert!(Ed25519Key::from_pem("random_ed448_for_testing").is_err());
+}
@@ -0,0 +1,90 @@
+use crate::prelude::*;
+use base64::engine::Config;
+use base64::Engine;
+
+/// Encoding of the header, payload and signature segments a `Jwt` is serialized to
+///
+/// Tokens use unpadded base64url as RFC 7515 asks. Any `base64` engine can be passed to
+/// `Jwt::with_encoder` to meet a verifier halfway, `with_legacy_padding` is the padded base64url
+/// one. Verification accepts base64url with or without padding only, whatever the encoder.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use base64::alphabet;
+/// use base64::engine::general_purpose::{GeneralPurpose, PAD};
+/// use smpl_jwt::{Algorithm, HmacKey, Jwt};
+///
+/// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let padded = GeneralPurpose::new(&alphabet::URL_SAFE, PAD);
+/// let token = Jwt::new("claims", key(), None).with_encoder(padded).finalize().unwrap();
+/// assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_ok());
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+pub trait SegmentEncoder: Send + Sync {
+    fn encode_segment(&self, bytes: &[u8]) -> String;
+
+    /// Appends the encoding of `bytes` to `out`, by default through `encode_segment`
+    fn encode_segment_into(&self, bytes: &[u8], out: &mut String) {
+        out.push_str(&self.encode_segment(bytes));
+    }
+
+    /// Exact length the encoding of `len` bytes will have, if known, to size buffers up front
+    fn encoded_len(&self, _len: usize) -> Option<usize> {
+        None
+    }
+}
+
+impl<E: Engine + Send + Sync> SegmentEncoder for E {
+    fn encode_segment(&self, bytes: &[u8]) -> String {
+        Engine::encode(self, bytes)
+    }
+
+    fn encode_segment_into(&self, bytes: &[u8], out: &mut String) {
+        Engine::encode_string(self, bytes, out)
+    }
+
+    fn encoded_len(&self, len: usize) -> Option<usize> {
+        base64::encoded_len(len, self.config().encode_padding())
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_segment_encoder() {
+    use crate::{Algorithm, HmacKey, Jwt};
+    use alloc::sync::Arc;
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A hand written encoder, counting the segments it is given
+    struct Counting(Arc<AtomicUsize>);
+
+    impl SegmentEncoder for Counting {
+        fn encode_segment(&self, bytes: &[u8]) -> String {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            URL_SAFE_NO_PAD.encode(bytes)
+        }
+    }
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let count = Arc::new(AtomicUsize::new(0));
+    let token = Jwt::new("claims", key(), None)
+        .with_encoder(Counting(count.clone()))
+        .finalize()
+        .unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+    assert_eq!(token, Jwt::new("claims", key(), None).finalize().unwrap());
+
+    // Standard base64 is produced as asked, but it is not base64url and does not verify
+    let token = Jwt::new("???", key(), None)
+        .with_encoder(STANDARD)
+        .finalize()
+        .unwrap();
+    assert_eq!(token.split('.').nth(1), Some("Ij8/PyI="));
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+}
@@ -0,0 +1,174 @@
+use core::{error, fmt};
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::prelude::*;
+use crate::ValidationError;
+
+/// The error of everything fallible in this crate
+///
+/// Errors converted from serde_json, OpenSSL or I/O errors display as a short message and keep the
+/// converted error as their `source`, to be downcast or walked like any error chain. What a token
+/// was rejected for is told by `validation_error`. More fields may be added, it can not be built or
+/// destructured outside of this crate.
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::RSAKey;
+/// use std::error::Error;
+///
+/// let err = RSAKey::from_pem("no_such_file").err().unwrap();
+/// assert_eq!(err.to_string(), "I/O error");
+/// assert!(err.source().unwrap().downcast_ref::<std::io::Error>().is_some());
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct JwtErr {
+    pub description: Option<String>,
+    pub data: Option<String>,
+    pub(crate) source: Option<Errs>,
+}
+
+pub type Result<T, E = JwtErr> = core::result::Result<T, E>;
+
+/// What a `JwtErr` was made from, if not just a message
+#[derive(Debug)]
+pub(crate) enum Errs {
+    Json(serde_json::Error),
+    #[cfg(feature = "openssl")]
+    OpenSsl(openssl::error::ErrorStack),
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Validation(ValidationError),
+}
+
+impl fmt::Display for JwtErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.description.as_ref() {
+            Some(err) => write!(f, "{}", err),
+            None => write!(f, "An unknown error has occurred!"),
+        }
+    }
+}
+
+impl error::Error for JwtErr {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.source.as_ref()? {
+            Errs::Json(err) => Some(err),
+            #[cfg(feature = "openssl")]
+            Errs::OpenSsl(err) => Some(err),
+            #[cfg(feature = "std")]
+            Errs::Io(err) => Some(err),
+            Errs::Validation(_) => None,
+        }
+    }
+}
+
+impl From<&str> for JwtErr {
+    fn from(description: &str) -> Self {
+        JwtErr {
+            description: Some(description.to_string()),
+            data: None,
+            source: None,
+        }
+    }
+}
+
+macro_rules! from {
+    ($($variant:ident($err:ty) => $description:literal),* $(,)?) => {
+        $(
+            // The message of the converted error is left to `source`
+            impl From<$err> for JwtErr {
+                fn from(err: $err) -> Self {
+                    JwtErr {
+                        description: Some($description.to_string()),
+                        data: None,
+                        source: Some(Errs::$variant(err)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from!(Json(serde_json::Error) => "JSON error");
+
+impl From<ValidationError> for JwtErr {
+    fn from(err: ValidationError) -> Self {
+        JwtErr {
+            description: Some(err.to_string()),
+            data: None,
+            source: Some(Errs::Validation(err)),
+        }
+    }
+}
+
+// Decoding errors have no cause to keep, and only implement `Error` with `std`
+impl From<base64::DecodeError> for JwtErr {
+    fn from(err: base64::DecodeError) -> Self {
+        JwtErr::from(err.to_string().as_str())
+    }
+}
+
+#[cfg(feature = "std")]
+from!(Io(io::Error) => "I/O error");
+
+#[cfg(feature = "openssl")]
+from!(OpenSsl(openssl::error::ErrorStack) => "OpenSSL error");
+
+impl error::Error for ValidationError {}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_error_source() {
+    use std::error::Error;
+
+    #[derive(Debug)]
+    struct Unplugged;
+    impl fmt::Display for Unplugged {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "disk unplugged")
+        }
+    }
+    impl Error for Unplugged {}
+    #[derive(Debug)]
+    struct ReadFailed(Unplugged);
+    impl fmt::Display for ReadFailed {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "read failed")
+        }
+    }
+    impl Error for ReadFailed {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    let err = JwtErr::from(io::Error::other(ReadFailed(Unplugged)));
+    assert_eq!(err.to_string(), "I/O error");
+    let io = err.source().unwrap();
+    assert!(io.downcast_ref::<io::Error>().is_some());
+    assert_eq!(io.to_string(), "read failed");
+    let cause = io.source().unwrap();
+    assert!(cause.downcast_ref::<Unplugged>().is_some());
+    assert!(cause.source().is_none());
+
+    let err = JwtErr::from(serde_json::from_str::<u8>("").unwrap_err());
+    assert_eq!(err.to_string(), "JSON error");
+    let json = err.source().unwrap();
+    assert!(json.downcast_ref::<serde_json::Error>().is_some());
+    assert_eq!(
+        json.to_string(),
+        "EOF while parsing a value at line 1 column 0"
+    );
+    let err = JwtErr::from(ValidationError::InvalidSignature);
+    assert!(err.source().is_none());
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::InvalidSignature)
+    );
+}
@@ -0,0 +1,239 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::digest;
+use crate::{ec, http, Algorithm, JwtErr, SigningKey};
+
+type TokenSource = Arc<dyn Fn() -> Result<String, JwtErr> + Send + Sync>;
+
+/// Google Cloud KMS asymmetric key version, every signature is an `asymmetricSign` call
+///
+/// The input is hashed locally and sent as a digest. `access_token` is asked for an OAuth2 token
+/// with the `cloudkms` scope before each call, so it can cache and refresh tokens as it sees fit.
+/// The key version's resource name is reported as the `kid`.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, GcpKmsKey, Jwt};
+///
+/// let key = GcpKmsKey::new(
+///     "projects/p/locations/global/keyRings/jwt/cryptoKeys/signing/cryptoKeyVersions/1",
+///     Algorithm::ES256,
+///     || Ok(std::env::var("ACCESS_TOKEN").unwrap()),
+/// )
+/// .unwrap();
+/// let token = Jwt::new("claims", key, None).finalize().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct GcpKmsKey {
+    name: String,
+    algorithm: Algorithm,
+    endpoint: String,
+    access_token: TokenSource,
+    timeout: Duration,
+}
+
+impl GcpKmsKey {
+    /// `name` is the full `projects/*/locations/*/keyRings/*/cryptoKeys/*/cryptoKeyVersions/*`
+    /// resource name, `algorithm` has to be the one the key version was created for
+    pub fn new<F>(name: &str, algorithm: Algorithm, access_token: F) -> Result<Self, JwtErr>
+    where
+        F: Fn() -> Result<String, JwtErr> + Send + Sync + 'static,
+    {
+        digest_name(&algorithm)?;
+        let segments: Vec<&str> = name.split('/').collect();
+        let expected = [
+            "projects",
+            "locations",
+            "keyRings",
+            "cryptoKeys",
+            "cryptoKeyVersions",
+        ];
+        let valid = segments.len() == 10
+            && segments
+                .chunks(2)
+                .zip(expected.iter())
+                .all(|(pair, kind)| pair[0] == *kind && !pair[1].is_empty());
+        if !valid {
+            return Err(JwtErr::from(
+                format!("Expected a KMS key version resource name, got {}", name).as_str(),
+            ));
+        }
+        Ok(GcpKmsKey {
+            name: name.to_string(),
+            algorithm,
+            endpoint: "https://cloudkms.googleapis.com".to_string(),
+            access_token: Arc::new(access_token),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Sends requests to `url` instead of `https://cloudkms.googleapis.com`, e.g. a private endpoint
+    pub fn with_endpoint(mut self, url: &str) -> Self {
+        self.endpoint = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Connect, read and write timeout for each `asymmetricSign` call, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn asymmetric_sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let mut hashed = serde_json::Map::new();
+        hashed.insert(
+            digest_name(algo)?.to_string(),
+            STANDARD.encode(digest(algo, input)?).into(),
+        );
+        let body = serde_json::json!({ "digest": hashed }).to_string();
+        let authorization = format!("Bearer {}", (self.access_token)()?);
+        let response = http::request(
+            "POST",
+            &format!("{}/v1/{}:asymmetricSign", self.endpoint, self.name),
+            &[
+                ("Authorization", &authorization),
+                ("Content-Type", "application/json"),
+            ],
+            body.as_bytes(),
+            self.timeout,
+        )?;
+        if response.status != 200 {
+            let json: serde_json::Value =
+                serde_json::from_slice(&response.body).unwrap_or_default();
+            return Err(JwtErr::from(
+                format!(
+                    "asymmetricSign failed with status {}: {}",
+                    response.status,
+                    json["error"]["message"].as_str().unwrap_or_default()
+                )
+                .as_str(),
+            ));
+        }
+        let json: serde_json::Value = serde_json::from_slice(&response.body)?;
+        let signature = json["signature"]
+            .as_str()
+            .ok_or_else(|| JwtErr::from("asymmetricSign response holds no signature"))?;
+        Ok(STANDARD.decode(signature)?)
+    }
+}
+
+impl SigningKey for GcpKmsKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let signature = self.asymmetric_sign(algo, input)?;
+        match *algo {
+            // Cloud KMS returns ECDSA signatures DER encoded
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES256K => {
+                ec::der_to_jws(&signature, algo)
+            }
+            _ => Ok(signature),
+        }
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+/// Runs `sign` on a helper thread so async tasks are not blocked
+#[cfg(feature = "async")]
+impl crate::AsyncSigningKey for GcpKmsKey {
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> crate::SignFuture<'a> {
+        let key = self.clone();
+        let (algo, input) = (*algo, input.to_vec());
+        Box::pin(crate::async_jwks::unblock(move || key.sign(&algo, &input)))
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        SigningKey::kid(self)
+    }
+}
+
+/// Member of the KMS `Digest` message, Cloud KMS has no P-521 keys so ES512 is not supported
+fn digest_name(algo: &Algorithm) -> Result<&'static str, JwtErr> {
+    Ok(match *algo {
+        Algorithm::RS256 | Algorithm::PS256 | Algorithm::ES256 | Algorithm::ES256K => "sha256",
+        Algorithm::ES384 => "sha384",
+        Algorithm::RS512 | Algorithm::PS512 => "sha512",
+        _ => {
+            return Err(JwtErr::from(
+                format!("{} is not supported by Cloud KMS", algo).as_str(),
+            ))
+        }
+    })
+}
+
+#[test]
+fn test_gcp_kms_key() {
+    use crate::{EcKey, VerifyingKey};
+    use openssl::sign::Signer;
+    use std::sync::Mutex;
+
+    let name = "projects/p/locations/global/keyRings/jwt/cryptoKeys/signing/cryptoKeyVersions/3";
+    let token = || Ok("token".to_string());
+    assert!(GcpKmsKey::new("projects/p/locations/global", Algorithm::ES256, token).is_err());
+    assert!(GcpKmsKey::new(
+        "projects/p/locations/global/keyRings/jwt/cryptoKeys/signing/versions/3",
+        Algorithm::ES256,
+        token
+    )
+    .is_err());
+    assert!(GcpKmsKey::new(name, Algorithm::ES512, token).is_err());
+    assert!(GcpKmsKey::new(name, Algorithm::HS256, token).is_err());
+
+    let local = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let pkey = crate::read_keyfile("random_ec_for_testing").unwrap();
+    let mut signer = Signer::new(openssl::hash::MessageDigest::sha256(), &pkey).unwrap();
+    signer.update(b"input").unwrap();
+    let der = signer.sign_to_vec().unwrap();
+    let body = Arc::new(Mutex::new(
+        serde_json::json!({"name": name, "signature": STANDARD.encode(der)}).to_string(),
+    ));
+    let (url, hits) = http::serve(body.clone());
+    let key = GcpKmsKey::new(name, Algorithm::ES256, token)
+        .unwrap()
+        .with_endpoint(&url);
+    assert_eq!(key.kid(), Some(name));
+    assert_eq!(key.default_algorithm(), Algorithm::ES256);
+    let signature = key.sign(&Algorithm::ES256, b"input").unwrap();
+    assert!(local
+        .verify(&Algorithm::ES256, b"input", &signature)
+        .unwrap());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let failing = GcpKmsKey::new(name, Algorithm::ES256, || {
+        Err(JwtErr::from("no credentials"))
+    })
+    .unwrap()
+    .with_endpoint(&url);
+    assert_eq!(
+        failing
+            .sign(&Algorithm::ES256, b"input")
+            .err()
+            .unwrap()
+            .to_string(),
+        "no credentials"
+    );
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let page = Arc::new(Mutex::new("<html>Bad Gateway</html>".to_string()));
+    let (url, _) = http::serve_status("502 Bad Gateway", page);
+    let key = key.with_endpoint(&url);
+    let err = key.sign(&Algorithm::ES256, b"input").unwrap_err();
+    assert!(err.to_string().contains("status 502"), "{}", err);
+}
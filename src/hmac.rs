@@ -0,0 +1,223 @@
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine as _};
+use std::env;
+
+use crate::backend::{constant_time_eq, digest_len, hmac, HmacWriter};
+use crate::secret::wipe;
+use crate::stream::Incremental;
+use crate::{
+    Algorithm, IncrementalSigner, Jwk, JwtErr, SigningContext, SigningKey, VerifyingKey,
+    URL_SAFE_LENIENT,
+};
+
+const STANDARD_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Shared secret used with the HMAC based `HS*` algorithms
+///
+/// RFC 7518 requires the secret to be at least as long as the hash output, so every constructor
+/// rejects secrets shorter than the 32 bytes needed for HS256, and signing with HS384 or HS512
+/// fails for secrets shorter than 48 or 64 bytes. The secret is wiped from memory on drop.
+///
+/// Verification recomputes the MAC and compares it to the token's in constant time, never with
+/// `==`, so how long a forged signature takes to reject does not tell which byte was wrong.
+pub struct HmacKey {
+    secret: Vec<u8>,
+}
+
+impl HmacKey {
+    pub fn from_bytes(secret: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_vec(secret.to_vec())
+    }
+
+    /// Decodes a base64 secret, both the standard and URL safe alphabets are accepted, padded or not
+    pub fn from_base64(secret: &str) -> Result<Self, JwtErr> {
+        let decoded = match STANDARD_LENIENT.decode(secret.trim()) {
+            Ok(decoded) => decoded,
+            Err(_) => URL_SAFE_LENIENT.decode(secret.trim())?,
+        };
+        Self::from_vec(decoded)
+    }
+
+    /// Uses the raw bytes of the environment variable `name` as the secret
+    pub fn from_env(name: &str) -> Result<Self, JwtErr> {
+        match env::var(name) {
+            Ok(secret) => Self::from_vec(secret.into_bytes()),
+            Err(_) => Err(JwtErr::from(
+                format!(
+                    "Environment variable {} is not set or not valid unicode",
+                    name
+                )
+                .as_str(),
+            )),
+        }
+    }
+
+    /// The secret as an `oct` JWK, keep it secret
+    pub fn to_jwk(&self) -> Jwk {
+        Jwk::oct(&self.secret)
+    }
+
+    fn from_vec(secret: Vec<u8>) -> Result<Self, JwtErr> {
+        // Wrap first so a rejected secret is wiped as well
+        let key = HmacKey { secret };
+        key.check_len(&Algorithm::HS256)?;
+        Ok(key)
+    }
+
+    fn check_len(&self, algo: &Algorithm) -> Result<(), JwtErr> {
+        let min = digest_len(algo);
+        if self.secret.len() < min {
+            return Err(JwtErr::from(
+                format!("{} requires a secret of at least {} bytes", algo, min).as_str(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn mac(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        self.check_len(algo)?;
+        hmac(algo, &self.secret, input)
+    }
+}
+
+impl Drop for HmacKey {
+    fn drop(&mut self) {
+        wipe(&mut self.secret);
+    }
+}
+
+impl SigningKey for HmacKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        check_algorithm(algo)?;
+        self.mac(algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        Algorithm::HS256
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        check_algorithm(algo)?;
+        self.check_len(algo)?;
+        Ok(Some(Box::new(Incremental::new(
+            HmacWriter::new(algo, &self.secret)?,
+            HmacWriter::finish,
+        ))))
+    }
+
+    fn signing_context(&self, algo: &Algorithm) -> Result<Option<Box<dyn SigningContext>>, JwtErr> {
+        check_algorithm(algo)?;
+        self.check_len(algo)?;
+        Ok(Some(Box::new(HmacWriter::new(algo, &self.secret)?)))
+    }
+}
+
+/// The secret is hashed into the padded blocks once, each token only clones their state
+impl SigningContext for HmacWriter {
+    fn sign(&self, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        self.mac(input)
+    }
+}
+
+fn check_algorithm(algo: &Algorithm) -> Result<(), JwtErr> {
+    match *algo {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Ok(()),
+        _ => Err(JwtErr::from(
+            format!("{} can not be used with an HMAC key", algo).as_str(),
+        )),
+    }
+}
+
+impl VerifyingKey for HmacKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        let expected = self.sign(algo, input)?;
+        // The MAC length is public, the comparison of its contents must not leak timing
+        Ok(constant_time_eq(&expected, signature))
+    }
+}
+
+#[test]
+fn test_hmac_verify() {
+    let key = HmacKey::from_bytes(&[7; 64]).unwrap();
+    for algo in [Algorithm::HS256, Algorithm::HS512] {
+        let mac = key.sign(&algo, b"input").unwrap();
+        assert!(key.verify(&algo, b"input", &mac).unwrap());
+        for at in [0, mac.len() / 2, mac.len() - 1] {
+            let mut forged = mac.clone();
+            forged[at] ^= 1;
+            assert!(!key.verify(&algo, b"input", &forged).unwrap());
+        }
+        assert!(!key.verify(&algo, b"input", &mac[..mac.len() - 1]).unwrap());
+        assert!(!key
+            .verify(&algo, b"input", &[mac.clone(), vec![0]].concat())
+            .unwrap());
+        assert!(!key.verify(&algo, b"input", &[]).unwrap());
+    }
+    assert!(key.verify(&Algorithm::RS256, b"input", b"").is_err());
+}
+
+#[test]
+fn test_hmac_key_length() {
+    assert!(HmacKey::from_bytes(b"").is_err());
+    assert!(HmacKey::from_bytes(&[7; 31]).is_err());
+
+    let key = HmacKey::from_bytes(&[7; 32]).unwrap();
+    assert!(key.sign(&Algorithm::HS256, b"input").is_ok());
+    assert!(key.sign(&Algorithm::HS384, b"input").is_err());
+
+    let key = HmacKey::from_bytes(&[7; 64]).unwrap();
+    assert_eq!(key.sign(&Algorithm::HS384, b"input").unwrap().len(), 48);
+    assert_eq!(key.sign(&Algorithm::HS512, b"input").unwrap().len(), 64);
+    assert!(key.sign(&Algorithm::RS256, b"input").is_err());
+}
+
+#[test]
+fn test_hmac_key_constructors() {
+    let raw = [0xfbu8; 33];
+    let expected = HmacKey::from_bytes(&raw)
+        .unwrap()
+        .sign(&Algorithm::HS256, b"input")
+        .unwrap();
+
+    for encoded in [
+        base64::engine::general_purpose::STANDARD.encode(raw),
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw),
+    ] {
+        let key = HmacKey::from_base64(&encoded).unwrap();
+        assert_eq!(key.sign(&Algorithm::HS256, b"input").unwrap(), expected);
+    }
+    assert!(HmacKey::from_base64("not base64!").is_err());
+    assert!(HmacKey::from_base64("c2hvcnQ=").is_err());
+
+    env::set_var(
+        "SMPL_JWT_TEST_HMAC_SECRET",
+        "secret-of-at-least-thirty-two-bytes",
+    );
+    let key = HmacKey::from_env("SMPL_JWT_TEST_HMAC_SECRET").unwrap();
+    assert_eq!(key.secret, b"secret-of-at-least-thirty-two-bytes");
+    assert!(HmacKey::from_env("SMPL_JWT_TEST_HMAC_SECRET_UNSET").is_err());
+}
+
+#[test]
+fn test_hmac_signing_context() {
+    let key = HmacKey::from_bytes(&[0xa5; 200]).unwrap();
+    for algo in [Algorithm::HS256, Algorithm::HS384, Algorithm::HS512] {
+        let context = key.signing_context(&algo).unwrap().unwrap();
+        for input in [&b""[..], b"input", &[1; 300]] {
+            assert_eq!(
+                context.sign(input).unwrap(),
+                key.sign(&algo, input).unwrap()
+            );
+        }
+    }
+    assert!(key.signing_context(&Algorithm::RS256).is_err());
+    let short = HmacKey::from_bytes(&[1; 32]).unwrap();
+    assert!(short.signing_context(&Algorithm::HS512).is_err());
+}
@@ -0,0 +1,370 @@
+use openssl::ssl::{SslConnector, SslMethod};
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::JwtErr;
+
+/// Upper bound for response bodies, key sets and KMS responses are a few kilobytes at most
+const MAX_RESPONSE: u64 = 1024 * 1024;
+
+/// Parsed `http(s)://host[:port]/path` URL
+pub(crate) struct Url {
+    pub(crate) tls: bool,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) path: String,
+}
+
+impl Url {
+    /// Plain `http` is only accepted for loopback hosts, keys fetched in the clear can be swapped
+    ///
+    /// The path goes into the request line as is, so anything but visible ASCII is refused.
+    /// A `#fragment` is never sent and is dropped.
+    pub(crate) fn parse(url: &str) -> Result<Url, JwtErr> {
+        if !url.bytes().all(|byte| (0x21..=0x7e).contains(&byte)) {
+            return Err(JwtErr::from(
+                "URLs may only contain visible ASCII characters",
+            ));
+        }
+        let url = url.split('#').next().unwrap_or(url);
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(JwtErr::from(
+                format!("Unsupported URL {}, expected https", url).as_str(),
+            ));
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(idx) if rest[idx..].starts_with('?') => {
+                (&rest[..idx], format!("/{}", &rest[idx..]))
+            }
+            Some(idx) => (&rest[..idx], rest[idx..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        if authority.contains('@') {
+            return Err(JwtErr::from("URLs with credentials are not supported"));
+        }
+        // A colon inside the brackets of an IPv6 literal is not a port separator
+        let (host, port) = match authority.rfind(':') {
+            Some(idx) if !authority[idx..].contains(']') => {
+                let port = authority[idx + 1..]
+                    .parse()
+                    .map_err(|_| JwtErr::from(format!("Invalid port in URL {}", url).as_str()))?;
+                (&authority[..idx], port)
+            }
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(JwtErr::from(
+                format!("Missing host in URL {}", url).as_str(),
+            ));
+        }
+        let host = host.to_ascii_lowercase();
+        if !tls && !is_loopback(&host) {
+            return Err(JwtErr::from(
+                format!("Refusing plain http for {}, use https", host).as_str(),
+            ));
+        }
+        Ok(Url {
+            tls,
+            host,
+            port,
+            path,
+        })
+    }
+
+    /// `host[:port]` as sent in the `Host` header, the port only when it is not the default
+    pub(crate) fn authority(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            (_, port) => format!("{}:{}", self.host, port),
+        }
+    }
+}
+
+/// `localhost` or a loopback IP literal, names like `127.example.com` resolve anywhere
+fn is_loopback(host: &str) -> bool {
+    let ip = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    host == "localhost" || ip.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Minimal blocking HTTP/1.1 client, one request per connection and no redirects
+pub(crate) fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<Response, JwtErr> {
+    let url = Url::parse(url)?;
+    // A line break in a name or value, e.g. a session token read from the environment, would
+    // start a header line of its own
+    if let Some((name, _)) = headers
+        .iter()
+        .find(|(name, value)| name.contains(['\r', '\n']) || value.contains(['\r', '\n']))
+    {
+        return Err(JwtErr::from(
+            format!("HTTP header {:?} contains a line break", name).as_str(),
+        ));
+    }
+    let addr = (
+        url.host.trim_start_matches('[').trim_end_matches(']'),
+        url.port,
+    )
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| JwtErr::from(format!("Could not resolve {}", url.host).as_str()))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let host = url.authority();
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: smpl_jwt\r\nContent-Length: {}\r\n",
+        method,
+        url.path,
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let mut raw = Vec::new();
+    if url.tls {
+        // Verifies the certificate chain against the system roots and the host name
+        let connector = SslConnector::builder(SslMethod::tls_client())?.build();
+        let mut stream = connector.connect(&url.host, stream).map_err(|e| {
+            JwtErr::from(format!("TLS handshake with {} failed: {}", url.host, e).as_str())
+        })?;
+        exchange(&mut stream, head.as_bytes(), body, &mut raw)?;
+    } else {
+        let mut stream = stream;
+        exchange(&mut stream, head.as_bytes(), body, &mut raw)?;
+    }
+    parse_response(&raw)
+}
+
+fn exchange<S: Read + Write>(
+    stream: &mut S,
+    head: &[u8],
+    body: &[u8],
+    raw: &mut Vec<u8>,
+) -> Result<(), JwtErr> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    stream.take(MAX_RESPONSE + 1).read_to_end(raw)?;
+    if raw.len() as u64 > MAX_RESPONSE {
+        return Err(JwtErr::from("HTTP response is too large"));
+    }
+    Ok(())
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, JwtErr> {
+    let malformed = || JwtErr::from("Malformed HTTP response");
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| malformed())?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(malformed)?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+
+    let rest = &raw[end + 4..];
+    response.body = if response
+        .header("Transfer-Encoding")
+        .is_some_and(|te| te.eq_ignore_ascii_case("chunked"))
+    {
+        dechunk(rest).ok_or_else(malformed)?
+    } else if let Some(len) = response.header("Content-Length") {
+        let len: usize = len.parse().map_err(|_| malformed())?;
+        rest.get(..len).ok_or_else(malformed)?.to_vec()
+    } else {
+        rest.to_vec()
+    };
+    Ok(response)
+}
+
+fn dechunk(mut rest: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&rest[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+}
+
+#[test]
+fn test_parse_url() {
+    let url = Url::parse("https://Example.com/.well-known/jwks.json").unwrap();
+    assert!(url.tls);
+    assert_eq!(url.host, "example.com");
+    assert_eq!(url.port, 443);
+    assert_eq!(url.path, "/.well-known/jwks.json");
+
+    let url = Url::parse("http://127.0.0.1:8080?x=1").unwrap();
+    assert!(!url.tls);
+    assert_eq!(url.port, 8080);
+    assert_eq!(url.path, "/?x=1");
+
+    assert_eq!(Url::parse("https://[::1]:8443/").unwrap().port, 8443);
+    assert_eq!(Url::parse("http://[::1]/").unwrap().host, "[::1]");
+    assert!(Url::parse("http://example.com/jwks").is_err());
+    assert!(Url::parse("http://127.1.2.3/").is_ok());
+    assert!(Url::parse("http://localhost:8080/").is_ok());
+    assert!(Url::parse("http://127.evil.com/").is_err());
+    assert!(Url::parse("http://127.0.0.1.evil.com/").is_err());
+    assert!(Url::parse("http://[::2]/").is_err());
+    assert!(Url::parse("ftp://example.com/jwks").is_err());
+    assert!(Url::parse("https://user@example.com/jwks").is_err());
+    assert!(Url::parse("https://example.com:port/").is_err());
+
+    assert_eq!(Url::parse("https://example.com/a#b?c").unwrap().path, "/a");
+    assert_eq!(Url::parse("https://example.com#/a").unwrap().path, "/");
+    for url in [
+        "https://example.com/x HTTP/1.1\r\nX: y\r\n\r\nGET /admin",
+        "https://example.com/\r\nX: y",
+        "https://example.com/\n",
+        "https://example.com/\t",
+        "https://example.com/caf\u{e9}",
+        "https://example.com/\x7f",
+    ] {
+        assert!(Url::parse(url).is_err(), "{:?}", url);
+    }
+}
+
+#[test]
+fn test_parse_response() {
+    let response =
+        parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nX-Test: a\r\n\r\n{}trailing")
+            .unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("x-test"), Some("a"));
+    assert_eq!(response.body, b"{}");
+
+    let response = parse_response(
+        b"HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(response.status, 404);
+    assert_eq!(response.body, b"abcde");
+
+    assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{}").is_err());
+    assert!(parse_response(b"garbage").is_err());
+}
+
+/// Serves `body` to every request on a loopback port, counting the requests
+#[cfg(test)]
+pub(crate) fn serve(
+    body: std::sync::Arc<std::sync::Mutex<String>>,
+) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    serve_status("200 OK", body)
+}
+
+/// Like `serve`, but answers with the given status line
+#[cfg(test)]
+pub(crate) fn serve_status(
+    status: &'static str,
+    body: std::sync::Arc<std::sync::Mutex<String>>,
+) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = body.lock().unwrap().clone();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+    (url, hits)
+}
+
+#[test]
+fn test_request() {
+    use std::sync::{Arc, Mutex};
+
+    let (url, hits) = serve(Arc::new(Mutex::new("{\"keys\":[]}".to_string())));
+    let response = request(
+        "GET",
+        &url,
+        &[("Accept", "application/json")],
+        b"",
+        Duration::from_secs(5),
+    )
+    .unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    assert_eq!(response.body, b"{\"keys\":[]}");
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    for header in [
+        ("X-Vault-Namespace", "ns\r\nX-Injected: 1"),
+        ("X-Amz-Security-Token", "token\n"),
+        ("X-Injected: 1\r\nAccept", "*/*"),
+    ] {
+        assert!(request("GET", &url, &[header], b"", Duration::from_secs(5)).is_err());
+    }
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
@@ -0,0 +1,1142 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use openssl::derive::Deriver;
+use openssl::encrypt::{Decrypter, Encrypter};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{HasPublic, PKeyRef, Private};
+use openssl::rsa::Padding;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::backend::{
+    aes_gcm_decrypt, aes_gcm_encrypt, aes_key_unwrap, aes_key_wrap, random_bytes, sha256,
+};
+use crate::deflate::{compress, decompress};
+use crate::secret::{wipe, Secret};
+use crate::{
+    Algorithm, DecodedJwt, EcCurve, EcKey, EcPublicKey, Jwk, JwkKey, Jwt, JwtErr, RSAKey,
+    RSAPublicKey, SigningKey, ValidationOptions, VerifyingKey, URL_SAFE_LENIENT,
+};
+
+/// Largest plaintext `Jwe::decrypt` inflates a `zip: DEF` token to, 1 MiB
+pub const MAX_DECOMPRESSED_LEN: usize = 1 << 20;
+
+/// Key management algorithm of a JWE, how the content encryption key reaches the recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    /// RSAES-OAEP with SHA-1, `RSA-OAEP`
+    RsaOaep,
+    /// RSAES-OAEP with SHA-256 and MGF1 with SHA-256, `RSA-OAEP-256`
+    RsaOaep256,
+    /// The shared key is the content encryption key, `dir`
+    Dir,
+    /// The content encryption key is agreed with ECDH on an ephemeral key, `ECDH-ES`
+    EcdhEs,
+    /// The key agreed with ECDH wraps the content encryption key with AES-128, `ECDH-ES+A128KW`
+    EcdhEsA128kw,
+    /// Like `EcdhEsA128kw` with AES-192, `ECDH-ES+A192KW`
+    EcdhEsA192kw,
+    /// Like `EcdhEsA128kw` with AES-256, `ECDH-ES+A256KW`
+    EcdhEsA256kw,
+}
+
+impl KeyAlgorithm {
+    fn from_name(name: &str) -> Option<KeyAlgorithm> {
+        match name {
+            "RSA-OAEP" => Some(KeyAlgorithm::RsaOaep),
+            "RSA-OAEP-256" => Some(KeyAlgorithm::RsaOaep256),
+            "dir" => Some(KeyAlgorithm::Dir),
+            "ECDH-ES" => Some(KeyAlgorithm::EcdhEs),
+            "ECDH-ES+A128KW" => Some(KeyAlgorithm::EcdhEsA128kw),
+            "ECDH-ES+A192KW" => Some(KeyAlgorithm::EcdhEsA192kw),
+            "ECDH-ES+A256KW" => Some(KeyAlgorithm::EcdhEsA256kw),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyAlgorithm::RsaOaep => write!(f, "RSA-OAEP"),
+            KeyAlgorithm::RsaOaep256 => write!(f, "RSA-OAEP-256"),
+            KeyAlgorithm::Dir => write!(f, "dir"),
+            KeyAlgorithm::EcdhEs => write!(f, "ECDH-ES"),
+            KeyAlgorithm::EcdhEsA128kw => write!(f, "ECDH-ES+A128KW"),
+            KeyAlgorithm::EcdhEsA192kw => write!(f, "ECDH-ES+A192KW"),
+            KeyAlgorithm::EcdhEsA256kw => write!(f, "ECDH-ES+A256KW"),
+        }
+    }
+}
+
+/// Content encryption algorithm of a JWE, AES in Galois/Counter Mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncryption {
+    A128GCM,
+    A192GCM,
+    A256GCM,
+}
+
+impl ContentEncryption {
+    fn from_name(name: &str) -> Option<ContentEncryption> {
+        match name {
+            "A128GCM" => Some(ContentEncryption::A128GCM),
+            "A192GCM" => Some(ContentEncryption::A192GCM),
+            "A256GCM" => Some(ContentEncryption::A256GCM),
+            _ => None,
+        }
+    }
+
+    /// Length of the content encryption key in bytes
+    pub fn key_len(&self) -> usize {
+        match *self {
+            ContentEncryption::A128GCM => 16,
+            ContentEncryption::A192GCM => 24,
+            ContentEncryption::A256GCM => 32,
+        }
+    }
+
+    /// Fresh random content encryption key, for `EncryptingKey` implementations that wrap one
+    pub fn generate_key(&self) -> Result<Vec<u8>, JwtErr> {
+        let mut cek = vec![0; self.key_len()];
+        random_bytes(&mut cek)?;
+        Ok(cek)
+    }
+}
+
+impl fmt::Display for ContentEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentEncryption::A128GCM => write!(f, "A128GCM"),
+            ContentEncryption::A192GCM => write!(f, "A192GCM"),
+            ContentEncryption::A256GCM => write!(f, "A256GCM"),
+        }
+    }
+}
+
+/// Protected header of a JWE, the whole header is integrity protected by the content encryption
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JweHeader {
+    alg: String,
+    enc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crit: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    epk: Option<Jwk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    apu: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    apv: Option<String>,
+    #[serde(flatten)]
+    params: BTreeMap<String, serde_json::Value>,
+}
+
+impl JweHeader {
+    pub fn alg(&self) -> &str {
+        &self.alg
+    }
+
+    pub fn enc(&self) -> &str {
+        &self.enc
+    }
+
+    /// Compression applied to the plaintext before encryption, `DEF` for DEFLATE
+    pub fn zip(&self) -> Option<&str> {
+        self.zip.as_deref()
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn typ(&self) -> Option<&str> {
+        self.typ.as_deref()
+    }
+
+    /// Content type of the plaintext, `JWT` for an encrypted signed token
+    pub fn cty(&self) -> Option<&str> {
+        self.cty.as_deref()
+    }
+
+    /// Ephemeral public key of an `ECDH-ES` token
+    pub fn epk(&self) -> Option<&Jwk> {
+        self.epk.as_ref()
+    }
+
+    /// Agreement PartyUInfo of an `ECDH-ES` token, usually naming the producer
+    pub fn apu(&self) -> Result<Option<Vec<u8>>, JwtErr> {
+        Ok(self
+            .apu
+            .as_deref()
+            .map(|apu| URL_SAFE_LENIENT.decode(apu))
+            .transpose()?)
+    }
+
+    /// Agreement PartyVInfo of an `ECDH-ES` token, usually naming the recipient
+    pub fn apv(&self) -> Result<Option<Vec<u8>>, JwtErr> {
+        Ok(self
+            .apv
+            .as_deref()
+            .map(|apv| URL_SAFE_LENIENT.decode(apv))
+            .transpose()?)
+    }
+
+    /// Header member not modelled by `JweHeader`
+    pub fn param(&self, name: &str) -> Option<&serde_json::Value> {
+        self.params.get(name)
+    }
+}
+
+/// Recipient key a `Jwe` is encrypted to, implemented by the RSA and EC keys and `AesKey`
+pub trait EncryptingKey {
+    /// Produces the content encryption key for `enc` and its encrypted form for the token
+    ///
+    /// Members the algorithm needs the recipient to see can be added to `header`.
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr>;
+
+    /// Key management algorithm `Jwe::new` uses when not given one
+    fn default_key_algorithm(&self) -> KeyAlgorithm;
+
+    /// Identifier of the key, if it has one
+    fn kid(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<K: EncryptingKey + ?Sized> EncryptingKey for &K {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        (**self).encrypt_key(alg, enc, header)
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        (**self).default_key_algorithm()
+    }
+
+    fn kid(&self) -> Option<&str> {
+        (**self).kid()
+    }
+}
+
+/// Private key a `Jwe` is decrypted with, implemented by `RSAKey`, `EcKey` and `AesKey`
+pub trait DecryptingKey {
+    /// Recovers the content encryption key from the token's `encrypted_key` and `header`
+    fn decrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &JweHeader,
+        encrypted_key: &[u8],
+    ) -> Result<Vec<u8>, JwtErr>;
+}
+
+/// Encrypted token, RFC 7516 JWE in the compact serialization
+///
+/// The plaintext is encrypted with a fresh content encryption key, which in turn is encrypted to
+/// the recipient's key. Encrypt a signed token to keep its claims confidential.
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{Jwe, RSAKey};
+///
+/// let key = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+/// let token = Jwe::new(b"secret", key.public_key().unwrap(), None)
+///     .finalize()
+///     .unwrap();
+/// assert_eq!(token.split('.').count(), 5);
+///
+/// let decrypted = Jwe::decrypt(&token, &key).unwrap();
+/// assert_eq!(decrypted.header().alg(), "RSA-OAEP-256");
+/// assert_eq!(decrypted.plaintext(), b"secret");
+/// ```
+pub struct Jwe<K = RSAPublicKey> {
+    plaintext: Vec<u8>,
+    key: K,
+    alg: KeyAlgorithm,
+    enc: ContentEncryption,
+    // `alg` and `enc` are filled in when encrypting
+    header: JweHeader,
+}
+
+impl<K: EncryptingKey> Jwe<K> {
+    /// Without an explicit `alg` the key's `default_key_algorithm` is used, content is
+    /// encrypted with A256GCM unless `with_enc` says otherwise
+    pub fn new(plaintext: &[u8], key: K, alg: Option<KeyAlgorithm>) -> Jwe<K> {
+        Jwe {
+            plaintext: plaintext.to_vec(),
+            alg: alg.unwrap_or_else(|| key.default_key_algorithm()),
+            key,
+            enc: ContentEncryption::A256GCM,
+            header: JweHeader {
+                alg: String::new(),
+                enc: String::new(),
+                zip: None,
+                kid: None,
+                typ: None,
+                cty: None,
+                crit: None,
+                epk: None,
+                apu: None,
+                apv: None,
+                params: BTreeMap::new(),
+            },
+        }
+    }
+
+    pub fn with_enc(mut self, enc: ContentEncryption) -> Self {
+        self.enc = enc;
+        self
+    }
+
+    /// Sets the `kid` header, overriding the one reported by the key
+    pub fn with_kid(mut self, kid: &str) -> Self {
+        self.header.kid = Some(kid.to_string());
+        self
+    }
+
+    pub fn with_typ(mut self, typ: &str) -> Self {
+        self.header.typ = Some(typ.to_string());
+        self
+    }
+
+    /// Sets the `cty` header, `JWT` when the plaintext is a signed token
+    pub fn with_cty(mut self, cty: &str) -> Self {
+        self.header.cty = Some(cty.to_string());
+        self
+    }
+
+    /// DEFLATE compresses the plaintext before encrypting it and sets `zip: DEF`
+    ///
+    /// Compressed length depends on the content, so leave it off when an attacker can mix their
+    /// own input into plaintext that also holds secrets.
+    pub fn with_compression(mut self) -> Self {
+        self.header.zip = Some("DEF".to_string());
+        self
+    }
+
+    /// Sets `apu` and `apv`, which `ECDH-ES` mixes into the derived key so both parties have to
+    /// agree on them
+    pub fn with_party_info(mut self, apu: &[u8], apv: &[u8]) -> Self {
+        self.header.apu = Some(URL_SAFE_NO_PAD.encode(apu));
+        self.header.apv = Some(URL_SAFE_NO_PAD.encode(apv));
+        self
+    }
+
+    /// Adds the extension header member `name`, registered members have their own setters
+    pub fn with_header_param(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.header.params.insert(name.to_string(), value);
+        self
+    }
+
+    /// Encrypts the plaintext into `header.encrypted_key.iv.ciphertext.tag`
+    pub fn finalize(&self) -> Result<String, JwtErr> {
+        let mut header = self.header.clone();
+        header.alg = self.alg.to_string();
+        header.enc = self.enc.to_string();
+        if header.kid.is_none() {
+            header.kid = self.key.kid().map(String::from);
+        }
+        let (cek, encrypted_key) = self.key.encrypt_key(&self.alg, &self.enc, &mut header)?;
+        let cek = Secret::from(cek);
+        if cek.len() != self.enc.key_len() {
+            return Err(JwtErr::from(
+                format!("{} needs a {} byte key", self.enc, self.enc.key_len()).as_str(),
+            ));
+        }
+
+        let protected = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+        let mut iv = [0; 12];
+        random_bytes(&mut iv)?;
+        let plaintext = match header.zip {
+            Some(_) => compress(&self.plaintext),
+            None => self.plaintext.clone(),
+        };
+        let (ciphertext, tag) = aes_gcm_encrypt(&cek, &iv, protected.as_bytes(), &plaintext)?;
+        Ok([
+            protected,
+            URL_SAFE_NO_PAD.encode(encrypted_key),
+            URL_SAFE_NO_PAD.encode(iv),
+            URL_SAFE_NO_PAD.encode(ciphertext),
+            URL_SAFE_NO_PAD.encode(tag),
+        ]
+        .join("."))
+    }
+}
+
+impl<K: EncryptingKey> Jwe<K> {
+    /// Signs `jwt` and takes the signed token as plaintext, marked with `cty: JWT`
+    ///
+    /// Use it over `Jwt::finalize_encrypted` to pick the content encryption or set headers.
+    pub fn nested<T: Serialize, S: SigningKey, H: Serialize>(
+        jwt: &Jwt<T, S, H>,
+        key: K,
+        alg: Option<KeyAlgorithm>,
+    ) -> Result<Jwe<K>, JwtErr> {
+        Ok(Jwe::new(jwt.finalize()?.as_bytes(), key, alg).with_cty("JWT"))
+    }
+}
+
+impl<T: Serialize, S: SigningKey, H: Serialize> Jwt<T, S, H> {
+    /// Signs the token, then encrypts it to `recipient`, the nested form of an encrypted OpenID
+    /// Connect ID token
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use smpl_jwt::{Algorithm, EcCurve, EcKey, Jwt, RSAKey};
+    ///
+    /// let issuer = EcKey::generate(EcCurve::P256).unwrap();
+    /// let client = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    /// let token = Jwt::new(serde_json::json!({"sub": "me"}), &issuer, None)
+    ///     .finalize_encrypted(client.public_key().unwrap())
+    ///     .unwrap();
+    ///
+    /// let decoded = Jwt::decrypt_and_verify(&token, &client, &issuer, &[Algorithm::ES256]).unwrap();
+    /// assert_eq!(decoded.claims()["sub"], "me");
+    /// ```
+    pub fn finalize_encrypted<E: EncryptingKey>(&self, recipient: E) -> Result<String, JwtErr> {
+        Jwe::nested(self, recipient, None)?.finalize()
+    }
+}
+
+impl Jwt<serde_json::Value> {
+    /// Decrypts a nested token with the recipient's key and verifies the signed token inside
+    pub fn decrypt_and_verify<D: DecryptingKey + ?Sized, V: VerifyingKey + ?Sized>(
+        token: &str,
+        decrypting_key: &D,
+        verifying_key: &V,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        Self::decrypt_and_verify_with(
+            token,
+            decrypting_key,
+            verifying_key,
+            &ValidationOptions::new(algorithms),
+        )
+    }
+
+    /// Like `decrypt_and_verify`, validating the inner token against `options`
+    ///
+    /// Encrypted tokens without `cty: JWT` are rejected, their plaintext is not a signed token.
+    pub fn decrypt_and_verify_with<D: DecryptingKey + ?Sized, V: VerifyingKey + ?Sized>(
+        token: &str,
+        decrypting_key: &D,
+        verifying_key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let decrypted = Jwe::decrypt(token, decrypting_key)?;
+        // RFC 7519 5.2, the content type compares case insensitively
+        if !decrypted
+            .header()
+            .cty()
+            .is_some_and(|cty| cty.eq_ignore_ascii_case("JWT"))
+        {
+            return Err(JwtErr::from("Encrypted token does not hold a JWT"));
+        }
+        let inner = std::str::from_utf8(decrypted.plaintext())
+            .map_err(|_| JwtErr::from("Nested token is not valid UTF-8"))?;
+        Self::verify_with(inner, verifying_key, options)
+    }
+}
+
+/// Header and plaintext of a token that has been decrypted and its integrity checked
+#[derive(Debug)]
+pub struct DecryptedJwe {
+    header: JweHeader,
+    plaintext: Vec<u8>,
+}
+
+impl DecryptedJwe {
+    pub fn header(&self) -> &JweHeader {
+        &self.header
+    }
+
+    pub fn plaintext(&self) -> &[u8] {
+        &self.plaintext
+    }
+
+    pub fn into_plaintext(self) -> Vec<u8> {
+        self.plaintext
+    }
+}
+
+impl Jwe {
+    /// Decrypts a compact serialized token with the recipient's private key
+    ///
+    /// Algorithms are taken from the header and have to fit `key`. Tokens listing any `crit`
+    /// extension are rejected, as are tokens whose authentication tag does not match. Compressed
+    /// plaintext is inflated to at most `MAX_DECOMPRESSED_LEN` bytes.
+    pub fn decrypt<D: DecryptingKey + ?Sized>(
+        token: &str,
+        key: &D,
+    ) -> Result<DecryptedJwe, JwtErr> {
+        let segments: Vec<&str> = token.split('.').collect();
+        if segments.len() != 5 {
+            return Err(JwtErr::from(
+                "Encrypted token must consist of five segments",
+            ));
+        }
+        let header: JweHeader = serde_json::from_slice(&URL_SAFE_LENIENT.decode(segments[0])?)?;
+        let alg = KeyAlgorithm::from_name(&header.alg).ok_or_else(|| {
+            JwtErr::from(format!("Unsupported key algorithm {}", header.alg).as_str())
+        })?;
+        let enc = ContentEncryption::from_name(&header.enc).ok_or_else(|| {
+            JwtErr::from(format!("Unsupported content encryption {}", header.enc).as_str())
+        })?;
+        if let Some(zip) = header.zip.as_deref().filter(|zip| *zip != "DEF") {
+            return Err(JwtErr::from(
+                format!("Unsupported compression {}", zip).as_str(),
+            ));
+        }
+        if let Some(name) = header.crit.iter().flatten().next() {
+            return Err(JwtErr::from(
+                format!("Critical header extension {} is not understood", name).as_str(),
+            ));
+        }
+
+        let encrypted_key = URL_SAFE_LENIENT.decode(segments[1])?;
+        let iv = URL_SAFE_LENIENT.decode(segments[2])?;
+        let ciphertext = URL_SAFE_LENIENT.decode(segments[3])?;
+        let tag = URL_SAFE_LENIENT.decode(segments[4])?;
+        if iv.len() != 12 {
+            return Err(JwtErr::from(
+                "AES-GCM initialization vectors are 96 bits long",
+            ));
+        }
+        let cek = Secret::from(key.decrypt_key(&alg, &enc, &header, &encrypted_key)?);
+        if cek.len() != enc.key_len() {
+            return Err(JwtErr::from("Decryption failed"));
+        }
+        let mut plaintext = aes_gcm_decrypt(&cek, &iv, segments[0].as_bytes(), &ciphertext, &tag)?;
+        if header.zip.is_some() {
+            plaintext = decompress(&plaintext, MAX_DECOMPRESSED_LEN)?;
+        }
+        Ok(DecryptedJwe { header, plaintext })
+    }
+}
+
+fn oaep_digest(alg: &KeyAlgorithm) -> Result<MessageDigest, JwtErr> {
+    match *alg {
+        KeyAlgorithm::RsaOaep => Ok(MessageDigest::sha1()),
+        KeyAlgorithm::RsaOaep256 => Ok(MessageDigest::sha256()),
+        _ => Err(JwtErr::from(
+            format!("{} can not be used with an RSA key", alg).as_str(),
+        )),
+    }
+}
+
+fn rsa_encrypt_key<T: HasPublic>(
+    key: &PKeyRef<T>,
+    alg: &KeyAlgorithm,
+    enc: &ContentEncryption,
+) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+    let digest = oaep_digest(alg)?;
+    if key.bits() < 2048 {
+        return Err(JwtErr::from("RSA keys must be at least 2048 bits long"));
+    }
+    let cek = enc.generate_key()?;
+    let mut encrypter = Encrypter::new(key)?;
+    encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+    encrypter.set_rsa_oaep_md(digest)?;
+    encrypter.set_rsa_mgf1_md(digest)?;
+    let mut encrypted_key = vec![0; encrypter.encrypt_len(&cek)?];
+    let len = encrypter.encrypt(&cek, &mut encrypted_key)?;
+    encrypted_key.truncate(len);
+    Ok((cek, encrypted_key))
+}
+
+impl EncryptingKey for RSAPublicKey {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        _header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        rsa_encrypt_key(&self.key, alg, enc)
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::RsaOaep256
+    }
+}
+
+/// Encrypts to the public half, so a service can encrypt tokens to itself
+impl EncryptingKey for RSAKey {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        _header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        rsa_encrypt_key(&self.key, alg, enc)
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::RsaOaep256
+    }
+}
+
+impl DecryptingKey for RSAKey {
+    fn decrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        _header: &JweHeader,
+        encrypted_key: &[u8],
+    ) -> Result<Vec<u8>, JwtErr> {
+        let digest = oaep_digest(alg)?;
+        let decrypted = (|| {
+            let mut decrypter = Decrypter::new(&self.key)?;
+            decrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+            decrypter.set_rsa_oaep_md(digest)?;
+            decrypter.set_rsa_mgf1_md(digest)?;
+            let mut cek = vec![0; decrypter.decrypt_len(encrypted_key)?];
+            let len = decrypter.decrypt(encrypted_key, &mut cek)?;
+            cek.truncate(len);
+            Ok::<_, JwtErr>(cek)
+        })();
+        // RFC 7516 11.5, a failed unwrap continues with a random key so that it fails just like
+        // a wrong tag and reveals nothing about the padding
+        match decrypted {
+            Ok(cek) if cek.len() == enc.key_len() => Ok(cek),
+            _ => enc.generate_key(),
+        }
+    }
+}
+
+/// Length in bytes of the key `alg` derives, the KEK of the key wrapping variants
+fn agreed_key_len(alg: &KeyAlgorithm, enc: &ContentEncryption) -> Result<usize, JwtErr> {
+    match *alg {
+        KeyAlgorithm::EcdhEs => Ok(enc.key_len()),
+        KeyAlgorithm::EcdhEsA128kw => Ok(16),
+        KeyAlgorithm::EcdhEsA192kw => Ok(24),
+        KeyAlgorithm::EcdhEsA256kw => Ok(32),
+        _ => Err(JwtErr::from(
+            format!("{} can not be used with an EC key", alg).as_str(),
+        )),
+    }
+}
+
+/// RFC 7518 4.6.2, the Concat KDF of NIST SP 800-56A with SHA-256 over the shared secret `z`
+fn concat_kdf(
+    z: &[u8],
+    alg: &KeyAlgorithm,
+    enc: &ContentEncryption,
+    header: &JweHeader,
+) -> Result<Vec<u8>, JwtErr> {
+    let len = agreed_key_len(alg, enc)?;
+    // Direct agreement derives the content key for `enc`, the wrapping variants a key for `alg`
+    let algorithm_id = match *alg {
+        KeyAlgorithm::EcdhEs => enc.to_string(),
+        _ => alg.to_string(),
+    };
+    let mut other_info = Vec::new();
+    for field in [
+        algorithm_id.into_bytes(),
+        header.apu()?.unwrap_or_default(),
+        header.apv()?.unwrap_or_default(),
+    ] {
+        other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(&field);
+    }
+    other_info.extend_from_slice(&(len as u32 * 8).to_be_bytes());
+
+    let mut key = Vec::with_capacity(len + 32);
+    let mut counter: u32 = 1;
+    while key.len() < len {
+        let mut round = counter.to_be_bytes().to_vec();
+        round.extend_from_slice(z);
+        round.extend_from_slice(&other_info);
+        key.extend_from_slice(&sha256(&round)?);
+        wipe(&mut round);
+        counter += 1;
+    }
+    wipe(&mut key[len..]);
+    key.truncate(len);
+    Ok(key)
+}
+
+fn ecdh<T: HasPublic>(private: &PKeyRef<Private>, peer: &PKeyRef<T>) -> Result<Vec<u8>, JwtErr> {
+    let mut deriver = Deriver::new(private)?;
+    deriver.set_peer(peer)?;
+    Ok(deriver.derive_to_vec()?)
+}
+
+/// ECDH with a fresh ephemeral key, published as `epk`, and the recipient's `key`
+fn ecdh_encrypt_key<T: HasPublic>(
+    key: &PKeyRef<T>,
+    curve: EcCurve,
+    alg: &KeyAlgorithm,
+    enc: &ContentEncryption,
+    header: &mut JweHeader,
+) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+    agreed_key_len(alg, enc)?;
+    if curve == EcCurve::Secp256k1 {
+        return Err(JwtErr::from("ECDH-ES is not defined for secp256k1 keys"));
+    }
+    let ephemeral = EcKey::generate(curve)?;
+    header.epk = Some(ephemeral.to_public_jwk()?);
+    let z = Secret::from(ecdh(&ephemeral.key, key)?);
+    let agreed = concat_kdf(&z, alg, enc, header)?;
+    match *alg {
+        KeyAlgorithm::EcdhEs => Ok((agreed, Vec::new())),
+        _ => {
+            let agreed = Secret::from(agreed);
+            let cek = enc.generate_key()?;
+            let encrypted_key = aes_key_wrap(&agreed, &cek)?;
+            Ok((cek, encrypted_key))
+        }
+    }
+}
+
+impl EncryptingKey for EcPublicKey {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        ecdh_encrypt_key(&self.key, self.curve(), alg, enc, header)
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::EcdhEs
+    }
+}
+
+/// Encrypts to the public half, like the `RSAKey` implementation
+impl EncryptingKey for EcKey {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        ecdh_encrypt_key(&self.key, self.curve(), alg, enc, header)
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::EcdhEs
+    }
+}
+
+impl DecryptingKey for EcKey {
+    fn decrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        header: &JweHeader,
+        encrypted_key: &[u8],
+    ) -> Result<Vec<u8>, JwtErr> {
+        agreed_key_len(alg, enc)?;
+        let epk = header
+            .epk
+            .as_ref()
+            .ok_or_else(|| JwtErr::from(format!("{} tokens need an epk header", alg).as_str()))?;
+        // Parsing checks the point is on its curve, a crafted point could leak the private key
+        let epk = match epk.to_key()? {
+            JwkKey::EcPublic(epk) => epk,
+            _ => return Err(JwtErr::from("epk must be a public EC key")),
+        };
+        if epk.curve() != self.curve() {
+            return Err(JwtErr::from(
+                format!("epk must be on curve {}", self.curve()).as_str(),
+            ));
+        }
+        if *alg == KeyAlgorithm::EcdhEs && !encrypted_key.is_empty() {
+            return Err(JwtErr::from(
+                "ECDH-ES tokens must have an empty encrypted key",
+            ));
+        }
+        let z = Secret::from(ecdh(&self.key, &epk.key)?);
+        let agreed = concat_kdf(&z, alg, enc, header)?;
+        match *alg {
+            KeyAlgorithm::EcdhEs => Ok(agreed),
+            _ => aes_key_unwrap(&Secret::from(agreed), encrypted_key),
+        }
+    }
+}
+
+/// Shared AES key for `dir` encryption, used as the content encryption key as is
+///
+/// Direct encryption skips wrapping a fresh key per token, which makes it the cheap choice for
+/// links where both sides hold the key. The key length has to match `enc`, 32 bytes for the
+/// default A256GCM. The key is wiped from memory on drop.
+///
+/// ```
+/// use smpl_jwt::{AesKey, ContentEncryption, Jwe};
+///
+/// let key = || AesKey::from_bytes(&[7; 16]).unwrap();
+/// let token = Jwe::new(b"claims", key(), None)
+///     .with_enc(ContentEncryption::A128GCM)
+///     .finalize()
+///     .unwrap();
+/// assert!(token.split('.').nth(1).unwrap().is_empty());
+/// assert_eq!(Jwe::decrypt(&token, &key()).unwrap().plaintext(), b"claims");
+/// ```
+pub struct AesKey {
+    key: Vec<u8>,
+}
+
+impl AesKey {
+    /// Takes a 128, 192 or 256 bit key
+    pub fn from_bytes(key: &[u8]) -> Result<Self, JwtErr> {
+        let key = AesKey { key: key.to_vec() };
+        match key.key.len() {
+            16 | 24 | 32 => Ok(key),
+            _ => Err(JwtErr::from("AES keys must be 128, 192 or 256 bits long")),
+        }
+    }
+
+    /// Generates a random key of the length `enc` needs
+    pub fn generate(enc: ContentEncryption) -> Result<Self, JwtErr> {
+        Ok(AesKey {
+            key: enc.generate_key()?,
+        })
+    }
+
+    fn check(&self, alg: &KeyAlgorithm, enc: &ContentEncryption) -> Result<(), JwtErr> {
+        if *alg != KeyAlgorithm::Dir {
+            return Err(JwtErr::from(
+                format!("{} can not be used with an AES key", alg).as_str(),
+            ));
+        }
+        if self.key.len() != enc.key_len() {
+            return Err(JwtErr::from(
+                format!("{} needs a {} byte key", enc, enc.key_len()).as_str(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AesKey {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+impl EncryptingKey for AesKey {
+    fn encrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        _header: &mut JweHeader,
+    ) -> Result<(Vec<u8>, Vec<u8>), JwtErr> {
+        self.check(alg, enc)?;
+        Ok((self.key.clone(), Vec::new()))
+    }
+
+    fn default_key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::Dir
+    }
+}
+
+impl DecryptingKey for AesKey {
+    fn decrypt_key(
+        &self,
+        alg: &KeyAlgorithm,
+        enc: &ContentEncryption,
+        _header: &JweHeader,
+        encrypted_key: &[u8],
+    ) -> Result<Vec<u8>, JwtErr> {
+        self.check(alg, enc)?;
+        if !encrypted_key.is_empty() {
+            return Err(JwtErr::from("dir tokens must have an empty encrypted key"));
+        }
+        Ok(self.key.clone())
+    }
+}
+
+#[test]
+fn test_jwe_rsa_oaep() {
+    use crate::{HmacKey, Jwt};
+
+    let key = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    for alg in [KeyAlgorithm::RsaOaep, KeyAlgorithm::RsaOaep256] {
+        for enc in [
+            ContentEncryption::A128GCM,
+            ContentEncryption::A192GCM,
+            ContentEncryption::A256GCM,
+        ] {
+            let token = Jwe::new(b"plaintext", key.public_key().unwrap(), Some(alg))
+                .with_enc(enc)
+                .with_kid("rsa-1")
+                .finalize()
+                .unwrap();
+            let decrypted = Jwe::decrypt(&token, &key).unwrap();
+            assert_eq!(decrypted.header().alg(), alg.to_string());
+            assert_eq!(decrypted.header().enc(), enc.to_string());
+            assert_eq!(decrypted.header().kid(), Some("rsa-1"));
+            assert_eq!(decrypted.into_plaintext(), b"plaintext");
+        }
+    }
+
+    // A signed token as plaintext
+    let hmac = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let signed = Jwt::new("claims", hmac(), None).finalize().unwrap();
+    let token = Jwe::new(signed.as_bytes(), &key, None)
+        .with_cty("JWT")
+        .finalize()
+        .unwrap();
+    let decrypted = Jwe::decrypt(&token, &key).unwrap();
+    assert_eq!(decrypted.header().cty(), Some("JWT"));
+    let inner = std::str::from_utf8(decrypted.plaintext()).unwrap();
+    assert_eq!(
+        Jwt::verify(inner, &hmac(), &[Algorithm::HS256])
+            .unwrap()
+            .claims(),
+        "claims"
+    );
+
+    // Tampering with any segment, or the wrong key, fails alike
+    let segments: Vec<&str> = token.split('.').collect();
+    let flip = |i: usize| {
+        let mut tampered: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+        let mut bytes = URL_SAFE_LENIENT.decode(segments[i]).unwrap();
+        bytes[0] ^= 1;
+        tampered[i] = URL_SAFE_NO_PAD.encode(bytes);
+        tampered.join(".")
+    };
+    for i in 1..5 {
+        let err = Jwe::decrypt(&flip(i), &key).err().unwrap();
+        assert_eq!(err.to_string(), "Decryption failed");
+    }
+    assert!(Jwe::decrypt(&flip(0), &key).is_err());
+    let other = RSAKey::generate(2048).unwrap();
+    assert_eq!(
+        Jwe::decrypt(&token, &other).err().unwrap().to_string(),
+        "Decryption failed"
+    );
+    assert!(Jwe::decrypt(&segments[..4].join("."), &key).is_err());
+
+    let header = |header: &str| {
+        let mut tampered: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+        tampered[0] = URL_SAFE_NO_PAD.encode(header);
+        tampered.join(".")
+    };
+    assert!(Jwe::decrypt(&header(r#"{"alg":"RSA1_5","enc":"A256GCM"}"#), &key).is_err());
+    assert!(Jwe::decrypt(&header(r#"{"alg":"RSA-OAEP","enc":"A256CBC-HS512"}"#), &key).is_err());
+    assert!(Jwe::decrypt(
+        &header(r#"{"alg":"RSA-OAEP-256","enc":"A256GCM","crit":["exp"],"exp":1}"#),
+        &key
+    )
+    .is_err());
+}
+
+#[test]
+fn test_jwe_dir() {
+    let key = AesKey::generate(ContentEncryption::A256GCM).unwrap();
+    let token = Jwe::new(b"plaintext", &key, None).finalize().unwrap();
+    let segments: Vec<&str> = token.split('.').collect();
+    assert!(segments[1].is_empty());
+    let decrypted = Jwe::decrypt(&token, &key).unwrap();
+    assert_eq!(decrypted.header().alg(), "dir");
+    assert_eq!(decrypted.header().enc(), "A256GCM");
+    assert_eq!(decrypted.plaintext(), b"plaintext");
+
+    let other = AesKey::generate(ContentEncryption::A256GCM).unwrap();
+    assert!(Jwe::decrypt(&token, &other).is_err());
+    let short = AesKey::from_bytes(&[1; 16]).unwrap();
+    assert!(Jwe::decrypt(&token, &short).is_err());
+    assert!(Jwe::new(b"plaintext", &short, None).finalize().is_err());
+    assert!(AesKey::from_bytes(&[1; 20]).is_err());
+
+    // Neither key kind takes the other's algorithm
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    assert!(Jwe::new(b"plaintext", &key, Some(KeyAlgorithm::RsaOaep))
+        .finalize()
+        .is_err());
+    assert!(Jwe::new(b"plaintext", &rsa, Some(KeyAlgorithm::Dir))
+        .finalize()
+        .is_err());
+    assert!(Jwe::decrypt(&token, &rsa).is_err());
+
+    let with_key = [segments[0], "AAAA", segments[2], segments[3], segments[4]].join(".");
+    assert!(Jwe::decrypt(&with_key, &key).is_err());
+}
+
+#[test]
+fn test_jwe_ecdh_es() {
+    use std::str::FromStr;
+
+    let key = EcKey::generate(EcCurve::P256).unwrap();
+    for alg in [
+        KeyAlgorithm::EcdhEs,
+        KeyAlgorithm::EcdhEsA128kw,
+        KeyAlgorithm::EcdhEsA192kw,
+        KeyAlgorithm::EcdhEsA256kw,
+    ] {
+        let token = Jwe::new(b"plaintext", key.public_key().unwrap(), Some(alg))
+            .with_enc(ContentEncryption::A128GCM)
+            .with_party_info(b"producer", b"recipient")
+            .finalize()
+            .unwrap();
+        let segments: Vec<&str> = token.split('.').collect();
+        assert_eq!(segments[1].is_empty(), alg == KeyAlgorithm::EcdhEs);
+        let decrypted = Jwe::decrypt(&token, &key).unwrap();
+        assert_eq!(decrypted.header().alg(), alg.to_string());
+        assert_eq!(decrypted.header().epk().unwrap().kty(), "EC");
+        assert_eq!(decrypted.header().apu().unwrap().unwrap(), b"producer");
+        assert_eq!(decrypted.plaintext(), b"plaintext");
+    }
+
+    let token = Jwe::new(b"plaintext", &key, None).finalize().unwrap();
+    let segments: Vec<&str> = token.split('.').collect();
+    let other = EcKey::generate(EcCurve::P256).unwrap();
+    assert!(Jwe::decrypt(&token, &other).is_err());
+    let p384 = EcKey::generate(EcCurve::P384).unwrap();
+    assert!(Jwe::decrypt(&token, &p384).is_err());
+    assert!(Jwe::new(b"plaintext", &key, Some(KeyAlgorithm::RsaOaep))
+        .finalize()
+        .is_err());
+    let k1 = EcKey::generate(EcCurve::Secp256k1).unwrap();
+    assert!(Jwe::new(b"plaintext", &k1, None).finalize().is_err());
+
+    // Swapping in another epk, or one off its curve, breaks the agreement
+    let mut header: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_LENIENT.decode(segments[0]).unwrap()).unwrap();
+    let with_epk = |header: &serde_json::Value| {
+        let protected = URL_SAFE_NO_PAD.encode(header.to_string());
+        [
+            protected.as_str(),
+            segments[1],
+            segments[2],
+            segments[3],
+            segments[4],
+        ]
+        .join(".")
+    };
+    header["epk"] =
+        serde_json::from_str(&other.to_public_jwk().unwrap().to_json().unwrap()).unwrap();
+    assert!(Jwe::decrypt(&with_epk(&header), &key).is_err());
+    header["epk"]["y"] = header["epk"]["x"].clone();
+    assert!(Jwe::decrypt(&with_epk(&header), &key).is_err());
+    header.as_object_mut().unwrap().remove("epk");
+    assert!(Jwe::decrypt(&with_epk(&header), &key).is_err());
+
+    // RFC 7518 Appendix C
+    let bob = Jwk::from_str(
+        r#"{"kty":"EC","crv":"P-256",
+            "x":"weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ",
+            "y":"e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck",
+            "d":"VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw"}"#,
+    )
+    .unwrap();
+    let alice = Jwk::from_str(
+        r#"{"kty":"EC","crv":"P-256",
+            "x":"gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0",
+            "y":"SLW_xSffzlPWrHEVI30DHM_4egVwt3NQqeUD7nMFpps",
+            "d":"0_NxaRPUMQoAJt50Gz8YiTr8gRTwyEaCumd-MToTmIo"}"#,
+    )
+    .unwrap();
+    let (bob, alice) = match (bob.to_key().unwrap(), alice.to_key().unwrap()) {
+        (JwkKey::Ec(bob), JwkKey::Ec(alice)) => (bob, alice),
+        _ => unreachable!(),
+    };
+    let jwe = Jwe::new(b"", &bob, None).with_party_info(b"Alice", b"Bob");
+    let z = ecdh(&alice.key, &bob.key).unwrap();
+    let derived = concat_kdf(
+        &z,
+        &KeyAlgorithm::EcdhEs,
+        &ContentEncryption::A128GCM,
+        &jwe.header,
+    )
+    .unwrap();
+    assert_eq!(URL_SAFE_NO_PAD.encode(derived), "VqqN6vgjbSBcIijNcacQGg");
+}
+
+#[test]
+fn test_nested_jwt() {
+    use crate::HmacKey;
+
+    let signer = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let recipient = EcKey::generate(EcCurve::P256).unwrap();
+    let jwt = Jwt::new(serde_json::json!({"sub": "me"}), &signer, None).with_typ("JWT");
+    let token = Jwe::nested(&jwt, recipient.public_key().unwrap(), None)
+        .unwrap()
+        .with_enc(ContentEncryption::A128GCM)
+        .finalize()
+        .unwrap();
+    let decoded =
+        Jwt::decrypt_and_verify(&token, &recipient, &signer, &[Algorithm::ES256]).unwrap();
+    assert_eq!(decoded.header().alg(), "ES256");
+    assert_eq!(decoded.claims()["sub"], "me");
+    let outer = Jwe::decrypt(&token, &recipient).unwrap();
+    assert_eq!(outer.header().cty(), Some("JWT"));
+    assert_eq!(outer.header().enc(), "A128GCM");
+
+    let options = ValidationOptions::new(&[Algorithm::RS256]);
+    assert!(Jwt::decrypt_and_verify_with(&token, &recipient, &signer, &options).is_err());
+    let other = EcKey::generate(EcCurve::P256).unwrap();
+    assert!(Jwt::decrypt_and_verify(&token, &other, &signer, &[Algorithm::ES256]).is_err());
+    assert!(Jwt::decrypt_and_verify(&token, &recipient, &other, &[Algorithm::ES256]).is_err());
+
+    let token = jwt.finalize_encrypted(&recipient).unwrap();
+    assert!(Jwt::decrypt_and_verify(&token, &recipient, &signer, &[Algorithm::ES256]).is_ok());
+
+    // A JWE holding anything but a signed token
+    let signed = jwt.finalize().unwrap();
+    let token = Jwe::new(signed.as_bytes(), &recipient, None)
+        .finalize()
+        .unwrap();
+    assert!(Jwt::decrypt_and_verify(&token, &recipient, &signer, &[Algorithm::ES256]).is_err());
+    let token = Jwe::new(signed.as_bytes(), &recipient, None)
+        .with_cty("jwt")
+        .finalize()
+        .unwrap();
+    assert!(Jwt::decrypt_and_verify(&token, &recipient, &signer, &[Algorithm::ES256]).is_ok());
+    let hmac = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    assert!(Jwt::decrypt_and_verify(&token, &recipient, &hmac, &[Algorithm::ES256]).is_err());
+}
+
+#[test]
+fn test_jwe_compression() {
+    let key = AesKey::generate(ContentEncryption::A256GCM).unwrap();
+    let entitlements: Vec<String> = (0..500).map(|i| format!("group-{}:read", i)).collect();
+    let plaintext =
+        serde_json::to_vec(&serde_json::json!({ "entitlements": entitlements })).unwrap();
+    let plain = Jwe::new(&plaintext, &key, None).finalize().unwrap();
+    let token = Jwe::new(&plaintext, &key, None)
+        .with_compression()
+        .finalize()
+        .unwrap();
+    assert!(token.len() < plain.len() / 2);
+    let decrypted = Jwe::decrypt(&token, &key).unwrap();
+    assert_eq!(decrypted.header().zip(), Some("DEF"));
+    assert_eq!(decrypted.plaintext(), &plaintext[..]);
+    assert_eq!(Jwe::decrypt(&plain, &key).unwrap().header().zip(), None);
+
+    // A small token inflating past the limit
+    let bomb = Jwe::new(&vec![0; MAX_DECOMPRESSED_LEN + 1], &key, None)
+        .with_compression()
+        .finalize()
+        .unwrap();
+    assert!(bomb.len() < 10000);
+    assert!(Jwe::decrypt(&bomb, &key).is_err());
+
+    let zlib = Jwe::new(b"plaintext", &key, None)
+        .with_header_param("zip", "GZIP".into())
+        .finalize()
+        .unwrap();
+    assert!(Jwe::decrypt(&zlib, &key).is_err());
+}
@@ -0,0 +1,896 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcGroup, EcKey as OpenSslEcKey};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private};
+use openssl::rsa::{Rsa, RsaPrivateKeyBuilder};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::backend::sha256;
+use crate::secret::{wipe_string, Secret};
+use crate::{
+    Algorithm, DecodedJwt, EcCurve, EcKey, EcPublicKey, Ed25519Key, Ed25519PublicKey, Ed448Key,
+    Ed448PublicKey, HmacKey, Jwt, JwtErr, RSAKey, RSAPublicKey, ValidationError, ValidationOptions,
+    VerifyingKey, URL_SAFE_LENIENT,
+};
+
+/// JSON Web Key as defined in RFC 7517, only the members needed to build a key are kept
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{Jwk, JwkKey};
+/// use std::str::FromStr;
+///
+/// let jwk = Jwk::from_str(r#"{"kty":"EC","crv":"P-256","kid":"1",
+///     "x":"MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+///     "y":"4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM"}"#).unwrap();
+/// assert_eq!(jwk.kid(), Some("1"));
+/// match jwk.to_key().unwrap() {
+///     JwkKey::EcPublic(key) => println!("{}", key.curve()),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Jwk {
+    kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alg: Option<String>,
+    #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+    key_use: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    d: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    q: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dq: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    k: Option<String>,
+}
+
+// Private members are left out, a logged key set must not leak its secrets
+impl fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let redacted = |member: &Option<String>| member.as_ref().map(|_| "<redacted>");
+        f.debug_struct("Jwk")
+            .field("kty", &self.kty)
+            .field("kid", &self.kid)
+            .field("alg", &self.alg)
+            .field("key_use", &self.key_use)
+            .field("crv", &self.crv)
+            .field("n", &self.n)
+            .field("e", &self.e)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("d", &redacted(&self.d))
+            .field("p", &redacted(&self.p))
+            .field("q", &redacted(&self.q))
+            .field("dp", &redacted(&self.dp))
+            .field("dq", &redacted(&self.dq))
+            .field("qi", &redacted(&self.qi))
+            .field("k", &redacted(&self.k))
+            .finish()
+    }
+}
+
+impl Drop for Jwk {
+    fn drop(&mut self) {
+        let private = [
+            &mut self.d,
+            &mut self.p,
+            &mut self.q,
+            &mut self.dp,
+            &mut self.dq,
+            &mut self.qi,
+            &mut self.k,
+        ];
+        for member in IntoIterator::into_iter(private).flatten() {
+            wipe_string(member);
+        }
+    }
+}
+
+/// Key built from a `Jwk`, public or private depending on the members present
+pub enum JwkKey {
+    Rsa(RSAKey),
+    RsaPublic(RSAPublicKey),
+    Ec(EcKey),
+    EcPublic(EcPublicKey),
+    Ed25519(Ed25519Key),
+    Ed25519Public(Ed25519PublicKey),
+    Ed448(Ed448Key),
+    Ed448Public(Ed448PublicKey),
+    Hmac(HmacKey),
+}
+
+/// JWK Set as published by identity providers, `{"keys": [...]}`
+///
+/// Keys are built when the set is parsed. Entries this crate cannot use, encryption keys and
+/// unsupported key types, are skipped rather than failing the whole set, as RFC 7517 5 asks.
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{Algorithm, HmacKey, JwkSet, Jwt};
+/// use std::str::FromStr;
+///
+/// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let jwks = format!(r#"{{"keys":[{}]}}"#, key.to_jwk().with_kid("1").to_json().unwrap());
+/// let set = JwkSet::from_str(&jwks).unwrap();
+///
+/// let token = Jwt::new("claims", key, Some(Algorithm::HS256)).finalize().unwrap();
+/// assert_eq!(set.verify(&token, &[Algorithm::HS256]).unwrap().claims(), "claims");
+/// ```
+pub struct JwkSet {
+    keys: Vec<(Jwk, JwkKey)>,
+}
+
+#[derive(Deserialize)]
+struct JwkSetDocument {
+    keys: Vec<serde_json::Value>,
+}
+
+impl JwkSet {
+    pub fn from_jwks(jwks: Vec<Jwk>) -> Self {
+        JwkSet {
+            keys: jwks
+                .into_iter()
+                .filter(|jwk| jwk.key_use() != Some("enc"))
+                .filter_map(|jwk| jwk.to_key().ok().map(|key| (jwk, key)))
+                .collect(),
+        }
+    }
+
+    pub fn find_by_kid(&self, kid: &str) -> Option<&JwkKey> {
+        self.entry_by_kid(kid).map(|(_, key)| key)
+    }
+
+    fn entry_by_kid(&self, kid: &str) -> Option<(&Jwk, &JwkKey)> {
+        self.iter().find(|(jwk, _)| jwk.kid() == Some(kid))
+    }
+
+    /// First key whose `alg` member names `algo`, keys without `alg` are not considered
+    pub fn find_by_alg(&self, algo: Algorithm) -> Option<&JwkKey> {
+        let name = algo.to_string();
+        self.iter()
+            .find(|(jwk, _)| jwk.alg() == Some(name.as_str()))
+            .map(|(_, key)| key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Jwk, &JwkKey)> {
+        self.keys.iter().map(|(jwk, key)| (jwk, key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Verifies `token` with the key named by its `kid` header, or with any fitting key if it has none
+    ///
+    /// A `kid` that is not part of the set is an error, it is not retried with the other keys. The
+    /// `alg` of the token has to be one of `algorithms`, as for `Jwt::verify`, and the `alg` of the
+    /// key if it has one, on either path.
+    pub fn verify(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        self.verify_with(token, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same key lookup as `verify`, checking the token against `options` like `Jwt::verify_with`
+    pub fn verify_with(
+        &self,
+        token: &str,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, _) = Jwt::decode_unverified(token)?;
+        match header.kid() {
+            Some(kid) => match self.entry_by_kid(kid) {
+                Some((jwk, _)) if jwk.alg().is_some_and(|alg| alg != header.alg()) => {
+                    Err(JwtErr::described(
+                        ValidationError::DisallowedAlgorithm(header.alg().to_string()),
+                        &format!("Token alg {} does not match key {}", header.alg(), kid),
+                    ))
+                }
+                Some((_, key)) => Jwt::verify_with(token, key, options),
+                None => Err(JwtErr::described(
+                    ValidationError::UnknownKid(kid.to_string()),
+                    &format!("No key with kid {} in the JWK set", kid),
+                )),
+            },
+            None => Jwt::verify_with(token, self, options),
+        }
+    }
+}
+
+impl FromStr for JwkSet {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        let document: JwkSetDocument = serde_json::from_str(s)?;
+        // Members this crate does not model, or malformed ones, only drop that single key
+        Ok(Self::from_jwks(
+            document
+                .keys
+                .into_iter()
+                .filter_map(|jwk| serde_json::from_value(jwk).ok())
+                .collect(),
+        ))
+    }
+}
+
+/// Tries every key whose `alg`, if present, matches, so tokens without a `kid` still verify
+impl VerifyingKey for JwkSet {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        let name = algo.to_string();
+        Ok(self
+            .iter()
+            .filter(|(jwk, _)| jwk.alg().is_none_or(|alg| alg == name))
+            .any(|(_, key)| key.verify(algo, input, signature).unwrap_or(false)))
+    }
+}
+
+impl Jwk {
+    pub fn kty(&self) -> &str {
+        &self.kty
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn alg(&self) -> Option<&str> {
+        self.alg.as_deref()
+    }
+
+    /// The `use` member, `sig` or `enc`
+    pub fn key_use(&self) -> Option<&str> {
+        self.key_use.as_deref()
+    }
+
+    /// Sets the `kid` member, used by verifiers to pick the key out of a set
+    pub fn with_kid(mut self, kid: &str) -> Self {
+        self.kid = Some(kid.to_string());
+        self
+    }
+
+    /// Sets the `alg` member, restricting the key to a single algorithm
+    pub fn with_alg(mut self, algo: Algorithm) -> Self {
+        self.alg = Some(algo.to_string());
+        self
+    }
+
+    /// Sets the `use` member, `sig` or `enc`
+    pub fn with_use(mut self, key_use: &str) -> Self {
+        self.key_use = Some(key_use.to_string());
+        self
+    }
+
+    /// Serializes the JWK, RFC 7517 JSON
+    pub fn to_json(&self) -> Result<String, JwtErr> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    // Struct update syntax can not move out of a `Jwk`, which wipes its private members on drop
+    fn of_type(kty: &str) -> Jwk {
+        let mut jwk = Jwk::default();
+        jwk.kty = kty.to_string();
+        jwk
+    }
+
+    pub(crate) fn rsa_public<T: HasPublic>(key: &PKeyRef<T>) -> Result<Jwk, JwtErr> {
+        let rsa = key.rsa()?;
+        let mut jwk = Jwk::of_type("RSA");
+        jwk.n = Some(encode(rsa.n()));
+        jwk.e = Some(encode(rsa.e()));
+        Ok(jwk)
+    }
+
+    pub(crate) fn rsa_private(key: &PKeyRef<Private>) -> Result<Jwk, JwtErr> {
+        let rsa = key.rsa()?;
+        let mut jwk = Self::rsa_public(key)?;
+        jwk.d = Some(encode(rsa.d()));
+        jwk.p = rsa.p().map(encode);
+        jwk.q = rsa.q().map(encode);
+        jwk.dp = rsa.dmp1().map(encode);
+        jwk.dq = rsa.dmq1().map(encode);
+        jwk.qi = rsa.iqmp().map(encode);
+        Ok(jwk)
+    }
+
+    pub(crate) fn ec_public<T: HasPublic>(key: &PKeyRef<T>, curve: EcCurve) -> Result<Jwk, JwtErr> {
+        let ec = key.ec_key()?;
+        let len = (ec.group().degree() as usize).div_ceil(8);
+        let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+        let mut ctx = BigNumContext::new()?;
+        ec.public_key()
+            .affine_coordinates(ec.group(), &mut x, &mut y, &mut ctx)?;
+        let mut jwk = Jwk::of_type("EC");
+        jwk.crv = Some(curve.to_string());
+        jwk.x = Some(URL_SAFE_NO_PAD.encode(x.to_vec_padded(len as i32)?));
+        jwk.y = Some(URL_SAFE_NO_PAD.encode(y.to_vec_padded(len as i32)?));
+        Ok(jwk)
+    }
+
+    pub(crate) fn ec_private(key: &PKeyRef<Private>, curve: EcCurve) -> Result<Jwk, JwtErr> {
+        let ec = key.ec_key()?;
+        let len = (ec.group().degree() as usize).div_ceil(8);
+        let mut jwk = Self::ec_public(key, curve)?;
+        jwk.d = Some(URL_SAFE_NO_PAD.encode(ec.private_key().to_vec_padded(len as i32)?));
+        Ok(jwk)
+    }
+
+    pub(crate) fn okp_public<T: HasPublic>(key: &PKeyRef<T>, crv: &str) -> Result<Jwk, JwtErr> {
+        let mut jwk = Jwk::of_type("OKP");
+        jwk.crv = Some(crv.to_string());
+        jwk.x = Some(URL_SAFE_NO_PAD.encode(key.raw_public_key()?));
+        Ok(jwk)
+    }
+
+    pub(crate) fn okp_private(key: &PKeyRef<Private>, crv: &str) -> Result<Jwk, JwtErr> {
+        let mut jwk = Self::okp_public(key, crv)?;
+        jwk.d = Some(URL_SAFE_NO_PAD.encode(key.raw_private_key()?));
+        Ok(jwk)
+    }
+
+    pub(crate) fn oct(secret: &[u8]) -> Jwk {
+        let mut jwk = Jwk::of_type("oct");
+        jwk.k = Some(URL_SAFE_NO_PAD.encode(secret));
+        jwk
+    }
+
+    /// SHA-256 JWK thumbprint as defined in RFC 7638, base64url encoded
+    ///
+    /// Only the required members of the key type are hashed, so the thumbprint of a private key
+    /// equals the one of its public half and is stable across `kid`, `alg` or `use` changes.
+    ///
+    /// ```
+    /// use smpl_jwt::HmacKey;
+    ///
+    /// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let kid = key.to_jwk().thumbprint().unwrap();
+    /// assert_eq!(kid.len(), 43);
+    /// ```
+    pub fn thumbprint(&self) -> Result<String, JwtErr> {
+        let required: &[(&str, &Option<String>)] = match self.kty.as_str() {
+            "RSA" => &[("e", &self.e), ("n", &self.n)],
+            "EC" => &[("crv", &self.crv), ("x", &self.x), ("y", &self.y)],
+            "OKP" => &[("crv", &self.crv), ("x", &self.x)],
+            "oct" => &[("k", &self.k)],
+            kty => {
+                return Err(JwtErr::from(
+                    format!("Unsupported JWK key type {}", kty).as_str(),
+                ))
+            }
+        };
+        // Lexicographic member order and no whitespace, as serde_json writes a BTreeMap
+        let mut members = BTreeMap::new();
+        members.insert("kty", self.kty.as_str());
+        for (name, value) in required {
+            members.insert(*name, self.member(name, value)?);
+        }
+        let digest = sha256(serde_json::to_string(&members)?.as_bytes())?;
+        Ok(URL_SAFE_NO_PAD.encode(digest))
+    }
+
+    /// Builds the key described by this JWK, a private key if the private members are present
+    pub fn to_key(&self) -> Result<JwkKey, JwtErr> {
+        match self.kty.as_str() {
+            "RSA" => self.rsa_key(),
+            "EC" => self.ec_key(),
+            "OKP" => self.okp_key(),
+            "oct" => Ok(JwkKey::Hmac(HmacKey::from_bytes(&self.param("k")?)?)),
+            kty => Err(JwtErr::from(
+                format!("Unsupported JWK key type {}", kty).as_str(),
+            )),
+        }
+    }
+
+    fn rsa_key(&self) -> Result<JwkKey, JwtErr> {
+        let n = BigNum::from_slice(&self.param("n")?)?;
+        let e = BigNum::from_slice(&self.param("e")?)?;
+        if self.d.is_none() {
+            let rsa = Rsa::from_public_components(n, e)?;
+            return Ok(JwkKey::RsaPublic(RSAPublicKey::from_pkey(PKey::from_rsa(
+                rsa,
+            )?)?));
+        }
+
+        let d = BigNum::from_slice(&self.param("d")?)?;
+        let mut builder = RsaPrivateKeyBuilder::new(n, e, d)?;
+        // The CRT members are optional, but if one is present all of them must be
+        if self.p.is_some() || self.q.is_some() {
+            builder = builder
+                .set_factors(self.bignum("p")?, self.bignum("q")?)?
+                .set_crt_params(self.bignum("dp")?, self.bignum("dq")?, self.bignum("qi")?)?;
+        }
+        Ok(JwkKey::Rsa(RSAKey::from_pkey(PKey::from_rsa(
+            builder.build(),
+        )?)?))
+    }
+
+    fn ec_key(&self) -> Result<JwkKey, JwtErr> {
+        let crv = self.member("crv", &self.crv)?;
+        let curve = EcCurve::from_name(crv)
+            .ok_or_else(|| JwtErr::from(format!("Unsupported JWK curve {}", crv).as_str()))?;
+        let group = EcGroup::from_curve_name(curve.nid())?;
+
+        // RFC 7518 6.2.1.2, coordinates are always encoded with the full size of the curve
+        let len = (group.degree() as usize).div_ceil(8);
+        let (x, y) = (self.param("x")?, self.param("y")?);
+        if x.len() != len || y.len() != len {
+            return Err(JwtErr::from("EC JWK coordinates have the wrong length"));
+        }
+        let (x, y) = (BigNum::from_slice(&x)?, BigNum::from_slice(&y)?);
+        let public = OpenSslEcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+        if self.d.is_none() {
+            return Ok(JwkKey::EcPublic(EcPublicKey::from_pkey(
+                PKey::from_ec_key(public)?,
+            )?));
+        }
+
+        let d = self.param("d")?;
+        if d.len() != len {
+            return Err(JwtErr::from("EC JWK private key has the wrong length"));
+        }
+        let d = BigNum::from_slice(&d)?;
+        let private = OpenSslEcKey::from_private_components(&group, &d, public.public_key())?;
+        // Also rejects a d that does not belong to x and y
+        private
+            .check_key()
+            .map_err(|_| JwtErr::from("EC JWK private key does not match x and y"))?;
+        Ok(JwkKey::Ec(EcKey::from_pkey(PKey::from_ec_key(private)?)?))
+    }
+
+    fn okp_key(&self) -> Result<JwkKey, JwtErr> {
+        let id = match self.member("crv", &self.crv)? {
+            "Ed25519" => Id::ED25519,
+            "Ed448" => Id::ED448,
+            crv => {
+                return Err(JwtErr::from(
+                    format!("Unsupported JWK curve {}", crv).as_str(),
+                ))
+            }
+        };
+        let x = self.param("x")?;
+        if self.d.is_none() {
+            let key = PKey::public_key_from_raw_bytes(&x, id)?;
+            return Ok(if id == Id::ED25519 {
+                JwkKey::Ed25519Public(Ed25519PublicKey::from_pkey(key)?)
+            } else {
+                JwkKey::Ed448Public(Ed448PublicKey::from_pkey(key)?)
+            });
+        }
+
+        let key = PKey::private_key_from_raw_bytes(&self.param("d")?, id)?;
+        if key.raw_public_key()? != *x {
+            return Err(JwtErr::from("OKP JWK private key does not match x"));
+        }
+        Ok(if id == Id::ED25519 {
+            JwkKey::Ed25519(Ed25519Key::from_pkey(key)?)
+        } else {
+            JwkKey::Ed448(Ed448Key::from_pkey(key)?)
+        })
+    }
+
+    fn member<'a>(&self, name: &str, value: &'a Option<String>) -> Result<&'a str, JwtErr> {
+        value
+            .as_deref()
+            .ok_or_else(|| JwtErr::from(format!("JWK is missing the {} member", name).as_str()))
+    }
+
+    /// Decodes a base64url encoded key parameter
+    ///
+    /// Private members are decoded into a `Secret`, public ones ride along for simplicity.
+    fn param(&self, name: &str) -> Result<Secret, JwtErr> {
+        let value = match name {
+            "n" => &self.n,
+            "e" => &self.e,
+            "x" => &self.x,
+            "y" => &self.y,
+            "d" => &self.d,
+            "p" => &self.p,
+            "q" => &self.q,
+            "dp" => &self.dp,
+            "dq" => &self.dq,
+            "qi" => &self.qi,
+            "k" => &self.k,
+            _ => &None,
+        };
+        Ok(Secret::from(
+            URL_SAFE_LENIENT.decode(self.member(name, value)?)?,
+        ))
+    }
+
+    fn bignum(&self, name: &str) -> Result<BigNum, JwtErr> {
+        Ok(BigNum::from_slice(&self.param(name)?)?)
+    }
+}
+
+/// RFC 7518 encodes integers as unpadded base64url of their minimal big endian bytes
+fn encode(n: &BigNumRef) -> String {
+    URL_SAFE_NO_PAD.encode(n.to_vec())
+}
+
+impl FromStr for Jwk {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+impl VerifyingKey for JwkKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        match self {
+            JwkKey::Rsa(key) => key.verify(algo, input, signature),
+            JwkKey::RsaPublic(key) => key.verify(algo, input, signature),
+            JwkKey::Ec(key) => key.verify(algo, input, signature),
+            JwkKey::EcPublic(key) => key.verify(algo, input, signature),
+            JwkKey::Ed25519(key) => key.verify(algo, input, signature),
+            JwkKey::Ed25519Public(key) => key.verify(algo, input, signature),
+            JwkKey::Ed448(key) => key.verify(algo, input, signature),
+            JwkKey::Ed448Public(key) => key.verify(algo, input, signature),
+            JwkKey::Hmac(key) => key.verify(algo, input, signature),
+        }
+    }
+}
+
+#[test]
+fn test_jwk_ec() {
+    use crate::SigningKey;
+
+    // RFC 7517 A.1 and A.2
+    let public = r#"{"kty":"EC","crv":"P-256",
+        "x":"MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+        "y":"4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM",
+        "use":"enc","kid":"1"}"#;
+    let private = r#"{"kty":"EC","crv":"P-256",
+        "x":"MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+        "y":"4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM",
+        "d":"870MB6gfuTJ4HtUnUvYMyJpr5eUZNP4Bk43bVdj3eAE",
+        "use":"enc","kid":"1"}"#;
+
+    let public = Jwk::from_str(public).unwrap();
+    assert_eq!(public.kty(), "EC");
+    assert_eq!(public.kid(), Some("1"));
+    assert_eq!(public.key_use(), Some("enc"));
+    let private = match Jwk::from_str(private).unwrap().to_key().unwrap() {
+        JwkKey::Ec(key) => key,
+        _ => panic!("expected an EC private key"),
+    };
+    assert_eq!(private.curve(), EcCurve::P256);
+
+    let signature = private.sign(&Algorithm::ES256, b"input").unwrap();
+    let public = public.to_key().unwrap();
+    assert!(matches!(public, JwkKey::EcPublic(_)));
+    assert!(public
+        .verify(&Algorithm::ES256, b"input", &signature)
+        .unwrap());
+
+    let mismatched = r#"{"kty":"EC","crv":"P-256",
+        "x":"MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+        "y":"4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM",
+        "d":"870MB6gfuTJ4HtUnUvYMyJpr5eUZNP4Bk43bVdj3eAA"}"#;
+    assert!(Jwk::from_str(mismatched).unwrap().to_key().is_err());
+    let short = r#"{"kty":"EC","crv":"P-256","x":"MKBC","y":"4Etl"}"#;
+    assert!(Jwk::from_str(short).unwrap().to_key().is_err());
+    let unknown = r#"{"kty":"EC","crv":"P-192","x":"MKBC","y":"4Etl"}"#;
+    assert!(Jwk::from_str(unknown).unwrap().to_key().is_err());
+}
+
+#[test]
+fn test_jwk_rsa() {
+    use crate::{Jwt, SigningKey};
+
+    let rsa_key = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let rsa = rsa_key.key.rsa().unwrap();
+    let b64 = |n: &openssl::bn::BigNumRef| URL_SAFE_LENIENT.encode(n.to_vec());
+    let public = serde_json::json!({
+        "kty": "RSA",
+        "n": b64(rsa.n()),
+        "e": b64(rsa.e()),
+    });
+    let mut private = public.clone();
+    private["d"] = b64(rsa.d()).into();
+    let mut crt = private.clone();
+    crt["p"] = b64(rsa.p().unwrap()).into();
+    crt["q"] = b64(rsa.q().unwrap()).into();
+    crt["dp"] = b64(rsa.dmp1().unwrap()).into();
+    crt["dq"] = b64(rsa.dmq1().unwrap()).into();
+    crt["qi"] = b64(rsa.iqmp().unwrap()).into();
+
+    let token = Jwt::new("body", rsa_key, None).finalize().unwrap();
+    let public = Jwk::from_str(&public.to_string())
+        .unwrap()
+        .to_key()
+        .unwrap();
+    assert!(matches!(public, JwkKey::RsaPublic(_)));
+    assert!(Jwt::verify(&token, &public, &[Algorithm::RS256]).is_ok());
+
+    for private in [private, crt] {
+        match Jwk::from_str(&private.to_string())
+            .unwrap()
+            .to_key()
+            .unwrap()
+        {
+            JwkKey::Rsa(key) => {
+                let signature = key.sign(&Algorithm::RS256, b"input").unwrap();
+                assert!(public
+                    .verify(&Algorithm::RS256, b"input", &signature)
+                    .unwrap());
+            }
+            _ => panic!("expected an RSA private key"),
+        }
+    }
+
+    assert!(Jwk::from_str(r#"{"kty":"RSA","n":"AQAB"}"#)
+        .unwrap()
+        .to_key()
+        .is_err());
+}
+
+#[test]
+fn test_jwk_okp() {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    // RFC 8037 A.1, A.2 and A.4
+    let private = r#"{"kty":"OKP","crv":"Ed25519",
+        "d":"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+        "x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+    let public = r#"{"kty":"OKP","crv":"Ed25519",
+        "x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+    let input = b"eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc";
+    let expected =
+        "hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg";
+
+    match Jwk::from_str(private).unwrap().to_key().unwrap() {
+        JwkKey::Ed25519(key) => {
+            use crate::SigningKey;
+            let signature = key.sign(&Algorithm::EdDSA, input).unwrap();
+            assert_eq!(URL_SAFE_NO_PAD.encode(signature), expected);
+        }
+        _ => panic!("expected an Ed25519 private key"),
+    }
+    let public = Jwk::from_str(public).unwrap().to_key().unwrap();
+    assert!(matches!(public, JwkKey::Ed25519Public(_)));
+    let signature = URL_SAFE_LENIENT.decode(expected).unwrap();
+    assert!(public.verify(&Algorithm::EdDSA, input, &signature).unwrap());
+
+    let mismatched = r#"{"kty":"OKP","crv":"Ed25519",
+        "d":"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+        "x":"AAAAAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+    assert!(Jwk::from_str(mismatched).unwrap().to_key().is_err());
+}
+
+#[test]
+fn test_jwk_oct() {
+    // RFC 7515 A.1
+    let jwk = r#"{"kty":"oct",
+        "k":"AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow"}"#;
+    let debug = format!("{:?}", Jwk::from_str(jwk).unwrap());
+    assert!(debug.contains(r#"k: Some("<redacted>")"#));
+    assert!(!debug.contains("AyM1"));
+    let key = Jwk::from_str(jwk).unwrap().to_key().unwrap();
+    let token = "eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9.eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+    let signature = URL_SAFE_LENIENT
+        .decode("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk")
+        .unwrap();
+    assert!(key
+        .verify(&Algorithm::HS256, token.as_bytes(), &signature)
+        .unwrap());
+
+    assert!(Jwk::from_str(r#"{"kty":"oct"}"#).unwrap().to_key().is_err());
+    assert!(Jwk::from_str(r#"{"kty":"dummy"}"#)
+        .unwrap()
+        .to_key()
+        .is_err());
+    assert!(Jwk::from_str(r#"{"k":"AyM1"}"#).is_err());
+}
+
+#[test]
+fn test_jwk_export() {
+    use crate::SigningKey;
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let ec = EcKey::from_pem("random_ec384_for_testing").unwrap();
+    let ed25519 = Ed25519Key::from_pem("random_ed25519_for_testing").unwrap();
+    let ed448 = Ed448Key::from_pem("random_ed448_for_testing").unwrap();
+    let hmac = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+
+    let exports: Vec<(&dyn SigningKey, Algorithm, Jwk, Jwk)> = vec![
+        (
+            &rsa,
+            Algorithm::PS256,
+            rsa.to_jwk().unwrap(),
+            rsa.to_public_jwk().unwrap(),
+        ),
+        (
+            &ec,
+            Algorithm::ES384,
+            ec.to_jwk().unwrap(),
+            ec.to_public_jwk().unwrap(),
+        ),
+        (
+            &ed25519,
+            Algorithm::EdDSA,
+            ed25519.to_jwk().unwrap(),
+            ed25519.to_public_jwk().unwrap(),
+        ),
+        (
+            &ed448,
+            Algorithm::EdDSA,
+            ed448.to_jwk().unwrap(),
+            ed448.to_public_jwk().unwrap(),
+        ),
+        (&hmac, Algorithm::HS256, hmac.to_jwk(), hmac.to_jwk()),
+    ];
+    for (key, algo, private, public) in exports {
+        let signature = key.sign(&algo, b"input").unwrap();
+        if private.kty() != "oct" {
+            assert!(private.d.is_some());
+            assert!(public.d.is_none());
+        }
+        for jwk in [private, public] {
+            let parsed = Jwk::from_str(&jwk.to_json().unwrap()).unwrap();
+            assert!(parsed
+                .to_key()
+                .unwrap()
+                .verify(&algo, b"input", &signature)
+                .unwrap());
+        }
+    }
+
+    // Private keys round trip through the JWK as signing keys
+    match rsa.to_jwk().unwrap().to_key().unwrap() {
+        JwkKey::Rsa(key) => assert_eq!(key.to_jwk().unwrap().d, rsa.to_jwk().unwrap().d),
+        _ => panic!("expected an RSA private key"),
+    }
+    let public = rsa.public_key().unwrap().to_jwk().unwrap();
+    assert_eq!(public.n, rsa.to_public_jwk().unwrap().n);
+    let public = ec.public_key().unwrap().to_jwk().unwrap();
+    assert_eq!(public.x.as_ref().unwrap().len(), 64);
+    let public = ed25519.public_key().unwrap().to_jwk().unwrap();
+    assert_eq!(public.crv.as_deref(), Some("Ed25519"));
+
+    let jwk = ec
+        .to_public_jwk()
+        .unwrap()
+        .with_kid("key-1")
+        .with_alg(Algorithm::ES384)
+        .with_use("sig");
+    let json: serde_json::Value = serde_json::from_str(&jwk.to_json().unwrap()).unwrap();
+    assert_eq!(json["kid"], "key-1");
+    assert_eq!(json["alg"], "ES384");
+    assert_eq!(json["use"], "sig");
+    assert_eq!(json["crv"], "P-384");
+    assert!(json.get("d").is_none());
+}
+
+#[test]
+fn test_jwk_set() {
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let jwks = serde_json::json!({
+        "keys": [
+            serde_json::from_str::<serde_json::Value>(
+                &rsa.to_public_jwk().unwrap().with_kid("rsa").with_alg(Algorithm::RS256).to_json().unwrap()
+            ).unwrap(),
+            serde_json::from_str::<serde_json::Value>(
+                &ec.to_public_jwk().unwrap().with_kid("ec").to_json().unwrap()
+            ).unwrap(),
+            {"kty": "EC", "crv": "P-256", "kid": "enc", "use": "enc",
+             "x": "MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+             "y": "4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM"},
+            {"kty": "unknown", "kid": "unknown"},
+            {"kid": "no kty"},
+        ]
+    });
+    let set = JwkSet::from_str(&jwks.to_string()).unwrap();
+    assert_eq!(set.len(), 2);
+    assert!(!set.is_empty());
+    assert!(set.find_by_kid("rsa").is_some());
+    assert!(set.find_by_kid("enc").is_none());
+    assert!(set.find_by_kid("unknown").is_none());
+    assert!(set.find_by_alg(Algorithm::RS256).is_some());
+    assert!(set.find_by_alg(Algorithm::ES256).is_none());
+    let kids: Vec<_> = set.iter().map(|(jwk, _)| jwk.kid().unwrap()).collect();
+    assert_eq!(kids, vec!["rsa", "ec"]);
+
+    // Tokens without a kid fall back to trying the keys
+    let rsa_token = Jwt::new("rsa", &rsa, None).finalize().unwrap();
+    let ec_token = Jwt::new("ec", ec, None).finalize().unwrap();
+    assert_eq!(
+        set.verify(&rsa_token, &[Algorithm::RS256, Algorithm::ES256])
+            .unwrap()
+            .claims(),
+        "rsa"
+    );
+    assert_eq!(
+        set.verify(&ec_token, &[Algorithm::RS256, Algorithm::ES256])
+            .unwrap()
+            .claims(),
+        "ec"
+    );
+    assert!(Jwt::verify(&ec_token, &set, &[Algorithm::ES256]).is_ok());
+
+    // The key's own alg is enforced when it is picked by kid as well
+    let pss = Jwt::new("pss", &rsa, Some(Algorithm::PS256));
+    let both = [Algorithm::RS256, Algorithm::PS256];
+    assert!(set.verify(&pss.finalize().unwrap(), &both).is_err());
+    let err = set
+        .verify(&pss.with_kid("rsa").finalize().unwrap(), &both)
+        .unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::DisallowedAlgorithm("PS256".to_string()))
+    );
+    let rs256 = Jwt::new("rs256", &rsa, None).with_kid("rsa");
+    assert!(set.verify(&rs256.finalize().unwrap(), &both).is_ok());
+
+    let other = EcKey::from_pem("random_ec384_for_testing").unwrap();
+    let other_token = Jwt::new("other", other, None).finalize().unwrap();
+    assert!(set.verify(&other_token, &[Algorithm::ES384]).is_err());
+    assert!(JwkSet::from_str(r#"{"not keys": []}"#).is_err());
+}
+
+#[test]
+fn test_jwk_thumbprint() {
+    // RFC 7638 3.1
+    let jwk = Jwk::from_str(
+        r#"{"kty":"RSA","alg":"RS256","kid":"2011-04-29","e":"AQAB",
+        "n":"0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        jwk.thumbprint().unwrap(),
+        "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+    );
+
+    // RFC 8037 A.3
+    let jwk = Jwk::from_str(
+        r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        jwk.thumbprint().unwrap(),
+        "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+    );
+
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    assert_eq!(
+        ec.to_jwk().unwrap().thumbprint().unwrap(),
+        ec.to_public_jwk()
+            .unwrap()
+            .with_kid("1")
+            .thumbprint()
+            .unwrap()
+    );
+    assert!(Jwk::from_str(r#"{"kty":"EC","crv":"P-256"}"#)
+        .unwrap()
+        .thumbprint()
+        .is_err());
+}
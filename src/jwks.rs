@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{
+    http, Algorithm, DecodedJwt, JwkKey, JwkSet, Jwt, JwtErr, ValidationError, ValidationOptions,
+};
+
+/// Fetches and caches the JWK Set published at a URL, e.g. an IdP's `jwks_uri`
+///
+/// Keys are fetched on first use and cached by `kid`. A token naming a `kid` missing from the
+/// cache triggers a refetch, so rotated keys are picked up, but at most once per
+/// `min_refresh_interval` so tokens with made up `kid`s cannot be used to hammer the IdP.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, JwksClient};
+///
+/// let client = JwksClient::new("https://www.googleapis.com/oauth2/v3/certs");
+/// # let token = "";
+/// let decoded = client.verify(token, &[Algorithm::RS256]).unwrap();
+/// println!("{}", decoded.claims());
+/// ```
+pub struct JwksClient {
+    url: String,
+    timeout: Duration,
+    min_refresh_interval: Duration,
+    set: RwLock<Option<Arc<JwkSet>>>,
+    // Held while fetching so concurrent misses share a single request
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl JwksClient {
+    /// Only `https` URLs are accepted, plain `http` is allowed for loopback hosts
+    pub fn new(url: &str) -> Self {
+        JwksClient {
+            url: url.to_string(),
+            timeout: Duration::from_secs(10),
+            min_refresh_interval: Duration::from_secs(60),
+            set: RwLock::new(None),
+            last_fetch: Mutex::new(None),
+        }
+    }
+
+    /// Connect, read and write timeout for fetching the set, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Minimum time between two fetches triggered by unknown `kid`s, 60 seconds by default
+    pub fn with_min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The cached set, fetching it if nothing has been fetched yet
+    pub fn key_set(&self) -> Result<Arc<JwkSet>, JwtErr> {
+        if let Some(set) = self.cached() {
+            return Ok(set);
+        }
+        self.refresh_if_due(true)?;
+        self.cached()
+            .ok_or_else(|| JwtErr::from("JWK set could not be fetched"))
+    }
+
+    /// Fetches the set now, regardless of the refresh interval
+    pub fn refresh(&self) -> Result<Arc<JwkSet>, JwtErr> {
+        let mut last_fetch = self.last_fetch.lock().unwrap_or_else(|e| e.into_inner());
+        let set = self.fetch()?;
+        *last_fetch = Some(Instant::now());
+        Ok(set)
+    }
+
+    /// Verifies `token` with the cached key named by its `kid`, refetching the set on a miss
+    ///
+    /// Tokens without a `kid` are checked against every key of the set, see `JwkSet::verify`.
+    pub fn verify(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        self.verify_with(token, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same key lookup as `verify`, checking the token against `options`
+    pub fn verify_with(
+        &self,
+        token: &str,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, _) = Jwt::decode_unverified(token)?;
+        match header.kid() {
+            Some(kid) => {
+                let set = self.key_set_with(kid)?;
+                match set.find_by_kid(kid) {
+                    Some(_) => set.verify_with(token, options),
+                    None => Err(JwtErr::described(
+                        ValidationError::UnknownKid(kid.to_string()),
+                        &format!("No key with kid {} at {}", kid, self.url),
+                    )),
+                }
+            }
+            None => self.key_set()?.verify_with(token, options),
+        }
+    }
+
+    /// Looks up the key for `kid`, refetching the set on a miss
+    pub fn find_by_kid(&self, kid: &str) -> Result<Option<CachedKey>, JwtErr> {
+        let set = self.key_set_with(kid)?;
+        let found = set.find_by_kid(kid).is_some();
+        Ok(if found {
+            Some(CachedKey {
+                set,
+                kid: kid.to_string(),
+            })
+        } else {
+            None
+        })
+    }
+
+    /// The cached set if it is already fetched and, with `kid` given, holds that key
+    pub(crate) fn cached_with(&self, kid: Option<&str>) -> Option<Arc<JwkSet>> {
+        let set = self.cached()?;
+        match kid {
+            Some(kid) if set.find_by_kid(kid).is_none() => None,
+            _ => Some(set),
+        }
+    }
+
+    fn key_set_with(&self, kid: &str) -> Result<Arc<JwkSet>, JwtErr> {
+        let set = self.key_set()?;
+        if set.find_by_kid(kid).is_some() {
+            return Ok(set);
+        }
+        self.refresh_if_due(false)?;
+        self.key_set()
+    }
+
+    fn cached(&self) -> Option<Arc<JwkSet>> {
+        self.set.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn refresh_if_due(&self, initial: bool) -> Result<(), JwtErr> {
+        let mut last_fetch = self.last_fetch.lock().unwrap_or_else(|e| e.into_inner());
+        // Another thread may have fetched while we waited for the lock
+        if initial && self.cached().is_some() {
+            return Ok(());
+        }
+        if let Some(at) = *last_fetch {
+            if !initial && at.elapsed() < self.min_refresh_interval {
+                return Ok(());
+            }
+        }
+        // Failed fetches count as well, a down IdP is not retried on every token
+        *last_fetch = Some(Instant::now());
+        self.fetch()?;
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<Arc<JwkSet>, JwtErr> {
+        let response = http::request(
+            "GET",
+            &self.url,
+            &[("Accept", "application/json")],
+            b"",
+            self.timeout,
+        )?;
+        if response.status != 200 {
+            return Err(JwtErr::from(
+                format!(
+                    "Fetching {} failed with status {}",
+                    self.url, response.status
+                )
+                .as_str(),
+            ));
+        }
+        let body = String::from_utf8(response.body)
+            .map_err(|_| JwtErr::from("JWK set is not valid UTF-8"))?;
+        let set = Arc::new(JwkSet::from_str(&body)?);
+        *self.set.write().unwrap_or_else(|e| e.into_inner()) = Some(set.clone());
+        Ok(set)
+    }
+}
+
+/// Key of a `JwksClient` set, kept alive independently of later refreshes
+pub struct CachedKey {
+    set: Arc<JwkSet>,
+    kid: String,
+}
+
+impl std::ops::Deref for CachedKey {
+    type Target = JwkKey;
+    fn deref(&self) -> &JwkKey {
+        // Only built for a kid present in the set
+        self.set.find_by_kid(&self.kid).unwrap()
+    }
+}
+
+/// Verifies tokens with the JWK Set named by their `jku` header, for hosts on an allow-list only
+///
+/// The `jku` comes from the unverified token, so without the allow-list anyone could point it at
+/// their own keys. Hosts are compared exactly, without subdomain matching.
+///
+/// Every URL gets its own cached `JwksClient`, keyed and fetched without its query or fragment, so
+/// `?1`, `?2`, ... all name the same set. At most `max_sets` sets are kept, the least recently used
+/// one is dropped for a new one. A set not cached yet is only fetched if no other new set was
+/// fetched from its host within `min_new_set_interval`, tokens naming fresh paths can not be used
+/// to hammer the host.
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, JkuVerifier};
+///
+/// let verifier = JkuVerifier::new(&["login.example.com"]);
+/// # let token = "";
+/// let decoded = verifier.verify(token, &[Algorithm::RS256]).unwrap();
+/// ```
+pub struct JkuVerifier {
+    allowed_hosts: Vec<String>,
+    timeout: Duration,
+    max_sets: usize,
+    min_new_set_interval: Duration,
+    cache: Mutex<JkuCache>,
+}
+
+#[derive(Default)]
+struct JkuCache {
+    // By normalized URL, with the time each client was last used
+    clients: HashMap<String, (Arc<JwksClient>, Instant)>,
+    // By host, when a set not cached before was last fetched from it
+    new_sets: HashMap<String, Instant>,
+}
+
+impl JkuVerifier {
+    pub fn new(allowed_hosts: &[&str]) -> Self {
+        JkuVerifier {
+            allowed_hosts: allowed_hosts
+                .iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+            timeout: Duration::from_secs(10),
+            max_sets: 16,
+            min_new_set_interval: Duration::from_secs(60),
+            cache: Mutex::new(JkuCache::default()),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of sets kept cached, 16 by default
+    pub fn with_max_sets(mut self, max_sets: usize) -> Self {
+        self.max_sets = max_sets.max(1);
+        self
+    }
+
+    /// Minimum time between fetching two sets not cached before from one host, 60 seconds by default
+    pub fn with_min_new_set_interval(mut self, interval: Duration) -> Self {
+        self.min_new_set_interval = interval;
+        self
+    }
+
+    /// Checks the `jku` of `token` against the allow-list, then verifies it with that set
+    pub fn verify(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        self.verify_with(token, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same `jku` checks as `verify`, checking the token against `options`
+    pub fn verify_with(
+        &self,
+        token: &str,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, _) = Jwt::decode_unverified(token)?;
+        let jku = header
+            .jku()
+            .ok_or_else(|| JwtErr::from("Token has no jku header"))?;
+        let url = http::Url::parse(jku)?;
+        if !self.allowed_hosts.contains(&url.host) {
+            return Err(JwtErr::from(
+                format!("jku host {} is not allowed", url.host).as_str(),
+            ));
+        }
+        self.client(&url)?.verify_with(token, options)
+    }
+
+    fn client(&self, url: &http::Url) -> Result<Arc<JwksClient>, JwtErr> {
+        let path = url.path.split(['?', '#']).next().unwrap_or("/");
+        let scheme = if url.tls { "https" } else { "http" };
+        let normalized = format!("{}://{}{}", scheme, url.authority(), path);
+
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((client, used)) = cache.clients.get_mut(&normalized) {
+            *used = now;
+            return Ok(client.clone());
+        }
+        if let Some(last) = cache.new_sets.get(&url.host) {
+            if now.duration_since(*last) < self.min_new_set_interval {
+                return Err(JwtErr::from(
+                    format!("Too many new jku sets from {}, retry later", url.host).as_str(),
+                ));
+            }
+        }
+        cache.new_sets.insert(url.host.clone(), now);
+        if cache.clients.len() >= self.max_sets {
+            let oldest = cache
+                .clients
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                cache.clients.remove(&oldest);
+            }
+        }
+        let client = Arc::new(JwksClient::new(&normalized).with_timeout(self.timeout));
+        cache.clients.insert(normalized, (client.clone(), now));
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+fn jwks_of(keys: &[(&crate::EcKey, &str)]) -> String {
+    let keys: Vec<serde_json::Value> = keys
+        .iter()
+        .map(|(key, kid)| {
+            serde_json::from_str(
+                &key.to_public_jwk()
+                    .unwrap()
+                    .with_kid(kid)
+                    .to_json()
+                    .unwrap(),
+            )
+            .unwrap()
+        })
+        .collect();
+    serde_json::json!({ "keys": keys }).to_string()
+}
+
+#[test]
+fn test_jwks_client_caches_and_refreshes() {
+    use crate::{EcKey, VerifyingKey};
+    use std::sync::atomic::Ordering;
+
+    let first = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let second = EcKey::from_pem("random_ec384_for_testing").unwrap();
+    let body = Arc::new(Mutex::new(jwks_of(&[(&first, "first")])));
+    let (url, hits) = http::serve(body.clone());
+    let client = JwksClient::new(&url).with_min_refresh_interval(Duration::ZERO);
+    assert_eq!(client.url(), url);
+
+    let signature = crate::SigningKey::sign(&first, &crate::Algorithm::ES256, b"input").unwrap();
+    let key = client.find_by_kid("first").unwrap().unwrap();
+    assert!(key
+        .verify(&crate::Algorithm::ES256, b"input", &signature)
+        .unwrap());
+    assert!(client.find_by_kid("first").unwrap().is_some());
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // An unknown kid refetches, picking up the rotated key
+    *body.lock().unwrap() = jwks_of(&[(&first, "first"), (&second, "second")]);
+    assert!(client.find_by_kid("second").unwrap().is_some());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    assert_eq!(client.key_set().unwrap().len(), 2);
+
+    let token = Jwt::new("claims", second, None).finalize().unwrap();
+    assert_eq!(
+        client.verify(&token, &[Algorithm::ES384]).unwrap().claims(),
+        "claims"
+    );
+    assert_eq!(client.refresh().unwrap().len(), 2);
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_jwks_client_rate_limits_misses() {
+    use crate::EcKey;
+    use std::sync::atomic::Ordering;
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let (url, hits) = http::serve(Arc::new(Mutex::new(jwks_of(&[(&key, "only")]))));
+    let client = JwksClient::new(&url);
+
+    for _ in 0..5 {
+        assert!(client.find_by_kid("made-up").unwrap().is_none());
+    }
+    // The set was just fetched, misses within the interval do not refetch it
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    assert!(JwksClient::new("http://example.com/jwks")
+        .key_set()
+        .is_err());
+    let err = JwksClient::new("http://127.evil.com/jwks")
+        .key_set()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Refusing plain http for 127.evil.com, use https"
+    );
+}
+
+#[test]
+fn test_jku_verifier() {
+    use crate::EcKey;
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let (url, hits) = http::serve(Arc::new(Mutex::new(jwks_of(&[(&key, "k1")]))));
+    let token = Jwt::new("claims", key, None)
+        .with_kid("k1")
+        .with_jku(&url)
+        .finalize()
+        .unwrap();
+    assert_eq!(
+        Jwt::decode_unverified(&token).unwrap().0.jku(),
+        Some(url.as_str())
+    );
+
+    let verifier = JkuVerifier::new(&["127.0.0.1"]);
+    assert_eq!(
+        verifier
+            .verify(&token, &[Algorithm::ES256])
+            .unwrap()
+            .claims(),
+        "claims"
+    );
+    assert!(verifier.verify(&token, &[Algorithm::ES256]).is_ok());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // The query does not make a new set
+    let with_query = |query: &str| {
+        let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+        Jwt::new("claims", key, None)
+            .with_kid("k1")
+            .with_jku(&format!("{}{}", url, query))
+            .finalize()
+            .unwrap()
+    };
+    for query in ["?1", "?2", "#3"] {
+        assert!(verifier
+            .verify(&with_query(query), &[Algorithm::ES256])
+            .is_ok());
+    }
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // Nor is a new path fetched right after another one of the same host
+    let other_path = with_query("/other");
+    assert!(verifier.verify(&other_path, &[Algorithm::ES256]).is_err());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // Past the limit the least recently used set is dropped and fetched again when needed
+    let verifier = JkuVerifier::new(&["127.0.0.1"])
+        .with_max_sets(1)
+        .with_min_new_set_interval(Duration::ZERO);
+    assert!(verifier.verify(&token, &[Algorithm::ES256]).is_ok());
+    assert!(verifier.verify(&other_path, &[Algorithm::ES256]).is_ok());
+    assert!(verifier.verify(&token, &[Algorithm::ES256]).is_ok());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 4);
+    assert_eq!(verifier.cache.lock().unwrap().clients.len(), 1);
+
+    // Nothing is fetched for hosts off the list
+    let verifier = JkuVerifier::new(&["login.example.com", "127.0.0.2"]);
+    assert!(verifier.verify(&token, &[Algorithm::ES256]).is_err());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 4);
+
+    // Header lines can not be smuggled into the request through the path
+    let verifier = JkuVerifier::new(&["127.0.0.1"]).with_min_new_set_interval(Duration::ZERO);
+    let smuggled = with_query(" HTTP/1.1\r\nX: y\r\n\r\nGET /admin");
+    let err = verifier
+        .verify(&smuggled, &[Algorithm::ES256])
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "URLs may only contain visible ASCII characters"
+    );
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 4);
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let no_jku = Jwt::new("claims", key, None).finalize().unwrap();
+    assert!(JkuVerifier::new(&["127.0.0.1"])
+        .verify(&no_jku, &[Algorithm::ES256])
+        .is_err());
+}
@@ -0,0 +1,383 @@
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::verify::{decode_segment, verify_segments};
+use crate::{
+    resolve_key, Algorithm, DecodedJwt, Jwt, JwtErr, SigningKey, ValidationOptions, VerifyingKey,
+};
+
+// RFC 7515 7.2, the JSON serializations carry the same protected header, payload and signature
+// segments as the compact one. Members that are not integrity protected are not written.
+
+impl<T, K, H> Jwt<T, K, H>
+where
+    T: Serialize,
+    K: SigningKey,
+    H: Serialize,
+{
+    /// Signs the token like `finalize`, producing the general JWS JSON serialization
+    ///
+    /// The output reads `{"payload": .., "signatures": [{"protected": .., "signature": ..}]}`.
+    /// An unencoded payload, see `with_unencoded_payload`, is written as a JSON string as is.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{HmacKey, Jwt};
+    ///
+    /// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let json = Jwt::new("claims", key, None).finalize_json_general().unwrap();
+    /// let jws: serde_json::Value = serde_json::from_str(&json).unwrap();
+    /// assert_eq!(jws["signatures"].as_array().unwrap().len(), 1);
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn finalize_json_general(&self) -> Result<String, JwtErr> {
+        self.finalize_json_general_with(&[])
+    }
+
+    /// Like `finalize_json_general`, adding a signature over the same payload for each of `keys`
+    ///
+    /// The token's own key signs first. The other signatures use the key's `default_algorithm`
+    /// and `kid`, the rest of their protected header is the token's, e.g. an RSA and an EC key
+    /// sign side by side while verifiers migrate.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, EcKey, Jwt, RSAKey, ValidationOptions, VerifyingKey};
+    ///
+    /// let rsa = || RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    /// let ec = || EcKey::from_pem("random_ec_for_testing").unwrap();
+    /// let json = Jwt::new("claims", rsa(), None)
+    ///     .finalize_json_general_with(&[&ec()])
+    ///     .unwrap();
+    ///
+    /// assert!(Jwt::verify_json(&json, &ec(), &[Algorithm::ES256]).is_ok());
+    /// let (rsa, ec) = (rsa(), ec());
+    /// let both: [&dyn VerifyingKey; 2] = [&rsa, &ec];
+    /// let options = ValidationOptions::new(&[Algorithm::RS256, Algorithm::ES256]);
+    /// assert_eq!(Jwt::verify_json_all_with(&json, &both, &options).unwrap().len(), 2);
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn finalize_json_general_with(&self, keys: &[&dyn SigningKey]) -> Result<String, JwtErr> {
+        let (protected, payload, signature) = self.json_parts()?;
+        let mut signatures = vec![serde_json::json!({
+            "protected": protected,
+            "signature": signature,
+        })];
+        for key in keys {
+            let algo = key.default_algorithm();
+            let mut header = self.header(None)?;
+            header.alg = algo;
+            header.kid = key.kid().map(String::from);
+            let protected = self.encode_protected(&header)?;
+            let input = format!("{}.{}", protected, payload);
+            signatures.push(serde_json::json!({
+                "protected": protected,
+                "signature": self.encoder.encode_segment(&key.sign(&algo, input.as_bytes())?),
+            }));
+        }
+        Ok(serde_json::json!({ "payload": payload, "signatures": signatures }).to_string())
+    }
+
+    /// Signs the token like `finalize`, producing the flattened JWS JSON serialization
+    ///
+    /// The output reads `{"payload": .., "protected": .., "signature": ..}`, see `Jwt::verify_json`.
+    pub fn finalize_json_flattened(&self) -> Result<String, JwtErr> {
+        let (protected, payload, signature) = self.json_parts()?;
+        Ok(serde_json::json!({
+            "payload": payload,
+            "protected": protected,
+            "signature": signature,
+        })
+        .to_string())
+    }
+
+    /// Protected header, payload and signature, the three segments of the compact token
+    fn json_parts(&self) -> Result<(String, String, String), JwtErr> {
+        let key = resolve_key(&self.pkey)?;
+        let input = self.input(key.kid(), None)?;
+        let signature = self.sign(key, &input)?;
+        let (protected, payload) = input.split_once('.').unwrap_or((&input, ""));
+        Ok((protected.to_string(), payload.to_string(), signature))
+    }
+}
+
+impl Jwt<serde_json::Value> {
+    /// Checks a token in either JWS JSON serialization, like `verify` does compact ones
+    ///
+    /// Of several signatures, the first one `key` verifies is used and its header returned. Only
+    /// the `protected` header counts, an unprotected `header` is tolerated but ignored and has to
+    /// be disjoint from the protected one.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt};
+    ///
+    /// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let json = Jwt::new("claims", key(), None).finalize_json_flattened().unwrap();
+    /// assert_eq!(Jwt::verify_json(&json, &key(), &[Algorithm::HS256]).unwrap().claims(), "claims");
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn verify_json<V: VerifyingKey + ?Sized>(
+        jws: &str,
+        key: &V,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        Self::verify_json_with(jws, key, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same checks as `verify_json`, then the header and claims against `options`
+    pub fn verify_json_with<V: VerifyingKey + ?Sized>(
+        jws: &str,
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        Self::verify_json_any_with(jws, &[key], options)
+    }
+
+    /// Accepts the token if any of `keys` verifies any of its signatures, checked in key order
+    pub fn verify_json_any_with<V: VerifyingKey + ?Sized>(
+        jws: &str,
+        keys: &[&V],
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let jws = JsonJws::parse(jws)?;
+        let mut last = JwtErr::from("No key to verify the signatures with");
+        for key in keys {
+            match jws.verify(*key, options) {
+                Ok(decoded) => return Ok(decoded),
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    /// Accepts the token only if each of `keys` verifies one of its signatures
+    ///
+    /// Returns the token decoded under the signature each key verified, in the order of `keys`.
+    pub fn verify_json_all_with<V: VerifyingKey + ?Sized>(
+        jws: &str,
+        keys: &[&V],
+        options: &ValidationOptions,
+    ) -> Result<Vec<DecodedJwt<serde_json::Value>>, JwtErr> {
+        let jws = JsonJws::parse(jws)?;
+        if keys.is_empty() {
+            return Err(JwtErr::from("No key to verify the signatures with"));
+        }
+        keys.iter().map(|key| jws.verify(*key, options)).collect()
+    }
+}
+
+/// Payload and signatures of either JSON serialization, flattened ones have a single signature
+struct JsonJws {
+    payload: String,
+    signatures: Vec<(String, String)>,
+}
+
+impl JsonJws {
+    fn parse(jws: &str) -> Result<JsonJws, JwtErr> {
+        let jws: serde_json::Value = serde_json::from_str(jws)?;
+        let member = |value: &serde_json::Value, name: &str| {
+            value[name].as_str().map(String::from).ok_or_else(|| {
+                JwtErr::from(format!("JWS JSON serialization has no {} member", name).as_str())
+            })
+        };
+        let signature = |value: &serde_json::Value| {
+            let protected = member(value, "protected")?;
+            check_unprotected(&protected, value.get("header"))?;
+            Ok((protected, member(value, "signature")?))
+        };
+        let signatures = match jws.get("signatures") {
+            Some(serde_json::Value::Array(signatures)) if !signatures.is_empty() => signatures
+                .iter()
+                .map(signature)
+                .collect::<Result<_, JwtErr>>()?,
+            Some(_) => return Err(JwtErr::from("JWS signatures member is malformed")),
+            None => vec![signature(&jws)?],
+        };
+        Ok(JsonJws {
+            payload: member(&jws, "payload")?,
+            signatures,
+        })
+    }
+
+    /// Decodes the token under the first signature `key` verifies
+    fn verify<V: VerifyingKey + ?Sized>(
+        &self,
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let mut last = None;
+        for (protected, signature) in &self.signatures {
+            let verified = verify_segments(protected, &self.payload, signature, key, options)
+                .and_then(|decoded| {
+                    options.validate(decoded.header(), decoded.claims())?;
+                    Ok(decoded)
+                });
+            match verified {
+                Ok(decoded) => return Ok(decoded),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| JwtErr::from("JWS has no signatures")))
+    }
+}
+
+/// RFC 7515 7.2.1, header members may not be both protected and unprotected
+fn check_unprotected(
+    protected: &str,
+    unprotected: Option<&serde_json::Value>,
+) -> Result<(), JwtErr> {
+    let unprotected = match unprotected {
+        None => return Ok(()),
+        Some(serde_json::Value::Object(header)) => header,
+        Some(_) => return Err(JwtErr::from("Unprotected JWS header is not an object")),
+    };
+    let protected: serde_json::Map<String, serde_json::Value> = decode_segment(protected)?;
+    match unprotected
+        .keys()
+        .find(|name| protected.contains_key(*name))
+    {
+        Some(name) => Err(JwtErr::from(
+            format!("Header member {} is both protected and unprotected", name).as_str(),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_json_general_serialization() {
+    use crate::{EcKey, HmacKey};
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let jwt = Jwt::new(serde_json::json!({"sub": "me"}), key, None).with_kid("ec-1");
+    let json: serde_json::Value =
+        serde_json::from_str(&jwt.finalize_json_general().unwrap()).unwrap();
+    assert_eq!(json.as_object().unwrap().len(), 2);
+    let signatures = json["signatures"].as_array().unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert!(signatures[0].get("header").is_none());
+
+    // The segments form a valid compact token again
+    let compact = format!(
+        "{}.{}.{}",
+        signatures[0]["protected"].as_str().unwrap(),
+        json["payload"].as_str().unwrap(),
+        signatures[0]["signature"].as_str().unwrap()
+    );
+    let decoded = Jwt::verify(&compact, &jwt.pkey, &[Algorithm::ES256]).unwrap();
+    assert_eq!(decoded.header().kid(), Some("ec-1"));
+    assert_eq!(decoded.claims()["sub"], "me");
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jwt = Jwt::new("1.5", key, None).with_unencoded_payload();
+    let json: serde_json::Value =
+        serde_json::from_str(&jwt.finalize_json_general().unwrap()).unwrap();
+    assert_eq!(json["payload"], r#""1.5""#);
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_json_flattened_serialization() {
+    use crate::{Algorithm, HmacKey};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jwt = Jwt::new(serde_json::json!({"sub": "me"}), key(), None);
+    let flattened = jwt.finalize_json_flattened().unwrap();
+    let mut json: serde_json::Value = serde_json::from_str(&flattened).unwrap();
+    assert_eq!(json.as_object().unwrap().len(), 3);
+    let decoded = Jwt::verify_json(&flattened, &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded.header().alg(), "HS256");
+    assert_eq!(decoded.claims()["sub"], "me");
+    let options = ValidationOptions::new(&[Algorithm::HS512]);
+    assert!(Jwt::verify_json_with(&flattened, &key(), &options).is_err());
+
+    json["header"] = serde_json::json!({"kid": "unprotected"});
+    let decoded = Jwt::verify_json(&json.to_string(), &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded.header().kid(), None);
+    json["header"] = serde_json::json!({"alg": "none"});
+    assert!(Jwt::verify_json(&json.to_string(), &key(), &[Algorithm::HS256]).is_err());
+    json["header"] = "kid".into();
+    assert!(Jwt::verify_json(&json.to_string(), &key(), &[Algorithm::HS256]).is_err());
+
+    json.as_object_mut().unwrap().remove("header");
+    json["payload"] = "eyJzdWIiOiJ5b3UifQ".into();
+    assert!(Jwt::verify_json(&json.to_string(), &key(), &[Algorithm::HS256]).is_err());
+    json.as_object_mut().unwrap().remove("payload");
+    assert!(Jwt::verify_json(&json.to_string(), &key(), &[Algorithm::HS256]).is_err());
+    assert!(Jwt::verify_json(&jwt.finalize().unwrap(), &key(), &[Algorithm::HS256]).is_err());
+
+    // Unencoded payloads may hold a '.' in the JSON serializations
+    let jwt = Jwt::new("1.5", key(), None).with_unencoded_payload();
+    let decoded = Jwt::verify_json(
+        &jwt.finalize_json_flattened().unwrap(),
+        &key(),
+        &[Algorithm::HS256],
+    )
+    .unwrap();
+    assert_eq!(decoded.claims(), "1.5");
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_json_multiple_signatures() {
+    use crate::{Algorithm, EcKey, HmacKey, RSAKey};
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let hmac = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jws = Jwt::new(serde_json::json!({"sub": "me"}), &rsa, None)
+        .with_typ("doc+jwt")
+        .with_kid("rsa-1")
+        .finalize_json_general_with(&[&ec])
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&jws).unwrap();
+    assert_eq!(json["signatures"].as_array().unwrap().len(), 2);
+
+    let decoded = Jwt::verify_json(&jws, &ec, &[Algorithm::ES256]).unwrap();
+    assert_eq!(decoded.header().alg(), "ES256");
+    assert_eq!(decoded.header().typ(), Some("doc+jwt"));
+    assert_eq!(decoded.header().kid(), None);
+    assert_eq!(
+        Jwt::verify_json(&jws, &rsa, &[Algorithm::RS256])
+            .unwrap()
+            .header()
+            .kid(),
+        Some("rsa-1")
+    );
+    assert!(Jwt::verify_json(&jws, &hmac, &[Algorithm::HS256]).is_err());
+
+    let options = ValidationOptions::new(&[Algorithm::HS256, Algorithm::RS256, Algorithm::ES256]);
+    let any = |keys: &[&dyn VerifyingKey]| Jwt::verify_json_any_with(&jws, keys, &options);
+    let all = |keys: &[&dyn VerifyingKey]| Jwt::verify_json_all_with(&jws, keys, &options);
+    assert_eq!(any(&[&hmac, &ec]).unwrap().header().alg(), "ES256");
+    assert!(any(&[&hmac]).is_err());
+    assert!(any(&[]).is_err());
+    let decoded = all(&[&ec, &rsa]).unwrap();
+    assert_eq!(decoded[0].header().alg(), "ES256");
+    assert_eq!(decoded[1].header().alg(), "RS256");
+    assert!(all(&[&ec, &hmac]).is_err());
+    assert!(all(&[]).is_err());
+
+    // A pinned algorithm skips the other signatures
+    let options = ValidationOptions::new(&[Algorithm::RS256]);
+    assert!(Jwt::verify_json_with(&jws, &ec, &options).is_err());
+    assert!(Jwt::verify_json_with(&jws, &rsa, &options).is_ok());
+
+    for malformed in [
+        r#"{"payload": "e30", "signatures": []}"#,
+        r#"{"payload": "e30", "signatures": {}}"#,
+        r#"{"payload": "e30", "signatures": [{"protected": "e30"}]}"#,
+    ] {
+        assert!(Jwt::verify_json(malformed, &ec, &[Algorithm::ES256]).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    Algorithm, DecodedJwt, IncrementalSigner, Jwt, JwtErr, SigningKey, ValidationError,
+    ValidationOptions, VerifyingKey,
+};
+
+/// Set of keys rotated on a schedule, each with an id and an activation time
+///
+/// The key activated most recently signs, and its id ends up as the token's `kid`. A key stops
+/// signing once the next one activates but keeps verifying for the `overlap` passed to
+/// `Keyring::new`, which should cover the lifetime of the tokens it issued.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, HmacKey, Jwt, Keyring};
+/// use std::time::{Duration, SystemTime};
+///
+/// let day = Duration::from_secs(24 * 60 * 60);
+/// let mut keyring = Keyring::new(day);
+/// keyring.add("2024-01", HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap(),
+///             SystemTime::now() - 40 * day);
+/// keyring.add("2024-02", HmacKey::from_bytes(b"another-secret-of-thirty-two-bytes").unwrap(),
+///             SystemTime::now() - 10 * day);
+///
+/// let token = Jwt::new("claims", &keyring, None).finalize().unwrap();
+/// let decoded = keyring.verify(&token, &[Algorithm::HS256]).unwrap();
+/// assert_eq!(decoded.header().kid(), Some("2024-02"));
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+pub struct Keyring<K> {
+    overlap: Duration,
+    // Sorted by activation time
+    entries: Vec<Entry<K>>,
+}
+
+struct Entry<K> {
+    kid: String,
+    key: K,
+    active_from: SystemTime,
+}
+
+impl<K> Keyring<K> {
+    pub fn new(overlap: Duration) -> Self {
+        Keyring {
+            overlap,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds `key` under `kid`, signing from `active_from` until the next key activates
+    ///
+    /// Keys may be added ahead of time, they only sign once their activation time has passed.
+    pub fn add(&mut self, kid: &str, key: K, active_from: SystemTime) {
+        let idx = self
+            .entries
+            .partition_point(|entry| entry.active_from <= active_from);
+        self.entries.insert(
+            idx,
+            Entry {
+                kid: kid.to_string(),
+                key,
+                active_from,
+            },
+        );
+    }
+
+    /// Drops the key with `kid`, returning it
+    pub fn remove(&mut self, kid: &str) -> Option<K> {
+        let idx = self.entries.iter().position(|entry| entry.kid == kid)?;
+        Some(self.entries.remove(idx).key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Id and key currently used for signing
+    pub fn current(&self) -> Option<(&str, &K)> {
+        self.current_at(SystemTime::now())
+    }
+
+    /// Key with `kid` if it is still within its verification window
+    pub fn find_by_kid(&self, kid: &str) -> Option<&K> {
+        self.verifying_at(SystemTime::now())
+            .find(|(id, _)| *id == kid)
+            .map(|(_, key)| key)
+    }
+
+    fn current_at(&self, now: SystemTime) -> Option<(&str, &K)> {
+        self.entry_at(now)
+            .map(|entry| (entry.kid.as_str(), &entry.key))
+    }
+
+    fn entry_at(&self, now: SystemTime) -> Option<&Entry<K>> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.active_from <= now)
+    }
+
+    /// Active keys whose successor activated less than `overlap` ago, the current key included
+    fn verifying_at(&self, now: SystemTime) -> impl Iterator<Item = (&str, &K)> {
+        let overlap = self.overlap;
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(idx, entry)| {
+                entry.active_from <= now
+                    && match self.entries.get(idx + 1) {
+                        Some(next) if next.active_from <= now => next
+                            .active_from
+                            .checked_add(overlap)
+                            .is_none_or(|end| end > now),
+                        _ => true,
+                    }
+            })
+            .map(|(_, entry)| (entry.kid.as_str(), &entry.key))
+    }
+
+    fn no_current_key() -> JwtErr {
+        JwtErr::from("Keyring has no active key")
+    }
+}
+
+impl<K: VerifyingKey> Keyring<K> {
+    /// Verifies `token` with the key named by its `kid` header, see `Jwt::verify`
+    ///
+    /// Tokens without a `kid` are checked against every key within its verification window.
+    pub fn verify(
+        &self,
+        token: &str,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        self.verify_with(token, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same key lookup as `verify`, checking the token against `options`
+    pub fn verify_with(
+        &self,
+        token: &str,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, _) = Jwt::decode_unverified(token)?;
+        match header.kid() {
+            Some(kid) => match self.find_by_kid(kid) {
+                Some(key) => Jwt::verify_with(token, key, options),
+                None => Err(JwtErr::described(
+                    ValidationError::UnknownKid(kid.to_string()),
+                    &format!("No key with kid {} in the keyring", kid),
+                )),
+            },
+            None => Jwt::verify_with(token, self, options),
+        }
+    }
+}
+
+impl<K: SigningKey> SigningKey for Keyring<K> {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        match self.current() {
+            Some((_, key)) => key.sign(algo, input),
+            None => Err(Self::no_current_key()),
+        }
+    }
+
+    /// Algorithm of the current key, `RS256` if no key is active yet, signing fails then anyway
+    fn default_algorithm(&self) -> Algorithm {
+        self.current()
+            .map(|(_, key)| key.default_algorithm())
+            .unwrap_or(Algorithm::RS256)
+    }
+
+    fn kid(&self) -> Option<&str> {
+        self.current().map(|(kid, _)| kid)
+    }
+
+    /// The current key together with its id, a rotation can not come between `kid` and `sign`
+    fn signing_key(&self) -> Result<Option<&dyn SigningKey>, JwtErr> {
+        match self.entry_at(SystemTime::now()) {
+            Some(entry) => Ok(Some(entry)),
+            None => Err(Self::no_current_key()),
+        }
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        match self.current() {
+            Some((_, key)) => key.incremental_signer(algo),
+            None => Err(Self::no_current_key()),
+        }
+    }
+}
+
+impl<K: SigningKey> SigningKey for Entry<K> {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        self.key.sign(algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.key.default_algorithm()
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.kid)
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        self.key.incremental_signer(algo)
+    }
+}
+
+impl<K: VerifyingKey> VerifyingKey for Keyring<K> {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        Ok(self
+            .verifying_at(SystemTime::now())
+            .any(|(_, key)| key.verify(algo, input, signature).unwrap_or(false)))
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_keyring_rotation() {
+    use crate::HmacKey;
+
+    let day = Duration::from_secs(24 * 60 * 60);
+    let now = SystemTime::now();
+    let mut keyring = Keyring::new(day);
+    assert!(keyring.is_empty());
+    assert!(keyring.current().is_none());
+    assert!(keyring.sign(&Algorithm::HS256, b"input").is_err());
+
+    let secret = |s: &[u8]| HmacKey::from_bytes(s).unwrap();
+    // Added out of order on purpose
+    keyring.add(
+        "march",
+        secret(b"march-secret-of-thirty-two-bytes!"),
+        now + 20 * day,
+    );
+    keyring.add(
+        "january",
+        secret(b"january-secret-of-thirty-two-byte"),
+        now - 40 * day,
+    );
+    keyring.add(
+        "february",
+        secret(b"february-secret-of-thirty-two-byt"),
+        now - 10 * day,
+    );
+    assert_eq!(keyring.len(), 3);
+
+    assert_eq!(keyring.current_at(now).unwrap().0, "february");
+    assert_eq!(keyring.current_at(now + 21 * day).unwrap().0, "march");
+    assert_eq!(keyring.current_at(now - 20 * day).unwrap().0, "january");
+    assert!(keyring.current_at(now - 50 * day).is_none());
+    assert_eq!(SigningKey::kid(&keyring), Some("february"));
+    assert_eq!(keyring.default_algorithm(), Algorithm::HS256);
+
+    let kids = |at| {
+        keyring
+            .verifying_at(at)
+            .map(|(kid, _)| kid)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(kids(now), vec!["february"]);
+    assert_eq!(kids(now - 9 * day - day / 2), vec!["january", "february"]);
+    assert_eq!(kids(now + 20 * day + day / 2), vec!["february", "march"]);
+
+    let token = Jwt::new("claims", &keyring, None).finalize().unwrap();
+    let decoded = keyring.verify(&token, &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded.header().kid(), Some("february"));
+    assert_eq!(decoded.claims(), "claims");
+
+    // Tokens of a retired key are rejected once the overlap has passed
+    let january = Jwt::new("old", secret(b"january-secret-of-thirty-two-byte"), None)
+        .finalize()
+        .unwrap();
+    assert!(keyring.verify(&january, &[Algorithm::HS256]).is_err());
+    assert!(keyring.find_by_kid("january").is_none());
+    assert!(keyring.remove("january").is_some());
+    assert!(keyring.remove("january").is_none());
+
+    // A never retiring overlap does not overflow
+    let mut forever = Keyring::new(Duration::MAX);
+    forever.add(
+        "old",
+        secret(b"january-secret-of-thirty-two-byte"),
+        now - day,
+    );
+    forever.add("new", secret(b"february-secret-of-thirty-two-byt"), now);
+    let kids: Vec<_> = forever
+        .verifying_at(now + day)
+        .map(|(kid, _)| kid)
+        .collect();
+    assert_eq!(kids, vec!["old", "new"]);
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_keyring_kid_matches_signature() {
+    use crate::{resolve_key, HmacKey, TokenSigner};
+
+    let secret = |s: &[u8]| HmacKey::from_bytes(s).unwrap();
+    let mut keyring = Keyring::new(Duration::from_secs(60));
+    let activation = SystemTime::now() + Duration::from_millis(50);
+    keyring.add(
+        "old",
+        secret(b"january-secret-of-thirty-two-byte"),
+        SystemTime::UNIX_EPOCH,
+    );
+    keyring.add(
+        "new",
+        secret(b"february-secret-of-thirty-two-byt"),
+        activation,
+    );
+
+    let picked = resolve_key(&keyring).unwrap();
+    assert_eq!(picked.kid(), Some("old"));
+    assert_eq!(
+        picked.sign(&Algorithm::HS256, b"input").unwrap(),
+        secret(b"january-secret-of-thirty-two-byte")
+            .sign(&Algorithm::HS256, b"input")
+            .unwrap()
+    );
+
+    let signer = TokenSigner::new(&keyring, None).unwrap();
+    assert!(signer.sign("claims").is_ok());
+
+    // Tokens made around the activation name the key that signed them
+    let mut kids = Vec::new();
+    while SystemTime::now() < activation + Duration::from_millis(20) {
+        let token = Jwt::new("claims", &keyring, None).finalize().unwrap();
+        let decoded = keyring.verify(&token, &[Algorithm::HS256]).unwrap();
+        kids.push(decoded.header().kid().unwrap().to_string());
+    }
+    assert_eq!(kids.first().map(String::as_str), Some("old"));
+    assert_eq!(kids.last().map(String::as_str), Some("new"));
+    // A signer whose header names the retired key stops signing
+    assert!(signer.sign("claims").is_err());
+
+    assert!(resolve_key(&Keyring::<HmacKey>::new(Duration::ZERO)).is_err());
+}
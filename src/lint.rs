@@ -0,0 +1,443 @@
+use base64::Engine as _;
+use core::fmt;
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+
+use crate::prelude::*;
+use crate::{Algorithm, URL_SAFE_LENIENT};
+
+/// Part of a compact token a `LintIssue` was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSegment {
+    Header,
+    Payload,
+    Signature,
+}
+
+impl fmt::Display for TokenSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenSegment::Header => write!(f, "header"),
+            TokenSegment::Payload => write!(f, "payload"),
+            TokenSegment::Signature => write!(f, "signature"),
+        }
+    }
+}
+
+/// One way a token departs from RFC 7515 or RFC 7519, as reported by `lint`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    segment: Option<TokenSegment>,
+    message: String,
+}
+
+impl LintIssue {
+    /// Segment the issue is in, `None` for the token as a whole
+    pub fn segment(&self) -> Option<TokenSegment> {
+        self.segment
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.segment {
+            Some(segment) => write!(f, "{}: {}", segment, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks a compact token against the serialization rules of RFC 7515 and RFC 7519
+///
+/// Lists every problem found rather than stopping at the first: the segment count, the base64url
+/// alphabet and missing padding, UTF-8 JSON objects without duplicate members, the types of the
+/// registered header members and claims, and the signature length where the algorithm fixes it.
+/// Signatures are not verified, this answers why another implementation rejects a token.
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{lint, TokenSegment};
+///
+/// let issues = lint("eyJhbGciOiJIUzI1NiJ9.eyJleHAiOiJzb29uIn0=.c2ln");
+/// assert_eq!(issues.len(), 2);
+/// assert_eq!(issues[0].segment(), Some(TokenSegment::Payload));
+/// assert_eq!(issues[0].to_string(), "payload: base64url segments must not be padded");
+/// assert_eq!(issues[1].to_string(), "payload: exp must be a NumericDate");
+/// ```
+pub fn lint(token: &str) -> Vec<LintIssue> {
+    let mut lint = Lint::default();
+    let segments: Vec<&str> = token.split('.').collect();
+    if segments.len() != 3 {
+        let message = match segments.len() {
+            5 => "Five segments make an encrypted token, only signed ones are linted".to_string(),
+            n => format!("Compact tokens have three segments, this one has {}", n),
+        };
+        lint.issues.push(LintIssue {
+            segment: None,
+            message,
+        });
+        return lint.issues;
+    }
+
+    let header = lint.json_object(TokenSegment::Header, segments[0].as_bytes(), true);
+    let mut b64 = true;
+    let mut algorithm = None;
+    if let Some(header) = &header {
+        b64 = lint.header(header);
+        algorithm = header["alg"].as_str().and_then(Algorithm::from_name);
+    }
+
+    let payload = segments[1].as_bytes();
+    let payload = if b64 {
+        lint.json_object(TokenSegment::Payload, payload, true)
+    } else {
+        lint.json_object(TokenSegment::Payload, payload, false)
+    };
+    let nested = header
+        .as_ref()
+        .and_then(|header| header["cty"].as_str())
+        .is_some_and(|cty| cty.eq_ignore_ascii_case("JWT"));
+    if let (Some(claims), false) = (&payload, nested) {
+        lint.claims(claims);
+    }
+
+    if let Some(signature) = lint.decode(TokenSegment::Signature, segments[2].as_bytes()) {
+        lint.signature(algorithm, &signature);
+    }
+    lint.issues
+}
+
+#[derive(Default)]
+struct Lint {
+    issues: Vec<LintIssue>,
+}
+
+impl Lint {
+    fn issue(&mut self, segment: TokenSegment, message: &str) {
+        self.issues.push(LintIssue {
+            segment: Some(segment),
+            message: message.to_string(),
+        });
+    }
+
+    /// RFC 7515 2, base64url without padding or line breaks
+    fn decode(&mut self, segment: TokenSegment, encoded: &[u8]) -> Option<Vec<u8>> {
+        let unpadded = match encoded.iter().position(|&c| c == b'=') {
+            Some(i) => {
+                self.issue(segment, "base64url segments must not be padded");
+                &encoded[..i]
+            }
+            None => encoded,
+        };
+        if unpadded.contains(&b'+') || unpadded.contains(&b'/') {
+            self.issue(
+                segment,
+                "uses the standard base64 alphabet instead of base64url",
+            );
+            return None;
+        }
+        match URL_SAFE_LENIENT.decode(unpadded) {
+            Ok(decoded) => Some(decoded),
+            Err(_) => {
+                self.issue(segment, "is not valid base64url");
+                None
+            }
+        }
+    }
+
+    /// Header or claims, a UTF-8 JSON object whose member names are unique
+    fn json_object(
+        &mut self,
+        segment: TokenSegment,
+        encoded: &[u8],
+        base64: bool,
+    ) -> Option<serde_json::Value> {
+        let json = if base64 {
+            self.decode(segment, encoded)?
+        } else {
+            encoded.to_vec()
+        };
+        if core::str::from_utf8(&json).is_err() {
+            self.issue(segment, "is not valid UTF-8");
+            return None;
+        }
+        let value: serde_json::Value = match serde_json::from_slice(&json) {
+            Ok(value) => value,
+            Err(_) => {
+                self.issue(segment, "is not valid JSON");
+                return None;
+            }
+        };
+        if !value.is_object() {
+            self.issue(segment, "must be a JSON object");
+            return None;
+        }
+        if let Ok(UniqueMembers(Some(name))) = serde_json::from_slice(&json) {
+            self.issue(segment, &format!("member {} appears more than once", name));
+        }
+        Some(value)
+    }
+
+    /// Returns the `b64` member, whether the payload is base64url encoded
+    fn header(&mut self, header: &serde_json::Value) -> bool {
+        const HEADER: TokenSegment = TokenSegment::Header;
+        match &header["alg"] {
+            serde_json::Value::String(alg) if Algorithm::from_name(alg).is_none() => self.issue(
+                HEADER,
+                &format!("alg {} is not a registered JWS algorithm", alg),
+            ),
+            serde_json::Value::String(_) => {}
+            serde_json::Value::Null => self.issue(HEADER, "alg is missing"),
+            _ => self.issue(HEADER, "alg must be a string"),
+        }
+        for name in ["typ", "cty", "kid", "jku", "x5u", "x5t", "x5t#S256"] {
+            if !matches!(
+                header[name],
+                serde_json::Value::Null | serde_json::Value::String(_)
+            ) {
+                self.issue(HEADER, &format!("{} must be a string", name));
+            }
+        }
+        if !header["x5c"].is_null() && !is_string_array(&header["x5c"]) {
+            self.issue(HEADER, "x5c must be an array of strings");
+        }
+
+        // RFC 7515 4.1.11, extensions that are present and not defined by the JWS spec itself
+        if !header["crit"].is_null() {
+            match header["crit"].as_array() {
+                Some(crit) if !crit.is_empty() && is_string_array(&header["crit"]) => {
+                    for name in crit.iter().filter_map(|name| name.as_str()) {
+                        if REGISTERED_HEADERS.contains(&name) {
+                            self.issue(HEADER, &format!("crit must not list {}", name));
+                        } else if header[name].is_null() {
+                            self.issue(HEADER, &format!("crit lists {} which is absent", name));
+                        }
+                    }
+                }
+                _ => self.issue(HEADER, "crit must be a non-empty array of strings"),
+            }
+        }
+
+        match &header["b64"] {
+            serde_json::Value::Null => true,
+            serde_json::Value::Bool(b64) => {
+                let critical = header["crit"]
+                    .as_array()
+                    .is_some_and(|crit| crit.iter().any(|name| name == "b64"));
+                if !critical {
+                    self.issue(HEADER, "b64 must be listed in crit");
+                }
+                *b64
+            }
+            _ => {
+                self.issue(HEADER, "b64 must be a boolean");
+                true
+            }
+        }
+    }
+
+    /// RFC 7519 4.1, the registered claims that are present have the right types
+    fn claims(&mut self, claims: &serde_json::Value) {
+        const PAYLOAD: TokenSegment = TokenSegment::Payload;
+        for name in ["iss", "sub", "jti"] {
+            if !matches!(
+                claims[name],
+                serde_json::Value::Null | serde_json::Value::String(_)
+            ) {
+                self.issue(PAYLOAD, &format!("{} must be a string", name));
+            }
+        }
+        let aud = &claims["aud"];
+        if !aud.is_null() && !aud.is_string() && !is_string_array(aud) {
+            self.issue(PAYLOAD, "aud must be a string or an array of strings");
+        }
+        for name in ["exp", "nbf", "iat"] {
+            if !matches!(
+                claims[name],
+                serde_json::Value::Null | serde_json::Value::Number(_)
+            ) {
+                self.issue(PAYLOAD, &format!("{} must be a NumericDate", name));
+            }
+        }
+    }
+
+    fn signature(&mut self, algorithm: Option<Algorithm>, signature: &[u8]) {
+        const SIGNATURE: TokenSegment = TokenSegment::Signature;
+        let expected = match algorithm {
+            Some(Algorithm::None) if !signature.is_empty() => {
+                return self.issue(SIGNATURE, "must be empty for alg none")
+            }
+            Some(Algorithm::None) => return,
+            _ if signature.is_empty() => return self.issue(SIGNATURE, "is empty"),
+            Some(Algorithm::ES256) | Some(Algorithm::ES256K) => 64,
+            Some(Algorithm::ES384) => 96,
+            Some(Algorithm::ES512) => 132,
+            _ => return,
+        };
+        if signature.len() != expected {
+            self.issue(
+                SIGNATURE,
+                &format!(
+                    "{} signatures are {} bytes, this one has {}",
+                    algorithm.unwrap(),
+                    expected,
+                    signature.len()
+                ),
+            );
+        }
+    }
+}
+
+// Header parameters registered by RFC 7515, which `crit` may not list
+const REGISTERED_HEADERS: [&str; 11] = [
+    "alg", "jku", "jwk", "kid", "x5u", "x5c", "x5t", "x5t#S256", "typ", "cty", "crit",
+];
+
+fn is_string_array(value: &serde_json::Value) -> bool {
+    value
+        .as_array()
+        .is_some_and(|values| values.iter().all(|value| value.is_string()))
+}
+
+/// First member name of a JSON object that is repeated, which `serde_json::Value` drops silently
+struct UniqueMembers(Option<String>);
+
+impl<'de> Deserialize<'de> for UniqueMembers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Members;
+
+        impl<'de> Visitor<'de> for Members {
+            type Value = UniqueMembers;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueMembers, A::Error> {
+                let mut seen = alloc::collections::BTreeSet::new();
+                let mut repeated = None;
+                while let Some(name) = map.next_key::<String>()? {
+                    map.next_value::<IgnoredAny>()?;
+                    if !seen.insert(name.clone()) && repeated.is_none() {
+                        repeated = Some(name);
+                    }
+                }
+                Ok(UniqueMembers(repeated))
+            }
+        }
+
+        deserializer.deserialize_map(Members)
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_lint() {
+    use crate::{EcKey, HmacKey, Jwt};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let claims = serde_json::json!({"sub": "me", "exp": 1_700_000_000, "aud": ["a", "b"]});
+    let token = Jwt::new(&claims, key(), None).finalize().unwrap();
+    assert_eq!(lint(&token), vec![]);
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    assert_eq!(
+        lint(&Jwt::new(&claims, ec, None).finalize().unwrap()),
+        vec![]
+    );
+    let unencoded = Jwt::new(&claims, key(), None).with_unencoded_payload();
+    assert_eq!(lint(&unencoded.finalize().unwrap()), vec![]);
+
+    let legacy = Jwt::new(&claims, key(), None).with_legacy_padding();
+    let issues = lint(&legacy.finalize().unwrap());
+    assert!(issues
+        .iter()
+        .all(|issue| issue.message() == "base64url segments must not be padded"));
+    assert!(!issues.is_empty());
+
+    assert!(Jwt::new(&claims, key(), None)
+        .with_legacy_padding()
+        .with_strict_conformance()
+        .finalize()
+        .is_err());
+    assert!(Jwt::new("claims", key(), None)
+        .with_strict_conformance()
+        .finalize()
+        .is_err());
+
+    assert_eq!(lint("a.b").len(), 1);
+    assert_eq!(lint("a.b.c.d.e")[0].segment(), None);
+
+    let encode = |json: &str| URL_SAFE_NO_PAD.encode(json);
+    let messages = |header: &str, payload: &str, signature: &str| -> Vec<String> {
+        lint(&format!(
+            "{}.{}.{}",
+            encode(header),
+            encode(payload),
+            signature
+        ))
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect()
+    };
+    assert_eq!(
+        messages(r#"{"alg":"none","alg":"HS256"}"#, "{}", "c2ln"),
+        ["header: member alg appears more than once"]
+    );
+    assert_eq!(
+        messages(r#"{"typ":1}"#, "[]", "c2ln"),
+        [
+            "header: alg is missing",
+            "header: typ must be a string",
+            "payload: must be a JSON object"
+        ]
+    );
+    assert_eq!(
+        messages(
+            r#"{"alg":"XS1","crit":["kid","ext"],"kid":"1"}"#,
+            "{}",
+            "c2ln"
+        ),
+        [
+            "header: alg XS1 is not a registered JWS algorithm",
+            "header: crit must not list kid",
+            "header: crit lists ext which is absent"
+        ]
+    );
+    assert_eq!(
+        messages(
+            r#"{"alg":"ES256","b64":"no"}"#,
+            r#"{"iss":1,"aud":[1],"nbf":"now"}"#,
+            "c2ln"
+        ),
+        [
+            "header: b64 must be a boolean",
+            "payload: iss must be a string",
+            "payload: aud must be a string or an array of strings",
+            "payload: nbf must be a NumericDate",
+            "signature: ES256 signatures are 64 bytes, this one has 3"
+        ]
+    );
+    assert_eq!(
+        messages(r#"{"alg":"none"}"#, "{}", "c2ln"),
+        ["signature: must be empty for alg none"]
+    );
+    assert_eq!(
+        messages(r#"{"alg":"none"}"#, "{}", ""),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        messages(r#"{"alg":"HS256"}"#, "{}", "a+b/"),
+        ["signature: uses the standard base64 alphabet instead of base64url"]
+    );
+    let latin1 = lint(&format!(
+        "{}.{}.c2ln",
+        encode(r#"{"alg":"HS256"}"#),
+        URL_SAFE_NO_PAD.encode([0xff])
+    ));
+    assert_eq!(latin1[0].to_string(), "payload: is not valid UTF-8");
+}
@@ -0,0 +1,297 @@
+use alloc::borrow::Cow;
+use base64::Engine as _;
+use serde::de::DeserializeOwned;
+
+use crate::prelude::*;
+use crate::verify::malformed;
+use crate::{Jwt, JwtErr, JwtHeader, TokenSegment, ValidationError, URL_SAFE_LENIENT};
+
+/// Decoded segments of a compact token, its signature not checked
+///
+/// Made by `Jwt::parse` for looking into tokens before, or instead of, verifying them. Nothing
+/// read from an unverified token can be trusted.
+#[derive(Debug, Clone)]
+pub struct TokenParts {
+    header: JwtHeader,
+    header_json: String,
+    payload_json: String,
+    signature: Vec<u8>,
+}
+
+impl TokenParts {
+    pub fn header(&self) -> &JwtHeader {
+        &self.header
+    }
+
+    /// The header as sent, before it was parsed into `header`
+    pub fn header_json(&self) -> &str {
+        &self.header_json
+    }
+
+    /// The payload as sent, the inner token for a nested one
+    pub fn payload_json(&self) -> &str {
+        &self.payload_json
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Deserializes the payload, e.g. into `serde_json::Value`
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, JwtErr> {
+        Ok(serde_json::from_str(&self.payload_json)?)
+    }
+}
+
+impl Jwt<serde_json::Value> {
+    /// Splits a compact token and decodes its segments without verifying it
+    ///
+    /// Errors name the segment that is malformed and how. Payloads of `b64: false` tokens are
+    /// taken as they are.
+    ///
+    /// ```
+    /// use smpl_jwt::Jwt;
+    ///
+    /// let parts = Jwt::parse("eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiJtZSJ9.c2ln").unwrap();
+    /// assert_eq!(parts.header().alg(), "HS256");
+    /// assert_eq!(parts.payload_json(), r#"{"sub":"me"}"#);
+    /// assert_eq!(parts.signature(), b"sig");
+    ///
+    /// let err = Jwt::parse("eyJhbGciOiJIUzI1NiJ9.e30*.c2ln").err().unwrap();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "The payload segment is not valid base64url: Invalid byte 42, offset 3."
+    /// );
+    /// ```
+    pub fn parse(token: &str) -> Result<TokenParts, JwtErr> {
+        TokenRef::parse(token)?.to_parts()
+    }
+}
+
+/// A compact token split in place, its segments decoded only when asked for
+///
+/// Borrows the token string and does not allocate until a segment is decoded. For gateways
+/// routing on a header member or a claim, where `TokenParts` decodes more than is needed.
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::TokenRef;
+///
+/// let token = "eyJhbGciOiJIUzI1NiIsImtpZCI6ImsxIn0.eyJzdWIiOiJtZSJ9.c2ln";
+/// let token = TokenRef::parse(token).unwrap();
+/// assert_eq!(token.encoded_payload(), "eyJzdWIiOiJtZSJ9");
+/// assert_eq!(token.header().unwrap().kid(), Some("k1"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenRef<'a> {
+    token: &'a str,
+    header: &'a str,
+    payload: &'a str,
+    signature: &'a str,
+}
+
+impl<'a> TokenRef<'a> {
+    /// Splits `token` into its segments, nothing is decoded yet
+    pub fn parse(token: &'a str) -> Result<Self, JwtErr> {
+        let mut segments = token.splitn(4, '.');
+        match (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            (Some(header), Some(payload), Some(signature), None) => Ok(TokenRef {
+                token,
+                header,
+                payload,
+                signature,
+            }),
+            _ => Err(JwtErr::described(
+                ValidationError::MalformedToken { segment: None },
+                &format!(
+                    "Token must consist of three segments, found {}",
+                    token.split('.').count()
+                ),
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.token
+    }
+
+    pub fn encoded_header(&self) -> &'a str {
+        self.header
+    }
+
+    pub fn encoded_payload(&self) -> &'a str {
+        self.payload
+    }
+
+    pub fn encoded_signature(&self) -> &'a str {
+        self.signature
+    }
+
+    /// `header.payload`, the octets the signature is computed over
+    pub fn signing_input(&self) -> &'a str {
+        &self.token[..self.header.len() + 1 + self.payload.len()]
+    }
+
+    pub fn header(&self) -> Result<JwtHeader, JwtErr> {
+        parse_header(&self.header_json()?)
+    }
+
+    pub fn header_json(&self) -> Result<String, JwtErr> {
+        utf8(
+            TokenSegment::Header,
+            decode(TokenSegment::Header, self.header)?,
+        )
+    }
+
+    /// The decoded payload, for a `b64: false` token the payload segment as it is
+    ///
+    /// The header is not looked at, `b64: false` has to be passed in by whoever decoded it.
+    pub fn payload_json(&self, b64: bool) -> Result<Cow<'a, str>, JwtErr> {
+        if b64 {
+            Ok(Cow::Owned(utf8(
+                TokenSegment::Payload,
+                decode(TokenSegment::Payload, self.payload)?,
+            )?))
+        } else {
+            Ok(Cow::Borrowed(self.payload))
+        }
+    }
+
+    /// Deserializes a base64url encoded payload
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, JwtErr> {
+        Ok(serde_json::from_slice(&decode(
+            TokenSegment::Payload,
+            self.payload,
+        )?)?)
+    }
+
+    pub fn signature(&self) -> Result<Vec<u8>, JwtErr> {
+        decode(TokenSegment::Signature, self.signature)
+    }
+
+    /// Decodes all segments into an owned `TokenParts`, as `Jwt::parse` does
+    pub fn to_parts(&self) -> Result<TokenParts, JwtErr> {
+        let header_json = self.header_json()?;
+        let header = parse_header(&header_json)?;
+        Ok(TokenParts {
+            payload_json: self.payload_json(header.b64())?.into_owned(),
+            signature: self.signature()?,
+            header,
+            header_json,
+        })
+    }
+}
+
+fn parse_header(header_json: &str) -> Result<JwtHeader, JwtErr> {
+    serde_json::from_str(header_json).map_err(|err| {
+        malformed(TokenSegment::Header)(format!("The header is not a JWS header: {}", err))
+    })
+}
+
+fn decode(segment: TokenSegment, encoded: &str) -> Result<Vec<u8>, JwtErr> {
+    URL_SAFE_LENIENT.decode(encoded).map_err(|err| {
+        malformed(segment)(format!(
+            "The {} segment is not valid base64url: {}",
+            segment, err
+        ))
+    })
+}
+
+fn utf8(segment: TokenSegment, decoded: Vec<u8>) -> Result<String, JwtErr> {
+    String::from_utf8(decoded).map_err(|err| {
+        malformed(segment)(format!(
+            "The {} segment is not valid UTF-8: {}",
+            segment, err
+        ))
+    })
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_parse() {
+    use crate::HmacKey;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(serde_json::json!({"sub": "me"}), &key, None)
+        .with_kid("k1")
+        .finalize()
+        .unwrap();
+    let parts = Jwt::parse(&token).unwrap();
+    assert_eq!(parts.header().kid(), Some("k1"));
+    assert_eq!(
+        parts.header_json(),
+        r#"{"alg":"HS256","typ":"JWT","kid":"k1"}"#
+    );
+    assert_eq!(parts.payload_json(), r#"{"sub":"me"}"#);
+    assert_eq!(parts.signature().len(), 32);
+    let claims: serde_json::Value = parts.claims().unwrap();
+    assert_eq!(claims["sub"], "me");
+
+    let token = Jwt::new(serde_json::json!({"sub": "me"}), &key, None)
+        .with_unencoded_payload()
+        .finalize()
+        .unwrap();
+    assert_eq!(
+        Jwt::parse(&token).unwrap().payload_json(),
+        r#"{"sub":"me"}"#
+    );
+
+    let err = |token: &str| Jwt::parse(token).err().unwrap().to_string();
+    assert_eq!(err("a.b"), "Token must consist of three segments, found 2");
+    assert!(err("e30*.e30.c2ln").starts_with("The header segment is not valid base64url"));
+    assert!(err("eyJhbGciOiJub25lIn0.e30.c2ln*")
+        .starts_with("The signature segment is not valid base64url"));
+    let latin1 = URL_SAFE_NO_PAD.encode([0xe9]);
+    assert!(err(&format!("e30.{}.c2ln", latin1)).starts_with("The header is not a JWS header"));
+    assert!(err(&format!("eyJhbGciOiJub25lIn0.{}.", latin1))
+        .starts_with("The payload segment is not valid UTF-8"));
+    assert!(err(&format!("{}.e30.", latin1)).starts_with("The header segment is not valid UTF-8"));
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_token_ref() {
+    use crate::HmacKey;
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(serde_json::json!({"sub": "me"}), &key, None)
+        .finalize()
+        .unwrap();
+    let token_ref = TokenRef::parse(&token).unwrap();
+    assert_eq!(token_ref.as_str(), token);
+    let (signing_input, signature) = token.rsplit_once('.').unwrap();
+    assert_eq!(token_ref.signing_input(), signing_input);
+    assert_eq!(token_ref.encoded_signature(), signature);
+    assert_eq!(token_ref.header().unwrap().alg(), "HS256");
+    let claims: serde_json::Value = token_ref.claims().unwrap();
+    assert_eq!(claims["sub"], "me");
+    assert_eq!(token_ref.signature().unwrap().len(), 32);
+    let parts = token_ref.to_parts().unwrap();
+    assert_eq!(parts.payload_json(), token_ref.payload_json(true).unwrap());
+
+    // Only the segments asked for are decoded
+    let broken = TokenRef::parse("eyJhbGciOiJub25lIn0.e30.*").unwrap();
+    assert!(broken.header().is_ok());
+    assert!(broken.signature().is_err());
+    assert!(broken.to_parts().is_err());
+
+    let unencoded = TokenRef::parse("eyJhbGciOiJub25lIn0.{}.").unwrap();
+    assert_eq!(unencoded.payload_json(false).unwrap(), "{}");
+    assert!(matches!(
+        unencoded.payload_json(false).unwrap(),
+        Cow::Borrowed(_)
+    ));
+
+    assert_eq!(
+        TokenRef::parse("a.b.c.d").err().unwrap().to_string(),
+        "Token must consist of three segments, found 4"
+    );
+    assert!(TokenRef::parse("").is_err());
+}
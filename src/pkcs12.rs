@@ -0,0 +1,123 @@
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, PKeyRef, Private};
+use openssl::x509::X509;
+
+use crate::{read_file, EcKey, Ed25519Key, Ed448Key, JwtErr, RSAKey};
+
+/// Private key and certificate chain unpacked from a password protected PKCS#12 (`.p12`) bundle
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Jwt, Pkcs12Keystore};
+///
+/// let keystore = Pkcs12Keystore::from_file("signing.p12", "changeit").unwrap();
+/// let token = Jwt::new("claims", keystore.rsa_key().unwrap(), None)
+///     .with_certificate_chain(keystore.certificate_chain())
+///     .unwrap()
+///     .finalize()
+///     .unwrap();
+/// ```
+pub struct Pkcs12Keystore {
+    key: PKey<Private>,
+    chain: Vec<X509>,
+}
+
+impl Pkcs12Keystore {
+    pub fn from_file(filename: &str, password: &str) -> Result<Self, JwtErr> {
+        Self::from_der(&read_file(filename)?, password)
+    }
+
+    /// Parses a DER encoded PKCS#12 bundle, failing if it holds no private key
+    pub fn from_der(der: &[u8], password: &str) -> Result<Self, JwtErr> {
+        let parsed = Pkcs12::from_der(der)
+            .and_then(|pkcs12| pkcs12.parse2(password))
+            .map_err(|e| {
+                JwtErr::from(format!("Invalid PKCS#12 bundle or password: {}", e).as_str())
+            })?;
+        let key = parsed
+            .pkey
+            .ok_or_else(|| JwtErr::from("PKCS#12 bundle holds no private key"))?;
+        let chain = parsed
+            .cert
+            .into_iter()
+            .chain(parsed.ca.into_iter().flatten())
+            .collect();
+        Ok(Pkcs12Keystore { key, chain })
+    }
+
+    pub fn private_key(&self) -> &PKeyRef<Private> {
+        &self.key
+    }
+
+    /// Certificate of the private key followed by the CA certificates, as `x5c` expects them
+    pub fn certificate_chain(&self) -> &[X509] {
+        &self.chain
+    }
+
+    pub fn rsa_key(&self) -> Result<RSAKey, JwtErr> {
+        if self.key.rsa().is_err() {
+            return Err(JwtErr::from("Key is not an RSA key"));
+        }
+        RSAKey::from_pkey(self.key.clone())
+    }
+
+    pub fn ec_key(&self) -> Result<EcKey, JwtErr> {
+        EcKey::from_pkey(self.key.clone())
+    }
+
+    pub fn ed25519_key(&self) -> Result<Ed25519Key, JwtErr> {
+        Ed25519Key::from_pkey(self.key.clone())
+    }
+
+    pub fn ed448_key(&self) -> Result<Ed448Key, JwtErr> {
+        Ed448Key::from_pkey(self.key.clone())
+    }
+}
+
+#[test]
+fn test_pkcs12_keystore() {
+    use crate::{self_signed, Algorithm, Jwt};
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let leaf = self_signed(&rsa.key, "leaf");
+    let ca = self_signed(&crate::read_keyfile("random_ec_for_testing").unwrap(), "ca");
+    let mut cas = openssl::stack::Stack::new().unwrap();
+    cas.push(ca.clone()).unwrap();
+    let der = Pkcs12::builder()
+        .name("signing")
+        .pkey(&rsa.key)
+        .cert(&leaf)
+        .ca(cas)
+        .build2("changeit")
+        .unwrap()
+        .to_der()
+        .unwrap();
+
+    assert!(Pkcs12Keystore::from_der(&der, "wrong").is_err());
+    let keystore = Pkcs12Keystore::from_der(&der, "changeit").unwrap();
+    assert!(keystore.private_key().public_eq(&rsa.key));
+    let chain = keystore.certificate_chain();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[0].to_der().unwrap(), leaf.to_der().unwrap());
+    assert_eq!(chain[1].to_der().unwrap(), ca.to_der().unwrap());
+    assert!(keystore.ec_key().is_err());
+    assert!(keystore.ed25519_key().is_err());
+
+    let token = Jwt::new("claims", keystore.rsa_key().unwrap(), None)
+        .with_certificate_chain(chain)
+        .unwrap()
+        .finalize()
+        .unwrap();
+    let decoded = Jwt::verify(&token, &rsa.public_key().unwrap(), &[Algorithm::RS256]).unwrap();
+    assert_eq!(decoded.header().x5c().unwrap().len(), 2);
+
+    let certless = Pkcs12::builder()
+        .pkey(&rsa.key)
+        .build2("changeit")
+        .unwrap()
+        .to_der()
+        .unwrap();
+    let keystore = Pkcs12Keystore::from_der(&certless, "changeit").unwrap();
+    assert!(keystore.certificate_chain().is_empty());
+}
@@ -0,0 +1,4 @@
+// The parts of the `std` prelude that live in `alloc`, which `no_std` builds have to import
+pub(crate) use alloc::boxed::Box;
+pub(crate) use alloc::string::{String, ToString};
+pub(crate) use alloc::vec::Vec;
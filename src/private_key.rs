@@ -0,0 +1,149 @@
+use openssl::pkey::{Id, PKey, Private};
+
+use crate::{
+    pem_from_env, Algorithm, EcKey, Ed25519Key, Ed448Key, IncrementalSigner, JwtErr, PublicKey,
+    RSAKey, SigningKey, VerifyingKey,
+};
+
+/// Private key of whichever type a PEM or DER blob holds, signing with the algorithm that fits it
+///
+/// ### Example
+///
+/// ```
+/// use smpl_jwt::{Algorithm, Jwt, PrivateKey, SigningKey};
+///
+/// let pem = std::fs::read("random_ec_for_testing").unwrap();
+/// let key = PrivateKey::from_pem(&pem).unwrap();
+/// assert!(matches!(key, PrivateKey::Ec(_)));
+/// assert_eq!(key.default_algorithm(), Algorithm::ES256);
+///
+/// let token = Jwt::new("claims", key, None).finalize().unwrap();
+/// ```
+pub enum PrivateKey {
+    Rsa(RSAKey),
+    Ec(EcKey),
+    Ed25519(Ed25519Key),
+    Ed448(Ed448Key),
+}
+
+impl PrivateKey {
+    pub fn from_pkey(pkey: PKey<Private>) -> Result<Self, JwtErr> {
+        match pkey.id() {
+            Id::RSA => Ok(PrivateKey::Rsa(RSAKey::from_pkey(pkey)?)),
+            Id::EC => Ok(PrivateKey::Ec(EcKey::from_pkey(pkey)?)),
+            Id::ED25519 => Ok(PrivateKey::Ed25519(Ed25519Key::from_pkey(pkey)?)),
+            Id::ED448 => Ok(PrivateKey::Ed448(Ed448Key::from_pkey(pkey)?)),
+            _ => Err(JwtErr::from("Unsupported private key type")),
+        }
+    }
+
+    /// Parses a PKCS#8, PKCS#1 (`BEGIN RSA PRIVATE KEY`) or SEC1 (`BEGIN EC PRIVATE KEY`) PEM
+    pub fn from_pem(pem: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::private_key_from_pem(pem)?)
+    }
+
+    /// Reads a PEM from the environment variable `name`, literal `\n` sequences are unescaped
+    pub fn from_env(name: &str) -> Result<Self, JwtErr> {
+        Self::from_pem(&pem_from_env(name)?)
+    }
+
+    /// Parses a DER encoded PKCS#8, PKCS#1 or SEC1 structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        match PKey::private_key_from_der(der) {
+            Ok(key) => Self::from_pkey(key),
+            Err(_) => Ok(PrivateKey::Rsa(RSAKey::from_der(der)?)),
+        }
+    }
+
+    /// Public half of the key, for verifying the tokens it signs
+    pub fn public_key(&self) -> Result<PublicKey, JwtErr> {
+        Ok(match self {
+            PrivateKey::Rsa(key) => PublicKey::Rsa(key.public_key()?),
+            PrivateKey::Ec(key) => PublicKey::Ec(key.public_key()?),
+            PrivateKey::Ed25519(key) => PublicKey::Ed25519(key.public_key()?),
+            PrivateKey::Ed448(key) => PublicKey::Ed448(key.public_key()?),
+        })
+    }
+}
+
+impl SigningKey for PrivateKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        match self {
+            PrivateKey::Rsa(key) => key.sign(algo, input),
+            PrivateKey::Ec(key) => key.sign(algo, input),
+            PrivateKey::Ed25519(key) => key.sign(algo, input),
+            PrivateKey::Ed448(key) => key.sign(algo, input),
+        }
+    }
+
+    /// `RS256` for RSA keys, the `ES*` algorithm of the curve for EC keys and `EdDSA` otherwise
+    fn default_algorithm(&self) -> Algorithm {
+        match self {
+            PrivateKey::Rsa(key) => key.default_algorithm(),
+            PrivateKey::Ec(key) => key.default_algorithm(),
+            PrivateKey::Ed25519(key) => key.default_algorithm(),
+            PrivateKey::Ed448(key) => key.default_algorithm(),
+        }
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        match self {
+            PrivateKey::Rsa(key) => key.incremental_signer(algo),
+            PrivateKey::Ec(key) => key.incremental_signer(algo),
+            PrivateKey::Ed25519(key) => key.incremental_signer(algo),
+            PrivateKey::Ed448(key) => key.incremental_signer(algo),
+        }
+    }
+}
+
+impl VerifyingKey for PrivateKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        match self {
+            PrivateKey::Rsa(key) => key.verify(algo, input, signature),
+            PrivateKey::Ec(key) => key.verify(algo, input, signature),
+            PrivateKey::Ed25519(key) => key.verify(algo, input, signature),
+            PrivateKey::Ed448(key) => key.verify(algo, input, signature),
+        }
+    }
+}
+
+#[test]
+fn test_private_key_detection() {
+    use crate::{read_file, EcCurve, Jwt};
+
+    let cases = [
+        ("random_rsa_for_testing", Algorithm::RS256),
+        ("random_ec_for_testing", Algorithm::ES256),
+        ("random_ec384_for_testing", Algorithm::ES384),
+        ("random_ec521_for_testing", Algorithm::ES512),
+        ("random_secp256k1_for_testing", Algorithm::ES256K),
+        ("random_ed25519_for_testing", Algorithm::EdDSA),
+        ("random_ed448_for_testing", Algorithm::EdDSA),
+    ];
+    for (file, algo) in cases.iter() {
+        let key = PrivateKey::from_pem(&read_file(file).unwrap()).unwrap();
+        assert_eq!(key.default_algorithm(), *algo, "{}", file);
+        let token = Jwt::new("claims", &key, None).finalize().unwrap();
+        let decoded = Jwt::verify(&token, &key.public_key().unwrap(), &[*algo]).unwrap();
+        assert_eq!(decoded.header().alg(), algo.to_string());
+    }
+
+    let ec = EcKey::generate(EcCurve::P384).unwrap();
+    match PrivateKey::from_der(&ec.to_der().unwrap()).unwrap() {
+        PrivateKey::Ec(key) => assert_eq!(key.curve(), EcCurve::P384),
+        _ => panic!("expected an EC key"),
+    }
+    let rsa = PKey::private_key_from_pem(&read_file("random_rsa_for_testing").unwrap()).unwrap();
+    let pkcs1 = rsa.rsa().unwrap().private_key_to_der().unwrap();
+    assert!(matches!(
+        PrivateKey::from_der(&pkcs1).unwrap(),
+        PrivateKey::Rsa(_)
+    ));
+
+    let x25519 = PKey::generate_x25519().unwrap();
+    assert!(PrivateKey::from_pkey(x25519).is_err());
+    assert!(PrivateKey::from_pem(b"not a key").is_err());
+}
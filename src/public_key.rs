@@ -0,0 +1,163 @@
+use openssl::asn1::Asn1Time;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::x509::X509;
+
+use crate::{
+    Algorithm, EcPublicKey, Ed25519PublicKey, Ed448PublicKey, JwtErr, RSAPublicKey, VerifyingKey,
+};
+
+/// Public key of whichever type a certificate or SubjectPublicKeyInfo holds
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, Jwt, PublicKey};
+///
+/// let pem = std::fs::read("issuer.crt").unwrap();
+/// let key = PublicKey::from_certificate_pem(&pem, true).unwrap();
+/// # let token = "";
+/// let decoded = Jwt::verify(token, &key, &[Algorithm::RS256, Algorithm::ES256]).unwrap();
+/// ```
+pub enum PublicKey {
+    Rsa(RSAPublicKey),
+    Ec(EcPublicKey),
+    Ed25519(Ed25519PublicKey),
+    Ed448(Ed448PublicKey),
+}
+
+impl PublicKey {
+    pub fn from_pkey(pkey: PKey<Public>) -> Result<Self, JwtErr> {
+        match pkey.id() {
+            Id::RSA => Ok(PublicKey::Rsa(RSAPublicKey::from_pkey(pkey)?)),
+            Id::EC => Ok(PublicKey::Ec(EcPublicKey::from_pkey(pkey)?)),
+            Id::ED25519 => Ok(PublicKey::Ed25519(Ed25519PublicKey::from_pkey(pkey)?)),
+            Id::ED448 => Ok(PublicKey::Ed448(Ed448PublicKey::from_pkey(pkey)?)),
+            _ => Err(JwtErr::from("Unsupported public key type")),
+        }
+    }
+
+    /// Parses a DER encoded SubjectPublicKeyInfo structure
+    pub fn from_der(der: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_der(der)?)
+    }
+
+    /// Parses a SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) PEM
+    pub fn from_pem(pem: &[u8]) -> Result<Self, JwtErr> {
+        Self::from_pkey(PKey::public_key_from_pem(pem)?)
+    }
+
+    /// Key the certificate was issued for, with `check_validity` outside of its validity period
+    /// is an error
+    ///
+    /// Only the dates are looked at, the certificate chain is not verified.
+    pub fn from_certificate(cert: &X509, check_validity: bool) -> Result<Self, JwtErr> {
+        if check_validity {
+            let now = Asn1Time::days_from_now(0)?;
+            if cert.not_after() < now {
+                return Err(JwtErr::from("Certificate has expired"));
+            }
+            if cert.not_before() > now {
+                return Err(JwtErr::from("Certificate is not valid yet"));
+            }
+        }
+        Self::from_pkey(cert.public_key()?)
+    }
+
+    /// Algorithm the key's owner most likely signs with, `RS256` for RSA keys
+    ///
+    /// EC keys only fit the `ES*` algorithm of their curve, both Edwards curves use `EdDSA`.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            PublicKey::Rsa(_) => Algorithm::RS256,
+            PublicKey::Ec(key) => key.curve().algorithm(),
+            PublicKey::Ed25519(_) | PublicKey::Ed448(_) => Algorithm::EdDSA,
+        }
+    }
+
+    /// Like `from_certificate`, parsing a `BEGIN CERTIFICATE` PEM
+    pub fn from_certificate_pem(pem: &[u8], check_validity: bool) -> Result<Self, JwtErr> {
+        Self::from_certificate(&X509::from_pem(pem)?, check_validity)
+    }
+
+    /// Like `from_certificate`, parsing a DER encoded certificate
+    pub fn from_certificate_der(der: &[u8], check_validity: bool) -> Result<Self, JwtErr> {
+        Self::from_certificate(&X509::from_der(der)?, check_validity)
+    }
+}
+
+impl VerifyingKey for PublicKey {
+    fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+        match self {
+            PublicKey::Rsa(key) => key.verify(algo, input, signature),
+            PublicKey::Ec(key) => key.verify(algo, input, signature),
+            PublicKey::Ed25519(key) => key.verify(algo, input, signature),
+            PublicKey::Ed448(key) => key.verify(algo, input, signature),
+        }
+    }
+}
+
+#[test]
+fn test_public_key_from_certificate() {
+    use crate::{self_signed, EcCurve, Ed25519Key, Jwt, RSAKey};
+    use openssl::hash::MessageDigest;
+    use openssl::x509::X509NameBuilder;
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let cert = self_signed(&rsa.key, "rsa");
+    let key = PublicKey::from_certificate_pem(&cert.to_pem().unwrap(), true).unwrap();
+    assert!(matches!(key, PublicKey::Rsa(_)));
+    let token = Jwt::new("claims", &rsa, None).finalize().unwrap();
+    assert!(Jwt::verify(&token, &key, &[Algorithm::RS256]).is_ok());
+
+    let group = openssl::ec::EcGroup::from_curve_name(EcCurve::P384.nid()).unwrap();
+    let ec_pkey = PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap()).unwrap();
+    let cert = self_signed(&ec_pkey, "ec");
+    match PublicKey::from_certificate_der(&cert.to_der().unwrap(), true).unwrap() {
+        PublicKey::Ec(key) => assert_eq!(key.curve(), EcCurve::P384),
+        _ => panic!("expected an EC key"),
+    }
+    assert_eq!(
+        PublicKey::from_certificate(&cert, false)
+            .unwrap()
+            .algorithm(),
+        Algorithm::ES384
+    );
+
+    let ed = PKey::generate_ed25519().unwrap();
+    let spki = ed.public_key_to_der().unwrap();
+    assert!(matches!(
+        PublicKey::from_der(&spki).unwrap(),
+        PublicKey::Ed25519(_)
+    ));
+    let token = Jwt::new("claims", Ed25519Key::from_pkey(ed).unwrap(), None)
+        .finalize()
+        .unwrap();
+    assert!(Jwt::verify(
+        &token,
+        &PublicKey::from_der(&spki).unwrap(),
+        &[Algorithm::EdDSA]
+    )
+    .is_ok());
+
+    // Expired in 1970
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "expired").unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&rsa.key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::from_unix(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::from_unix(86_400).unwrap())
+        .unwrap();
+    builder.sign(&rsa.key, MessageDigest::sha256()).unwrap();
+    let expired = builder.build();
+    let err = PublicKey::from_certificate(&expired, true).err().unwrap();
+    assert_eq!(err.to_string(), "Certificate has expired");
+    assert!(PublicKey::from_certificate(&expired, false).is_ok());
+
+    assert!(PublicKey::from_certificate_pem(b"not a certificate", false).is_err());
+}
@@ -0,0 +1,99 @@
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites `bytes` with zeros
+///
+/// Volatile writes can not be optimized away even though the buffer is usually freed right after.
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Wipes the bytes of `string`, leaving it empty
+#[cfg(feature = "openssl")]
+pub(crate) fn wipe_string(string: &mut String) {
+    let mut bytes = std::mem::take(string).into_bytes();
+    wipe(&mut bytes);
+}
+
+/// Key material in transit, PEM and DER read from files or the environment, wiped on drop
+///
+/// It never grows in place, where the allocator could leave a copy of the secret behind.
+pub(crate) struct Secret(Vec<u8>);
+
+impl Secret {
+    /// Reads `reader` to the end, `size_hint` is the length expected, e.g. of a file
+    pub(crate) fn read<R: Read>(mut reader: R, size_hint: usize) -> io::Result<Self> {
+        // One byte over the hint, so a full buffer is only seen when there is more to read
+        let mut secret = Secret(vec![0; size_hint.max(1023) + 1]);
+        let mut len = 0;
+        loop {
+            if len == secret.0.len() {
+                let mut grown = vec![0; secret.0.len() * 2];
+                grown[..len].copy_from_slice(&secret.0);
+                secret = Secret(grown);
+            }
+            match reader.read(&mut secret.0[len..]) {
+                Ok(0) => break,
+                Ok(read) => len += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // Past `len` the buffer was never written to
+        secret.0.truncate(len);
+        Ok(secret)
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(bytes: Vec<u8>) -> Self {
+        Secret(bytes)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(string: String) -> Self {
+        Secret(string.into_bytes())
+    }
+}
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+#[test]
+fn test_secret() {
+    let mut bytes = *b"secret";
+    wipe(&mut bytes);
+    assert_eq!(bytes, [0; 6]);
+    #[cfg(feature = "openssl")]
+    {
+        let mut string = String::from("secret");
+        wipe_string(&mut string);
+        assert!(string.is_empty());
+    }
+
+    let pem = std::fs::read("random_rsa_for_testing").unwrap();
+    for hint in [0, 10, pem.len(), pem.len() + 1, 1 << 16] {
+        assert_eq!(&*Secret::read(&pem[..], hint).unwrap(), &pem[..]);
+    }
+    // Reads out growing past the initial buffer several times
+    let large = vec![7; 10_000];
+    assert_eq!(&*Secret::read(&large[..], 0).unwrap(), &large[..]);
+    assert!(Secret::read(&b""[..], 0).unwrap().is_empty());
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::str::FromStr;
+
+use crate::secret::Secret;
+use crate::{read_file, Algorithm, IncrementalSigner, JwtErr, RSAKey, SigningKey};
+
+/// Google Cloud service account key file, as `GOOGLE_APPLICATION_CREDENTIALS` points to
+///
+/// Signs with the account's RSA key, the `private_key_id` becomes the token's `kid` and
+/// `client_email` is meant to be its `iss` and `sub`.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{ClaimsBuilder, Jwt, ServiceAccount};
+/// use std::time::Duration;
+///
+/// let account = ServiceAccount::from_env().unwrap();
+/// let claims = ClaimsBuilder::new()
+///     .issuer(account.client_email())
+///     .subject(account.client_email())
+///     .audience("https://oauth2.googleapis.com/token")
+///     .expires_in(Duration::from_secs(3600))
+///     .build(serde_json::json!({"scope": "https://www.googleapis.com/auth/cloud-platform"}))
+///     .unwrap();
+/// let token = Jwt::new(claims, account, None).finalize().unwrap();
+/// ```
+pub struct ServiceAccount {
+    key: RSAKey,
+    client_email: String,
+    private_key_id: String,
+}
+
+#[derive(Deserialize)]
+struct KeyFile {
+    #[serde(rename = "type")]
+    kind: String,
+    private_key: String,
+    private_key_id: String,
+    client_email: String,
+}
+
+impl ServiceAccount {
+    pub fn from_file(filename: &str) -> Result<Self, JwtErr> {
+        Self::from_json(&read_file(filename)?)
+    }
+
+    /// Reads the key file `GOOGLE_APPLICATION_CREDENTIALS` points to
+    pub fn from_env() -> Result<Self, JwtErr> {
+        match env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+            Ok(filename) => Self::from_file(&filename),
+            Err(_) => Err(JwtErr::from(
+                "Environment variable GOOGLE_APPLICATION_CREDENTIALS is not set or not valid unicode",
+            )),
+        }
+    }
+
+    fn from_json(json: &[u8]) -> Result<Self, JwtErr> {
+        let file: KeyFile = serde_json::from_slice(json)?;
+        // Wiped whether or not the file is accepted
+        let private_key = Secret::from(file.private_key);
+        if file.kind != "service_account" {
+            return Err(JwtErr::from(
+                format!("Expected a service_account key file, got {}", file.kind).as_str(),
+            ));
+        }
+        Ok(ServiceAccount {
+            key: RSAKey::from_reader(&private_key[..])?,
+            client_email: file.client_email,
+            private_key_id: file.private_key_id,
+        })
+    }
+
+    pub fn client_email(&self) -> &str {
+        &self.client_email
+    }
+
+    pub fn private_key_id(&self) -> &str {
+        &self.private_key_id
+    }
+
+    pub fn key(&self) -> &RSAKey {
+        &self.key
+    }
+
+    pub fn into_key(self) -> RSAKey {
+        self.key
+    }
+}
+
+/// Entry points on the key type, for callers looking for the key first
+///
+/// The account is returned as a whole, `client_email` and `private_key_id` are meant for the
+/// token's `iss` and `kid` and `into_key` leaves the bare key.
+impl RSAKey {
+    /// Same as `ServiceAccount::from_file`
+    ///
+    /// ```no_run
+    /// use smpl_jwt::RSAKey;
+    ///
+    /// let account = RSAKey::from_service_account_json("service-account.json").unwrap();
+    /// println!("iss {} kid {}", account.client_email(), account.private_key_id());
+    /// let key: RSAKey = account.into_key();
+    /// ```
+    pub fn from_service_account_json(filename: &str) -> Result<ServiceAccount, JwtErr> {
+        ServiceAccount::from_file(filename)
+    }
+
+    /// Same as `ServiceAccount::from_env`, reading the file `GOOGLE_APPLICATION_CREDENTIALS` names
+    pub fn from_service_account_env() -> Result<ServiceAccount, JwtErr> {
+        ServiceAccount::from_env()
+    }
+}
+
+impl FromStr for ServiceAccount {
+    type Err = JwtErr;
+    fn from_str(s: &str) -> Result<Self, JwtErr> {
+        Self::from_json(s.as_bytes())
+    }
+}
+
+impl SigningKey for ServiceAccount {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        self.key.sign(algo, input)
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.key.default_algorithm()
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.private_key_id)
+    }
+
+    fn incremental_signer(
+        &self,
+        algo: &Algorithm,
+    ) -> Result<Option<Box<dyn IncrementalSigner + '_>>, JwtErr> {
+        self.key.incremental_signer(algo)
+    }
+}
+
+#[cfg(test)]
+fn key_file(kind: &str) -> String {
+    let key = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    serde_json::json!({
+        "type": kind,
+        "project_id": "project",
+        "private_key_id": "0123abcd",
+        "private_key": String::from_utf8(key.to_pem().unwrap()).unwrap(),
+        "client_email": "signer@project.iam.gserviceaccount.com",
+        "client_id": "1234",
+        "token_uri": "https://oauth2.googleapis.com/token",
+    })
+    .to_string()
+}
+
+#[test]
+fn test_service_account() {
+    use crate::Jwt;
+
+    let account = ServiceAccount::from_str(&key_file("service_account")).unwrap();
+    assert_eq!(
+        account.client_email(),
+        "signer@project.iam.gserviceaccount.com"
+    );
+    assert_eq!(account.private_key_id(), "0123abcd");
+    let public = account.key().public_key().unwrap();
+    let token = Jwt::new("claims", account, None).finalize().unwrap();
+    let decoded = Jwt::verify(&token, &public, &[Algorithm::RS256]).unwrap();
+    assert_eq!(decoded.header().kid(), Some("0123abcd"));
+    assert_eq!(decoded.header().alg(), "RS256");
+
+    let err = ServiceAccount::from_str(&key_file("authorized_user"))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Expected a service_account key file, got authorized_user"
+    );
+    assert!(ServiceAccount::from_str(r#"{"type": "service_account"}"#).is_err());
+
+    let path = env::temp_dir().join(format!("smpl_jwt_sa_{}.json", std::process::id()));
+    std::fs::write(&path, key_file("service_account")).unwrap();
+    let account = ServiceAccount::from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        account.into_key().public_key_pem().unwrap(),
+        public.to_pem().unwrap()
+    );
+    let account = RSAKey::from_service_account_json(path.to_str().unwrap()).unwrap();
+    assert_eq!(account.private_key_id(), "0123abcd");
+    env::set_var("GOOGLE_APPLICATION_CREDENTIALS", &path);
+    let account = RSAKey::from_service_account_env().unwrap();
+    assert_eq!(
+        account.client_email(),
+        "signer@project.iam.gserviceaccount.com"
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert!(RSAKey::from_service_account_env().is_err());
+    env::remove_var("GOOGLE_APPLICATION_CREDENTIALS");
+}
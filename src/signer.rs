@@ -0,0 +1,351 @@
+use alloc::sync::Arc;
+use serde::Serialize;
+#[cfg(feature = "std")]
+use std::thread;
+
+use crate::prelude::*;
+#[cfg(feature = "std")]
+use crate::DetachedWriter;
+use crate::{check_conformance, resolve_key, Algorithm, Jwt, JwtErr, SegmentEncoder, SigningKey};
+
+/// Signs any number of bodies with the header of one `Jwt`, encoded once up front
+///
+/// `finalize` clones, serializes and encodes the header for every token. A `TokenSigner` does
+/// that once when it is made, leaving only the body and the signature to each `sign`. Keys with a
+/// `signing_context`, like `HmacKey`, also prepare their signing state once. Others, RSA, EC and
+/// EdDSA keys among them, still set up an OpenSSL signer per token. Tokens come out as `finalize`
+/// would produce them for the same body.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, EcKey, Jwt};
+///
+/// let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+/// let signer = Jwt::new((), &key, None).with_kid("v2").into_signer().unwrap();
+///
+/// for sub in ["alice", "bob"] {
+///     let token = signer.sign(&serde_json::json!({"sub": sub})).unwrap();
+///     let decoded = Jwt::verify(&token, &key, &[Algorithm::ES256]).unwrap();
+///     assert_eq!(decoded.header().kid(), Some("v2"));
+///     assert_eq!(decoded.claims()["sub"], sub);
+/// }
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+pub struct TokenSigner<K> {
+    key: K,
+    algo: Algorithm,
+    // The encoded header followed by the `.` the payload is appended to
+    header: String,
+    b64: bool,
+    encoder: Arc<dyn SegmentEncoder>,
+    strict: bool,
+    context: Option<Box<dyn SigningContext>>,
+    // `kid` of the key picked when the signer was made, see `SigningKey::signing_key`
+    key_kid: Option<String>,
+}
+
+/// Signing state of a key prepared for one algorithm, see `SigningKey::signing_context`
+pub trait SigningContext: Send + Sync {
+    /// Signs `input` as the key's `sign` would with the algorithm the context was made for
+    fn sign(&self, input: &[u8]) -> Result<Vec<u8>, JwtErr>;
+}
+
+impl<T: Serialize, K: SigningKey, H: Serialize> Jwt<T, K, H> {
+    /// Keeps the key, header and encoding of this token for a `TokenSigner`, dropping the body
+    ///
+    /// Fails for nested tokens, whose body is the inner token rather than claims.
+    pub fn into_signer(self) -> Result<TokenSigner<K>, JwtErr> {
+        if self.nested {
+            return Err(JwtErr::from(
+                "Nested tokens can not be signed by a TokenSigner",
+            ));
+        }
+        let key_kid = resolve_key(&self.pkey)?.kid().map(String::from);
+        let mut header = self.encode_protected(&self.header(key_kid.as_deref())?)?;
+        header.push('.');
+        // A key unfit for `algo` is left to fail on `sign`, as it would in `finalize`
+        let context = self.pkey.signing_context(&self.algo).ok().flatten();
+        Ok(TokenSigner {
+            algo: self.algo,
+            b64: self.header.b64(),
+            key: self.pkey,
+            header,
+            encoder: self.encoder,
+            strict: self.strict,
+            context,
+            key_kid,
+        })
+    }
+}
+
+impl<K: SigningKey> TokenSigner<K> {
+    /// Signs with `key` and a header holding nothing but `alg` and `typ` besides the key's `kid`
+    pub fn new(key: K, algo: Option<Algorithm>) -> Result<Self, JwtErr> {
+        Jwt::new((), key, algo).into_signer()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algo
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Produces the compact token for `body`, as `finalize` would
+    pub fn sign<T: Serialize + ?Sized>(&self, body: &T) -> Result<String, JwtErr> {
+        let mut token = String::new();
+        self.sign_into(body, &mut token)?;
+        Ok(token)
+    }
+
+    /// Like `sign`, appending the token to `out` as `Jwt::finalize_into` does
+    pub fn sign_into<T: Serialize + ?Sized>(
+        &self,
+        body: &T,
+        out: &mut String,
+    ) -> Result<(), JwtErr> {
+        let start = out.len();
+        let written = self.write_token(body, out, start);
+        if written.is_err() {
+            out.truncate(start);
+        }
+        written
+    }
+
+    fn write_token<T: Serialize + ?Sized>(
+        &self,
+        body: &T,
+        token: &mut String,
+        start: usize,
+    ) -> Result<(), JwtErr> {
+        let payload = serde_json::to_vec(body)?;
+        let payload_len = if self.b64 {
+            self.encoder.encoded_len(payload.len())
+        } else {
+            Some(payload.len())
+        };
+        token.reserve(self.header.len() + payload_len.unwrap_or(0) + 1);
+        token.push_str(&self.header);
+        if self.b64 {
+            self.encoder.encode_segment_into(&payload, token);
+        } else {
+            // RFC 7797 5.2, as `check_compact` does for `finalize`
+            let payload = String::from_utf8(payload)
+                .map_err(|_| JwtErr::from("Unencoded payload is not valid UTF-8"))?;
+            if payload.contains('.') {
+                return Err(JwtErr::from(
+                    "An unencoded payload containing '.' can only be sent detached",
+                ));
+            }
+            token.push_str(&payload);
+        }
+        let input = &token.as_bytes()[start..];
+        let signature = match &self.context {
+            Some(context) => context.sign(input)?,
+            None => self.picked_key()?.sign(&self.algo, input)?,
+        };
+        token.push('.');
+        self.encoder.encode_segment_into(&signature, token);
+        check_conformance(self.strict, &token[start..])
+    }
+
+    /// Starts a detached token, `header..signature`, over a payload written in pieces
+    ///
+    /// Neither the payload nor its encoding is held in memory as a whole, if the key has an
+    /// `incremental_signer`. The token verifies with `Jwt::verify_detached` given the octets written.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt, TokenSigner};
+    /// use std::io;
+    ///
+    /// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let document = br#"{"doc": "a few megabytes"}"#;
+    /// let signer = TokenSigner::new(key(), None).unwrap();
+    /// let mut writer = signer.detached_writer().unwrap();
+    /// io::copy(&mut &document[..], &mut writer).unwrap();
+    /// let token = writer.finish().unwrap();
+    ///
+    /// assert!(Jwt::verify_detached(&token, document, &key(), &[Algorithm::HS256]).is_ok());
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn detached_writer(&self) -> Result<DetachedWriter<'_>, JwtErr> {
+        DetachedWriter::new(
+            self.picked_key()?,
+            self.algo,
+            &self.header,
+            self.b64,
+            &*self.encoder,
+        )
+    }
+
+    /// Key picked as when the signer was made, the header names it by its `kid`
+    fn picked_key(&self) -> Result<&dyn SigningKey, JwtErr> {
+        let key = resolve_key(&self.key)?;
+        if key.kid() != self.key_kid.as_deref() {
+            return Err(JwtErr::from(
+                "The key of this TokenSigner rotated, make a new one",
+            ));
+        }
+        Ok(key)
+    }
+
+    /// Signs each of `bodies` in turn, failing on the first body that can not be signed
+    pub fn sign_batch<T: Serialize>(&self, bodies: &[T]) -> Result<Vec<String>, JwtErr> {
+        bodies.iter().map(|body| self.sign(body)).collect()
+    }
+
+    /// Like `sign_batch`, spreading `bodies` over `threads` scoped threads
+    ///
+    /// The tokens come back in the order of `bodies`. One thread or fewer signs on the calling one.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{HmacKey, TokenSigner};
+    ///
+    /// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let signer = TokenSigner::new(key, None).unwrap();
+    /// let bodies: Vec<_> = (0..1000).map(|n| serde_json::json!({"n": n})).collect();
+    /// let tokens = signer.sign_batch_parallel(&bodies, 4).unwrap();
+    /// assert_eq!(tokens, signer.sign_batch(&bodies).unwrap());
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn sign_batch_parallel<T: Serialize + Sync>(
+        &self,
+        bodies: &[T],
+        threads: usize,
+    ) -> Result<Vec<String>, JwtErr>
+    where
+        K: Sync,
+    {
+        if threads <= 1 || bodies.len() <= 1 {
+            return self.sign_batch(bodies);
+        }
+        let chunk = bodies.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = bodies
+                .chunks(chunk)
+                .map(|bodies| scope.spawn(move || self.sign_batch(bodies)))
+                .collect();
+            let mut tokens = Vec::with_capacity(bodies.len());
+            for worker in workers {
+                match worker.join() {
+                    Ok(signed) => tokens.extend(signed?),
+                    Err(_) => return Err(JwtErr::from("A signing thread panicked")),
+                }
+            }
+            Ok(tokens)
+        })
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_token_signer() {
+    use crate::{HmacKey, RSAKey};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jwt = |body: serde_json::Value| {
+        Jwt::new(body, key(), None)
+            .with_typ("at+jwt")
+            .with_kid("k1")
+            .with_header_param("ext", true.into())
+    };
+    let signer = jwt(serde_json::Value::Null).into_signer().unwrap();
+    assert_eq!(signer.algorithm(), Algorithm::HS256);
+    for n in 0..3 {
+        let body = serde_json::json!({"n": n});
+        assert_eq!(signer.sign(&body).unwrap(), jwt(body).finalize().unwrap());
+    }
+
+    let signer = Jwt::new((), key(), None)
+        .with_unencoded_payload()
+        .with_legacy_padding()
+        .into_signer()
+        .unwrap();
+    let token = signer.sign(&serde_json::json!({"n": 1})).unwrap();
+    assert!(token.contains(r#".{"n":1}."#));
+    assert_eq!(
+        Jwt::verify(&token, &key(), &[Algorithm::HS256])
+            .unwrap()
+            .claims()["n"],
+        1
+    );
+    assert!(signer.sign(&serde_json::json!({"n": 1.5})).is_err());
+
+    let signer = Jwt::new((), key(), None)
+        .with_strict_conformance()
+        .into_signer()
+        .unwrap();
+    assert!(signer.sign(&serde_json::json!({"sub": "me"})).is_ok());
+    assert!(signer.sign(&serde_json::json!({"exp": "soon"})).is_err());
+
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let signer = TokenSigner::new(&rsa, Some(Algorithm::PS256)).unwrap();
+    let token = signer.sign("claims").unwrap();
+    assert_eq!(
+        Jwt::verify(&token, &rsa, &[Algorithm::PS256])
+            .unwrap()
+            .header()
+            .alg(),
+        "PS256"
+    );
+    assert!(TokenSigner::new(&rsa, Some(Algorithm::HS256))
+        .unwrap()
+        .sign("claims")
+        .is_err());
+
+    let inner = Jwt::new("claims", key(), None).finalize().unwrap();
+    assert!(Jwt::new_nested(&inner, key(), None).into_signer().is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_sign_batch() {
+    use crate::EcKey;
+
+    let key = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let signer = TokenSigner::new(&key, None).unwrap();
+    let bodies: Vec<_> = (0..37).map(|n| serde_json::json!({"n": n})).collect();
+    for threads in [0, 1, 4, 64] {
+        let tokens = signer.sign_batch_parallel(&bodies, threads).unwrap();
+        assert_eq!(tokens.len(), bodies.len());
+        for (n, token) in tokens.iter().enumerate() {
+            assert_eq!(
+                Jwt::verify(token, &key, &[Algorithm::ES256])
+                    .unwrap()
+                    .claims()["n"],
+                n
+            );
+        }
+    }
+    assert!(signer.sign_batch::<u8>(&[]).unwrap().is_empty());
+    assert!(signer.sign_batch_parallel::<u8>(&[], 4).unwrap().is_empty());
+
+    let signer = Jwt::new((), &key, None)
+        .with_unencoded_payload()
+        .into_signer()
+        .unwrap();
+    let bodies = [
+        serde_json::json!(1),
+        serde_json::json!(2.5),
+        serde_json::json!(3),
+    ];
+    assert!(signer.sign_batch(&bodies[..1]).is_ok());
+    assert!(signer.sign_batch(&bodies).is_err());
+    assert!(signer.sign_batch_parallel(&bodies, 3).is_err());
+}
@@ -0,0 +1,209 @@
+use std::io::{self, Write};
+
+use crate::{Algorithm, JwtErr, SegmentEncoder, SigningKey};
+
+// Payload octets are encoded in runs of a multiple of 3 bytes, which base64 encodes without
+// padding, so the segment reads as if it had been encoded in one go
+const ENCODE_CHUNK: usize = 3 * 4096;
+
+/// A signature computed over input written to it in pieces, see `SigningKey::incremental_signer`
+pub trait IncrementalSigner: Write {
+    /// Ends the input, returning the signature `SigningKey::sign` would have made over all of it
+    fn finish(self: Box<Self>) -> Result<Vec<u8>, JwtErr>;
+}
+
+/// `IncrementalSigner` over `writer`, with `finish` turning it into the signature
+#[cfg(feature = "openssl")]
+pub(crate) struct Incremental<W, F> {
+    writer: W,
+    finish: F,
+}
+
+#[cfg(feature = "openssl")]
+impl<W, F> Incremental<W, F>
+where
+    W: Write,
+    F: FnOnce(W) -> Result<Vec<u8>, JwtErr>,
+{
+    pub(crate) fn new(writer: W, finish: F) -> Self {
+        Incremental { writer, finish }
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl<W: Write, F> Write for Incremental<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl<W, F> IncrementalSigner for Incremental<W, F>
+where
+    W: Write,
+    F: FnOnce(W) -> Result<Vec<u8>, JwtErr>,
+{
+    fn finish(self: Box<Self>) -> Result<Vec<u8>, JwtErr> {
+        (self.finish)(self.writer)
+    }
+}
+
+/// Keys that can only sign all of the input at once get it collected
+struct Buffered<'a, K: ?Sized> {
+    key: &'a K,
+    algo: Algorithm,
+    input: Vec<u8>,
+}
+
+impl<K: ?Sized> Write for Buffered<'_, K> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<K: SigningKey + ?Sized> IncrementalSigner for Buffered<'_, K> {
+    fn finish(self: Box<Self>) -> Result<Vec<u8>, JwtErr> {
+        self.key.sign(&self.algo, &self.input)
+    }
+}
+
+/// Writes the payload of a detached token into its signature, made by `TokenSigner::detached_writer`
+///
+/// Octets written are the payload as `Jwt::verify_detached` expects it. They are encoded and fed
+/// to the key as they arrive, unless the key has no `incremental_signer`, then they are buffered.
+pub struct DetachedWriter<'a> {
+    signer: Box<dyn IncrementalSigner + 'a>,
+    encoder: &'a dyn SegmentEncoder,
+    header: &'a str,
+    b64: bool,
+    pending: Vec<u8>,
+}
+
+impl<'a> DetachedWriter<'a> {
+    /// `header` is the encoded header followed by its `.`
+    pub(crate) fn new<K: SigningKey + ?Sized>(
+        key: &'a K,
+        algo: Algorithm,
+        header: &'a str,
+        b64: bool,
+        encoder: &'a dyn SegmentEncoder,
+    ) -> Result<Self, JwtErr> {
+        let mut signer = match key.incremental_signer(&algo)? {
+            Some(signer) => signer,
+            None => Box::new(Buffered {
+                key,
+                algo,
+                input: Vec::new(),
+            }),
+        };
+        signer.write_all(header.as_bytes())?;
+        Ok(DetachedWriter {
+            signer,
+            encoder,
+            header: header.strip_suffix('.').unwrap_or(header),
+            b64,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Signs what was written, returning the token as `header..signature`
+    pub fn finish(mut self) -> Result<String, JwtErr> {
+        if self.b64 {
+            let tail = self.encoder.encode_segment(&self.pending);
+            self.signer.write_all(tail.as_bytes())?;
+        }
+        let signature = self.signer.finish()?;
+        Ok(format!(
+            "{}..{}",
+            self.header,
+            self.encoder.encode_segment(&signature)
+        ))
+    }
+}
+
+impl Write for DetachedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.b64 {
+            return self.signer.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() >= ENCODE_CHUNK {
+            let whole = self.pending.len() - self.pending.len() % ENCODE_CHUNK;
+            let encoded = self.encoder.encode_segment(&self.pending[..whole]);
+            self.signer.write_all(encoded.as_bytes())?;
+            self.pending.drain(..whole);
+        }
+        Ok(buf.len())
+    }
+
+    // Only the key's side is flushed, a partial run can not be encoded before the payload ends
+    fn flush(&mut self) -> io::Result<()> {
+        self.signer.flush()
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_detached_writer() {
+    use crate::{EcKey, Ed25519Key, HmacKey, Jwt, RSAKey, VerifyingKey};
+
+    let document =
+        serde_json::to_vec(&serde_json::json!({"doc": "large ".repeat(20_000)})).unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&document).unwrap();
+    let write = |mut writer: DetachedWriter, chunk: usize| {
+        for piece in document.chunks(chunk) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.finish().unwrap()
+    };
+
+    // Deterministic signatures match the ones `finalize_detached` makes over the whole payload
+    let hmac = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let cases = [
+        Jwt::new(body.clone(), &hmac as &dyn SigningKey, None),
+        Jwt::new(body.clone(), &rsa as &dyn SigningKey, None).with_legacy_padding(),
+    ];
+    for jwt in cases {
+        let expected = jwt.finalize_detached().unwrap();
+        let signer = jwt.into_signer().unwrap();
+        for chunk in [1, 4095, ENCODE_CHUNK, ENCODE_CHUNK + 1, document.len()] {
+            assert_eq!(write(signer.detached_writer().unwrap(), chunk), expected);
+        }
+    }
+
+    // ECDSA signs incrementally, EdDSA needs the input at once and has it buffered
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let ed = Ed25519Key::from_pem("random_ed25519_for_testing").unwrap();
+    assert!(ec.incremental_signer(&Algorithm::ES256).unwrap().is_some());
+    assert!(ed.incremental_signer(&Algorithm::EdDSA).unwrap().is_none());
+    let signers = vec![
+        Jwt::new((), &ec as &dyn SigningKey, None),
+        Jwt::new((), &ed as &dyn SigningKey, None),
+        Jwt::new((), &ec as &dyn SigningKey, None).with_unencoded_payload(),
+    ];
+    let keys: [&dyn VerifyingKey; 3] = [&ec, &ed, &ec];
+    for (jwt, key) in signers.into_iter().zip(keys) {
+        let signer = jwt.into_signer().unwrap();
+        let token = write(signer.detached_writer().unwrap(), 1000);
+        let decoded = Jwt::verify_detached(
+            &token,
+            &document,
+            key,
+            &[Algorithm::ES256, Algorithm::EdDSA],
+        )
+        .unwrap();
+        assert_eq!(decoded.claims(), &body);
+    }
+    assert!(ec.incremental_signer(&Algorithm::ES384).is_err());
+}
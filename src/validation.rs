@@ -0,0 +1,776 @@
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+use serde::Deserialize;
+
+use crate::clock::{default_clock, no_clock, Clock};
+use crate::prelude::*;
+use crate::{Algorithm, Audience, JwtErr, JwtHeader, TokenSegment};
+
+/// Policy `Jwt::verify_with` checks the header and claims of a token against, once its signature
+/// has been verified
+///
+/// Only the algorithms passed to `ValidationOptions::new` are accepted. Beyond that `exp` and
+/// `nbf` are checked without any leeway while `iat`, `iss` and `aud` are ignored, which is what
+/// `Jwt::verify` applies.
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, ClaimsBuilder, HmacKey, Jwt, ValidationOptions};
+/// use core::time::Duration;
+///
+/// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let claims = ClaimsBuilder::new()
+///     .issuer("auth.example.com")
+///     .audience("api")
+///     .expires_in(Duration::from_secs(60))
+///     .build_registered()
+///     .unwrap();
+/// let token = Jwt::new(claims, key(), None).finalize().unwrap();
+///
+/// let options = ValidationOptions::new(&[Algorithm::HS256])
+///     .with_leeway(30)
+///     .with_issuer("auth.example.com")
+///     .with_audience("api");
+/// assert!(Jwt::verify_with(&token, &key(), &options).is_ok());
+/// assert!(Jwt::verify_with(&token, &key(), &options.with_audience("other")).is_err());
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct ValidationOptions {
+    algorithms: Vec<Algorithm>,
+    leeway: u64,
+    validate_exp: bool,
+    validate_nbf: bool,
+    validate_iat: bool,
+    max_age: Option<Duration>,
+    issuers: Vec<String>,
+    issuer_prefixes: Vec<String>,
+    audiences: Vec<String>,
+    subject: Option<SubjectValidator>,
+    validators: Vec<ClaimValidator>,
+    typ: Option<String>,
+    understood_crit: Vec<String>,
+    required: Vec<String>,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+type SubjectValidator = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+type ClaimValidator = Arc<dyn Fn(&serde_json::Value) -> Result<(), JwtErr> + Send + Sync>;
+
+impl fmt::Debug for ValidationOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ValidationOptions")
+            .field("algorithms", &self.algorithms)
+            .field("leeway", &self.leeway)
+            .field("validate_exp", &self.validate_exp)
+            .field("validate_nbf", &self.validate_nbf)
+            .field("validate_iat", &self.validate_iat)
+            .field("max_age", &self.max_age)
+            .field("issuers", &self.issuers)
+            .field("issuer_prefixes", &self.issuer_prefixes)
+            .field("audiences", &self.audiences)
+            .field(
+                "subject",
+                &self.subject.as_ref().map(|_| "Fn(&str) -> bool"),
+            )
+            .field("validators", &self.validators.len())
+            .field("typ", &self.typ)
+            .field("understood_crit", &self.understood_crit)
+            .field("required", &self.required)
+            .field("clock", &self.clock)
+            .finish()
+    }
+}
+
+impl ValidationOptions {
+    /// Accepts tokens signed with one of `algorithms`, see `Jwt::verify_with` for the other checks
+    ///
+    /// There is no default, an empty list rejects every token.
+    pub fn new(algorithms: &[Algorithm]) -> Self {
+        ValidationOptions {
+            algorithms: algorithms.to_vec(),
+            leeway: 0,
+            validate_exp: true,
+            validate_nbf: true,
+            validate_iat: false,
+            max_age: None,
+            issuers: Vec::new(),
+            issuer_prefixes: Vec::new(),
+            audiences: Vec::new(),
+            subject: None,
+            validators: Vec::new(),
+            typ: None,
+            understood_crit: Vec::new(),
+            required: Vec::new(),
+            clock: default_clock(),
+        }
+    }
+
+    /// Seconds of clock skew tolerated by the `exp`, `nbf` and `iat` checks
+    pub fn with_leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Whether tokens past their `exp` are rejected, on by default
+    pub fn validate_exp(mut self, validate: bool) -> Self {
+        self.validate_exp = validate;
+        self
+    }
+
+    /// Whether tokens before their `nbf` are rejected, on by default
+    pub fn validate_nbf(mut self, validate: bool) -> Self {
+        self.validate_nbf = validate;
+        self
+    }
+
+    /// Whether tokens issued in the future are rejected, off by default
+    pub fn validate_iat(mut self, validate: bool) -> Self {
+        self.validate_iat = validate;
+        self
+    }
+
+    /// Rejects tokens issued more than `max_age` ago, even if they have not expired yet
+    ///
+    /// Tokens without `iat` are rejected, the leeway is applied like for `exp`.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Requires `iss` to equal `iss`
+    pub fn with_issuer(self, iss: &str) -> Self {
+        self.with_issuers(&[iss])
+    }
+
+    /// Requires `iss` to equal one of `issuers`, or to start with one of the prefixes
+    pub fn with_issuers(mut self, issuers: &[&str]) -> Self {
+        self.issuers = issuers.iter().map(|iss| iss.to_string()).collect();
+        self
+    }
+
+    /// Requires `iss` to start with one of `prefixes`, or to equal one of the issuers
+    ///
+    /// Prefixes should end in a separator, `https://auth.example.com/` and not
+    /// `https://auth.example.com`, which `https://auth.example.com.evil.org` would match.
+    pub fn with_issuer_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.issuer_prefixes = prefixes.iter().map(|prefix| prefix.to_string()).collect();
+        self
+    }
+
+    /// Requires `aud` to be or to contain `aud`
+    pub fn with_audience(self, aud: &str) -> Self {
+        self.with_audiences(&[aud])
+    }
+
+    /// Requires `aud` to be or to contain at least one of `auds`
+    pub fn with_audiences(mut self, auds: &[&str]) -> Self {
+        self.audiences = auds.iter().map(|aud| aud.to_string()).collect();
+        self
+    }
+
+    /// Requires `sub` to be present and accepted by `validator`
+    ///
+    /// ```
+    /// use smpl_jwt::{Algorithm, ValidationOptions};
+    ///
+    /// // Subjects look like "tenant:user"
+    /// let options = ValidationOptions::new(&[Algorithm::HS256]).with_subject_validator(|sub| {
+    ///     matches!(sub.split_once(':'), Some((tenant, user)) if !tenant.is_empty() && !user.is_empty())
+    /// });
+    /// ```
+    pub fn with_subject_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.subject = Some(Arc::new(validator));
+        self
+    }
+
+    /// Adds a check run on the claims once all built-in ones passed, its error fails verification
+    ///
+    /// Validators run in the order they were added, the first failing one stops verification.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt, JwtErr, ValidationOptions};
+    ///
+    /// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let token = Jwt::new(serde_json::json!({"department": ""}), key(), None)
+    ///     .finalize()
+    ///     .unwrap();
+    ///
+    /// let options = ValidationOptions::new(&[Algorithm::HS256]).with_claim_validator(|claims| {
+    ///     match claims["department"].as_str() {
+    ///         Some(department) if !department.is_empty() => Ok(()),
+    ///         _ => Err(JwtErr::from("department must not be empty")),
+    ///     }
+    /// });
+    /// let err = Jwt::verify_with(&token, &key(), &options).unwrap_err();
+    /// assert_eq!(err.to_string(), "department must not be empty");
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn with_claim_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Result<(), JwtErr> + Send + Sync + 'static,
+    {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Requires the `typ` header to match, see `JwtHeader::typ_matches`
+    pub fn with_typ(mut self, typ: &str) -> Self {
+        self.typ = Some(typ.to_string());
+        self
+    }
+
+    /// Extensions accepted in the `crit` header, tokens listing any other one are rejected
+    pub fn with_understood_crit(mut self, names: &[&str]) -> Self {
+        self.understood_crit = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Takes the time `exp`, `nbf` and `iat` are compared to from `clock` instead of the system
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Rejects tokens lacking any of `claims`, a `null` claim counts as missing
+    ///
+    /// Absent time claims are otherwise not checked at all, `require_claims(&["exp"])` makes sure
+    /// every accepted token expires.
+    pub fn require_claims(mut self, claims: &[&str]) -> Self {
+        self.required = claims.iter().map(|claim| claim.to_string()).collect();
+        self
+    }
+
+    /// Fails unless `algo` is allowed, checked before the signature
+    pub(crate) fn check_algorithm(&self, algo: &Algorithm) -> Result<(), JwtErr> {
+        if self.algorithms.contains(algo) {
+            Ok(())
+        } else {
+            Err(JwtErr::from(ValidationError::DisallowedAlgorithm(
+                algo.to_string(),
+            )))
+        }
+    }
+
+    /// Checks `header` and `claims` against the policy at the time of its clock
+    pub(crate) fn validate(
+        &self,
+        header: &JwtHeader,
+        claims: &serde_json::Value,
+    ) -> Result<(), JwtErr> {
+        self.check_crit(header)?;
+        if let Some(typ) = &self.typ {
+            if !header.typ_matches(typ) {
+                return Err(JwtErr::from(format!("Token typ is not {}", typ).as_str()));
+            }
+        }
+        self.validate_claims(claims, self.clock.as_ref().map(|clock| clock.unix_now()))
+    }
+
+    /// `now` is `None` without a clock, then only tokens without time claims to check pass
+    fn validate_claims(&self, claims: &serde_json::Value, now: Option<i64>) -> Result<(), JwtErr> {
+        if let Some(missing) = self.required.iter().find(|claim| {
+            claims
+                .get(claim.as_str())
+                .is_none_or(|value| value.is_null())
+        }) {
+            return Err(JwtErr::from(ValidationError::MissingClaim(
+                missing.to_string(),
+            )));
+        }
+        let now = || now.ok_or_else(no_clock);
+        let leeway = self.leeway as f64;
+        if self.validate_exp {
+            if let Some(exp) = numeric_date(claims, "exp")? {
+                let now = now()?;
+                if now as f64 - leeway >= exp {
+                    return Err(JwtErr::from(ValidationError::Expired {
+                        exp: exp as i64,
+                        now,
+                    }));
+                }
+            }
+        }
+        if self.validate_nbf {
+            if let Some(nbf) = numeric_date(claims, "nbf")? {
+                let now = now()?;
+                if now as f64 + leeway < nbf {
+                    return Err(JwtErr::from(ValidationError::NotYetValid {
+                        nbf: nbf as i64,
+                        now,
+                    }));
+                }
+            }
+        }
+        if self.validate_iat {
+            if let Some(iat) = numeric_date(claims, "iat")? {
+                if now()? as f64 + leeway < iat {
+                    return Err(JwtErr::from("Token is issued in the future"));
+                }
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            let iat = numeric_date(claims, "iat")?
+                .ok_or_else(|| JwtErr::from(ValidationError::MissingClaim("iat".to_string())))?;
+            if now()? as f64 - leeway >= iat + max_age.as_secs_f64() {
+                return Err(JwtErr::from("Token was issued too long ago"));
+            }
+        }
+        if !self.issuers.is_empty() || !self.issuer_prefixes.is_empty() {
+            let iss = claims.get("iss").and_then(|iss| iss.as_str());
+            let trusted = iss.is_some_and(|iss| {
+                self.issuers.iter().any(|trusted| trusted == iss)
+                    || self
+                        .issuer_prefixes
+                        .iter()
+                        .any(|prefix| iss.starts_with(prefix.as_str()))
+            });
+            if !trusted {
+                return Err(JwtErr::from(ValidationError::UntrustedIssuer(
+                    iss.map(str::to_string),
+                )));
+            }
+        }
+        if let Some(validator) = &self.subject {
+            let sub = claims.get("sub").and_then(|sub| sub.as_str());
+            if !sub.is_some_and(|sub| validator(sub)) {
+                return Err(JwtErr::from(ValidationError::InvalidSubject(
+                    sub.map(str::to_string),
+                )));
+            }
+        }
+        if !self.audiences.is_empty() {
+            let aud = match claims.get("aud") {
+                Some(aud) => Audience::deserialize(aud).map_err(|_| {
+                    JwtErr::from("aud claim must be a string or an array of strings")
+                })?,
+                None => return Err(JwtErr::from(ValidationError::InvalidAudience(None))),
+            };
+            if !self.audiences.iter().any(|expected| aud.contains(expected)) {
+                return Err(JwtErr::from(ValidationError::InvalidAudience(Some(aud))));
+            }
+        }
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator(claims))
+    }
+
+    fn check_crit(&self, header: &JwtHeader) -> Result<(), JwtErr> {
+        let crit = match header.crit() {
+            Some(crit) => crit,
+            None => return Ok(()),
+        };
+        if crit.is_empty() {
+            return Err(JwtErr::from("crit header must not be empty"));
+        }
+        for name in crit {
+            if REGISTERED_HEADERS.contains(&name.as_str()) {
+                return Err(JwtErr::from(
+                    format!("crit header must not list the registered member {}", name).as_str(),
+                ));
+            }
+            if !self.understood_crit.contains(name)
+                && !IMPLEMENTED_EXTENSIONS.contains(&name.as_str())
+            {
+                return Err(JwtErr::from(
+                    format!("Critical header extension {} is not understood", name).as_str(),
+                ));
+            }
+            if header.param(name).is_none() {
+                return Err(JwtErr::from(
+                    format!("Critical header extension {} is missing", name).as_str(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a token was rejected, see `JwtErr::validation_error`
+///
+/// Covers the failures callers tell apart, e.g. to answer an expired token differently from a
+/// forged one. More variants may be added, matches need a wildcard arm.
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, HmacKey, Jwt, ValidationError};
+///
+/// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let token = Jwt::new(serde_json::json!({"exp": 1}), &key, None).finalize().unwrap();
+/// let err = Jwt::verify(&token, &key, &[Algorithm::HS256]).err().unwrap();
+/// let status = match err.validation_error() {
+///     Some(ValidationError::Expired { .. }) => 401,
+///     Some(ValidationError::MalformedToken { .. }) => 400,
+///     _ => 403,
+/// };
+/// assert_eq!(status, 401);
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// `iss` matched none of the trusted issuers, `None` if the token had no `iss`
+    UntrustedIssuer(Option<String>),
+    /// A claim listed in `require_claims` is absent
+    MissingClaim(String),
+    /// The token's `alg` is not one of the algorithms the options allow
+    DisallowedAlgorithm(String),
+    /// `sub` was rejected by the subject validator, `None` if the token had no `sub`
+    InvalidSubject(Option<String>),
+    /// `exp` has passed, both in seconds since the epoch with any fraction dropped
+    Expired { exp: i64, now: i64 },
+    /// `nbf` is still to come, in seconds like `Expired`
+    NotYetValid { nbf: i64, now: i64 },
+    /// The signature does not verify with the key
+    InvalidSignature,
+    /// `aud` holds none of the accepted audiences, `None` if the token had no `aud`
+    InvalidAudience(Option<Audience>),
+    /// The token does not decode, `None` if it does not even split into its segments
+    MalformedToken { segment: Option<TokenSegment> },
+    /// No key carries the `kid` the token names
+    UnknownKid(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::UntrustedIssuer(Some(iss)) => {
+                write!(f, "Token issuer {} is not trusted", iss)
+            }
+            ValidationError::UntrustedIssuer(None) => write!(f, "Token has no issuer"),
+            ValidationError::MissingClaim(claim) => write!(f, "Token has no {} claim", claim),
+            ValidationError::DisallowedAlgorithm(alg) => {
+                write!(f, "Algorithm {} is not allowed", alg)
+            }
+            ValidationError::InvalidSubject(Some(sub)) => {
+                write!(f, "Token subject {} is malformed", sub)
+            }
+            ValidationError::InvalidSubject(None) => write!(f, "Token has no subject"),
+            ValidationError::Expired { .. } => write!(f, "Token has expired"),
+            ValidationError::NotYetValid { .. } => write!(f, "Token is not valid yet"),
+            ValidationError::InvalidSignature => write!(f, "Invalid signature"),
+            ValidationError::InvalidAudience(Some(_)) => {
+                write!(f, "Token audience is not accepted")
+            }
+            ValidationError::InvalidAudience(None) => write!(f, "Token has no audience"),
+            ValidationError::MalformedToken {
+                segment: Some(segment),
+            } => {
+                write!(f, "The {} segment is malformed", segment)
+            }
+            ValidationError::MalformedToken { segment: None } => {
+                write!(f, "Token must consist of three segments")
+            }
+            ValidationError::UnknownKid(kid) => write!(f, "No key with kid {}", kid),
+        }
+    }
+}
+
+/// Header members defined by RFC 7515 and RFC 7519, which may not be listed in `crit`
+const REGISTERED_HEADERS: &[&str] = &[
+    "alg", "jku", "jwk", "kid", "x5u", "x5c", "x5t", "x5t#S256", "typ", "cty", "crit",
+];
+
+/// Extensions the crate implements itself, understood without `with_understood_crit`
+const IMPLEMENTED_EXTENSIONS: &[&str] = &["b64"];
+
+fn numeric_date(claims: &serde_json::Value, claim: &str) -> Result<Option<f64>, JwtErr> {
+    match claims.get(claim) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| JwtErr::from(format!("{} claim must be a NumericDate", claim).as_str())),
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_validate_time_claims() {
+    use crate::{HmacKey, Jwt};
+
+    let now = 1_000_000;
+    let options = ValidationOptions::new(&[Algorithm::HS256]);
+    let validate =
+        |options: &ValidationOptions, claims| options.validate_claims(&claims, Some(now));
+    assert!(validate(&options, serde_json::json!({})).is_ok());
+    assert!(validate(&options, serde_json::json!({"exp": now + 1})).is_ok());
+    assert!(validate(&options, serde_json::json!({"exp": now})).is_err());
+    assert!(validate(&options, serde_json::json!({"nbf": now})).is_ok());
+    let err = validate(&options, serde_json::json!({"nbf": now + 1})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::NotYetValid { nbf: now + 1, now })
+    );
+    assert!(validate(&options, serde_json::json!({"exp": "tomorrow"})).is_err());
+    assert!(validate(&options, serde_json::json!({"iat": now + 100})).is_ok());
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(serde_json::json!({"exp": 1}), key, Some(Algorithm::HS256))
+        .finalize()
+        .unwrap();
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    assert!(Jwt::verify(&token, &key, &[Algorithm::HS256]).is_err());
+    let lenient = ValidationOptions::new(&[Algorithm::HS256]).validate_exp(false);
+    assert!(Jwt::verify_with(&token, &key, &lenient).is_ok());
+}
+
+#[test]
+fn test_validation_leeway_and_toggles() {
+    let now = 1_000_000;
+    let validate = |options: ValidationOptions, claims| options.validate_claims(&claims, Some(now));
+    let leeway = || ValidationOptions::new(&[Algorithm::HS256]).with_leeway(30);
+
+    assert!(validate(leeway(), serde_json::json!({"exp": now - 29})).is_ok());
+    assert!(validate(leeway(), serde_json::json!({"exp": now - 30})).is_err());
+    assert!(validate(leeway(), serde_json::json!({"nbf": now + 30})).is_ok());
+    assert!(validate(leeway(), serde_json::json!({"nbf": now + 31})).is_err());
+
+    let iat = || leeway().validate_iat(true);
+    assert!(validate(iat(), serde_json::json!({"iat": now + 30})).is_ok());
+    assert!(validate(iat(), serde_json::json!({"iat": now + 31})).is_err());
+    assert!(validate(iat(), serde_json::json!({"iat": "now"})).is_err());
+
+    let off = || {
+        ValidationOptions::new(&[Algorithm::HS256])
+            .validate_exp(false)
+            .validate_nbf(false)
+    };
+    assert!(validate(off(), serde_json::json!({"exp": 0, "nbf": now * 2})).is_ok());
+}
+
+#[test]
+fn test_validation_issuer_and_audience() {
+    let validate = |options: ValidationOptions, claims| options.validate_claims(&claims, Some(0));
+    let iss = || ValidationOptions::new(&[Algorithm::HS256]).with_issuer("me");
+    assert!(validate(iss(), serde_json::json!({"iss": "me"})).is_ok());
+    assert!(validate(iss(), serde_json::json!({"iss": "you"})).is_err());
+    assert!(validate(iss(), serde_json::json!({})).is_err());
+    assert!(validate(iss(), serde_json::json!("nested")).is_err());
+
+    let trusted = || {
+        ValidationOptions::new(&[Algorithm::HS256])
+            .with_issuers(&["billing", "payroll"])
+            .with_issuer_prefixes(&["https://auth.example.com/"])
+    };
+    assert!(validate(trusted(), serde_json::json!({"iss": "payroll"})).is_ok());
+    assert!(validate(
+        trusted(),
+        serde_json::json!({"iss": "https://auth.example.com/eu"})
+    )
+    .is_ok());
+    let err = validate(trusted(), serde_json::json!({"iss": "payroll-eu"})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::UntrustedIssuer(Some(
+            "payroll-eu".to_string()
+        )))
+    );
+    assert_eq!(err.to_string(), "Token issuer payroll-eu is not trusted");
+    let err = validate(trusted(), serde_json::json!({"iss": 1})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::UntrustedIssuer(None))
+    );
+    let err = validate(trusted(), serde_json::json!({"exp": 0})).unwrap_err();
+    assert!(matches!(
+        err.validation_error(),
+        Some(&ValidationError::Expired { exp: 0, .. })
+    ));
+
+    let aud = || ValidationOptions::new(&[Algorithm::HS256]).with_audience("api");
+    assert!(validate(aud(), serde_json::json!({"aud": "api"})).is_ok());
+    assert!(validate(aud(), serde_json::json!({"aud": ["web", "api"]})).is_ok());
+    let err = validate(aud(), serde_json::json!({"aud": ["web"]})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::InvalidAudience(Some(Audience::Multiple(
+            vec!["web".to_string()]
+        ))))
+    );
+    let err = validate(aud(), serde_json::json!({})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::InvalidAudience(None))
+    );
+    assert!(validate(aud(), serde_json::json!({"aud": [1]})).is_err());
+
+    let auds = || ValidationOptions::new(&[Algorithm::HS256]).with_audiences(&["web", "mobile"]);
+    assert!(validate(auds(), serde_json::json!({"aud": "mobile"})).is_ok());
+    assert!(validate(auds(), serde_json::json!({"aud": ["api", "web"]})).is_ok());
+    assert!(validate(auds(), serde_json::json!({"aud": ["api", "admin"]})).is_err());
+    assert!(validate(auds(), serde_json::json!({"aud": []})).is_err());
+    // Without an expected audience the claim is not looked at
+    assert!(validate(
+        ValidationOptions::new(&[Algorithm::HS256]),
+        serde_json::json!({"aud": 1})
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_validation_subject() {
+    let validate = |options: &ValidationOptions, claims| options.validate_claims(&claims, Some(0));
+    let options = ValidationOptions::new(&[Algorithm::HS256]).with_subject_validator(
+        |sub| matches!(sub.split_once(':'), Some((tenant, _)) if tenant.starts_with("t-")),
+    );
+    assert!(validate(&options, serde_json::json!({"sub": "t-42:alice"})).is_ok());
+    let err = validate(&options, serde_json::json!({"sub": "alice"})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::InvalidSubject(Some("alice".to_string())))
+    );
+    let err = validate(&options, serde_json::json!({})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::InvalidSubject(None))
+    );
+    assert!(format!("{:?}", options).contains("Fn(&str) -> bool"));
+    assert!(validate(
+        &ValidationOptions::new(&[Algorithm::HS256]),
+        serde_json::json!({"sub": 1})
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_validation_claim_validators() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let options = ValidationOptions::new(&[Algorithm::HS256])
+        .with_claim_validator(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .with_claim_validator(|claims| match claims.get("role") {
+            Some(role) if role == "admin" => Ok(()),
+            _ => Err(JwtErr::from("role must be admin")),
+        })
+        .with_claim_validator(|_| Err(JwtErr::from("unreachable for non admins")));
+    let validate = |claims| options.validate_claims(&claims, Some(0));
+
+    let err = validate(serde_json::json!({"role": "user"})).unwrap_err();
+    assert_eq!(err.to_string(), "role must be admin");
+    let err = validate(serde_json::json!({"role": "admin"})).unwrap_err();
+    assert_eq!(err.to_string(), "unreachable for non admins");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Built-in checks come first
+    let err = validate(serde_json::json!({"role": "admin", "exp": 0})).unwrap_err();
+    assert_eq!(err.to_string(), "Token has expired");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_validation_algorithms() {
+    use crate::{HmacKey, Jwt, RSAKey};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new("claims", key(), Some(Algorithm::HS256))
+        .finalize()
+        .unwrap();
+    let options = |algorithms: &[Algorithm]| ValidationOptions::new(algorithms);
+    assert!(Jwt::verify_with(&token, &key(), &options(&[Algorithm::HS256])).is_ok());
+    let err = Jwt::verify_with(&token, &key(), &options(&[Algorithm::HS384])).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::DisallowedAlgorithm("HS256".to_string()))
+    );
+    assert!(Jwt::verify_with(&token, &key(), &options(&[])).is_err());
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_ok());
+    assert!(Jwt::verify(&token, &key(), &[]).is_err());
+
+    // RSAKey verifies both RS256 and PS256, the options narrow that down
+    let rsa = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let pss = Jwt::new("claims", &rsa, Some(Algorithm::PS256))
+        .finalize()
+        .unwrap();
+    assert!(Jwt::verify(&pss, &rsa, &[Algorithm::PS256]).is_ok());
+    assert!(Jwt::verify(&pss, &rsa, &[Algorithm::RS256]).is_err());
+    assert!(Jwt::verify_with(&pss, &rsa, &options(&[Algorithm::RS256])).is_err());
+    let pinned = options(&[Algorithm::RS256, Algorithm::PS256]);
+    assert!(Jwt::verify_with(&pss, &rsa, &pinned).is_ok());
+}
+
+#[test]
+fn test_validation_required_claims() {
+    let options =
+        ValidationOptions::new(&[Algorithm::HS256]).require_claims(&["exp", "iss", "aud"]);
+    let validate = |claims| options.validate_claims(&claims, Some(0));
+    assert!(validate(serde_json::json!({"exp": 10, "iss": "me", "aud": "api"})).is_ok());
+    let err = validate(serde_json::json!({"iss": "me", "aud": "api"})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::MissingClaim("exp".to_string()))
+    );
+    assert_eq!(err.to_string(), "Token has no exp claim");
+    let err = validate(serde_json::json!({"exp": 10, "iss": "me", "aud": null})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::MissingClaim("aud".to_string()))
+    );
+    assert!(validate(serde_json::json!("claims")).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_validation_clock() {
+    use crate::{FixedClock, HmacKey, Jwt};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(serde_json::json!({"nbf": 1_000, "exp": 2_000}), key(), None)
+        .finalize()
+        .unwrap();
+    let at = |seconds| {
+        ValidationOptions::new(&[Algorithm::HS256]).with_clock(FixedClock::from_unix(seconds))
+    };
+    assert!(Jwt::verify_with(&token, &key(), &at(999)).is_err());
+    assert!(Jwt::verify_with(&token, &key(), &at(1_500)).is_ok());
+    assert!(Jwt::verify_with(&token, &key(), &at(2_000)).is_err());
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+}
+
+#[test]
+fn test_validation_max_age() {
+    let now = 1_000_000;
+    let options =
+        ValidationOptions::new(&[Algorithm::HS256]).with_max_age(Duration::from_secs(300));
+    let validate =
+        |options: &ValidationOptions, claims| options.validate_claims(&claims, Some(now));
+    assert!(validate(&options, serde_json::json!({"iat": now - 299})).is_ok());
+    let err = validate(
+        &options,
+        serde_json::json!({"iat": now - 300, "exp": now + 60}),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Token was issued too long ago");
+    let err = validate(&options, serde_json::json!({"exp": now + 60})).unwrap_err();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::MissingClaim("iat".to_string()))
+    );
+    assert!(validate(&options, serde_json::json!({"iat": "yesterday"})).is_err());
+
+    let lenient = options.with_leeway(30);
+    assert!(validate(&lenient, serde_json::json!({"iat": now - 329})).is_ok());
+    assert!(validate(&lenient, serde_json::json!({"iat": now - 330})).is_err());
+}
@@ -0,0 +1,242 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{http, Algorithm, JwtErr, SigningKey, URL_SAFE_LENIENT};
+
+type TokenSource = Arc<dyn Fn() -> Result<String, JwtErr> + Send + Sync>;
+
+/// Key in HashiCorp Vault's transit secrets engine, every signature is a `sign` call
+///
+/// The signing input is sent as is, Vault hashes it with the algorithm's digest. ECDSA signatures
+/// are requested in the JWS format and RSA keys are told which padding to use, so Vault's output
+/// only needs its `vault:v<version>:` prefix removed. `token` is asked for a Vault token before
+/// each call, the key name is reported as the `kid`.
+///
+/// ### Example
+///
+/// ```no_run
+/// use smpl_jwt::{Algorithm, Jwt, VaultTransitKey};
+///
+/// let key = VaultTransitKey::new("https://vault.example.com:8200", "jwt-signing", Algorithm::ES256, || {
+///     Ok(std::env::var("VAULT_TOKEN").unwrap())
+/// })
+/// .unwrap()
+/// .with_namespace("team-a");
+/// let token = Jwt::new("claims", key, None).finalize().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct VaultTransitKey {
+    addr: String,
+    name: String,
+    algorithm: Algorithm,
+    mount: String,
+    namespace: Option<String>,
+    token: TokenSource,
+    timeout: Duration,
+}
+
+impl VaultTransitKey {
+    /// `addr` is the Vault server, as in `VAULT_ADDR`, and `name` the transit key
+    pub fn new<F>(addr: &str, name: &str, algorithm: Algorithm, token: F) -> Result<Self, JwtErr>
+    where
+        F: Fn() -> Result<String, JwtErr> + Send + Sync + 'static,
+    {
+        hash_algorithm(&algorithm)?;
+        http::Url::parse(addr)?;
+        if name.is_empty() || name.contains('/') {
+            return Err(JwtErr::from(
+                format!("Invalid transit key name {}", name).as_str(),
+            ));
+        }
+        Ok(VaultTransitKey {
+            addr: addr.trim_end_matches('/').to_string(),
+            name: name.to_string(),
+            algorithm,
+            mount: "transit".to_string(),
+            namespace: None,
+            token: Arc::new(token),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Path the transit engine is mounted at, `transit` by default
+    pub fn with_mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+
+    /// Vault Enterprise namespace the mount lives in, sent as `X-Vault-Namespace`
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Connect, read and write timeout for each `sign` call, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl SigningKey for VaultTransitKey {
+    fn sign(&self, algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+        let mut body = serde_json::json!({ "input": STANDARD.encode(input) });
+        if let Some(hash) = hash_algorithm(algo)? {
+            body["hash_algorithm"] = hash.into();
+        }
+        match *algo {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+                body["signature_algorithm"] = "pkcs1v15".into()
+            }
+            Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => {
+                body["signature_algorithm"] = "pss".into()
+            }
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512 => {
+                body["marshaling_algorithm"] = "jws".into()
+            }
+            _ => {}
+        }
+        let body = body.to_string();
+
+        let token = (self.token)()?;
+        let mut headers = vec![
+            ("X-Vault-Token", token.as_str()),
+            ("Content-Type", "application/json"),
+        ];
+        if let Some(namespace) = &self.namespace {
+            headers.push(("X-Vault-Namespace", namespace));
+        }
+        let response = http::request(
+            "POST",
+            &format!("{}/v1/{}/sign/{}", self.addr, self.mount, self.name),
+            &headers,
+            body.as_bytes(),
+            self.timeout,
+        )?;
+        if response.status != 200 {
+            let json: serde_json::Value =
+                serde_json::from_slice(&response.body).unwrap_or_default();
+            return Err(JwtErr::from(
+                format!(
+                    "Vault transit sign failed with status {}: {}",
+                    response.status, json["errors"]
+                )
+                .as_str(),
+            ));
+        }
+        let json: serde_json::Value = serde_json::from_slice(&response.body)?;
+        let signature = json["data"]["signature"]
+            .as_str()
+            .and_then(|signature| signature.strip_prefix("vault:v"))
+            .and_then(|signature| signature.split_once(':'))
+            .map(|(_version, signature)| signature)
+            .ok_or_else(|| JwtErr::from("Vault transit response holds no signature"))?;
+        match *algo {
+            // The `jws` marshaling is base64url, everything else standard base64
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512 => {
+                Ok(URL_SAFE_LENIENT.decode(signature)?)
+            }
+            _ => Ok(STANDARD.decode(signature)?),
+        }
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+/// Runs `sign` on a helper thread so async tasks are not blocked
+#[cfg(feature = "async")]
+impl crate::AsyncSigningKey for VaultTransitKey {
+    fn sign_async<'a>(&'a self, algo: &'a Algorithm, input: &'a [u8]) -> crate::SignFuture<'a> {
+        let key = self.clone();
+        let (algo, input) = (*algo, input.to_vec());
+        Box::pin(crate::async_jwks::unblock(move || key.sign(&algo, &input)))
+    }
+
+    fn default_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn kid(&self) -> Option<&str> {
+        SigningKey::kid(self)
+    }
+}
+
+/// Transit `hash_algorithm` of `algo`, none for Ed25519 keys which hash internally
+fn hash_algorithm(algo: &Algorithm) -> Result<Option<&'static str>, JwtErr> {
+    Ok(match *algo {
+        Algorithm::RS256 | Algorithm::PS256 | Algorithm::ES256 => Some("sha2-256"),
+        Algorithm::RS384 | Algorithm::PS384 | Algorithm::ES384 => Some("sha2-384"),
+        Algorithm::RS512 | Algorithm::PS512 | Algorithm::ES512 => Some("sha2-512"),
+        Algorithm::EdDSA => None,
+        _ => {
+            return Err(JwtErr::from(
+                format!("{} is not supported by Vault transit", algo).as_str(),
+            ))
+        }
+    })
+}
+
+#[test]
+fn test_vault_transit_key() {
+    use crate::{EcKey, Ed25519Key, VerifyingKey};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use std::sync::Mutex;
+
+    let token = || Ok("s.token".to_string());
+    let addr = "https://vault.example.com:8200";
+    assert!(VaultTransitKey::new(addr, "jwt", Algorithm::HS256, token).is_err());
+    assert!(VaultTransitKey::new(addr, "jwt", Algorithm::ES256K, token).is_err());
+    assert!(VaultTransitKey::new(addr, "a/b", Algorithm::ES256, token).is_err());
+    assert!(VaultTransitKey::new("vault:8200", "jwt", Algorithm::ES256, token).is_err());
+    let key = VaultTransitKey::new(addr, "jwt", Algorithm::EdDSA, token).unwrap();
+    assert_eq!(key.kid(), Some("jwt"));
+    assert_eq!(key.default_algorithm(), Algorithm::EdDSA);
+
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let signature = ec.sign(&Algorithm::ES256, b"input").unwrap();
+    let reply = |signature: String| {
+        serde_json::json!({"data": {"signature": signature, "key_version": 2}}).to_string()
+    };
+    let body = Arc::new(Mutex::new(reply(format!(
+        "vault:v2:{}",
+        URL_SAFE_NO_PAD.encode(&signature)
+    ))));
+    let (url, hits) = http::serve(body.clone());
+    let vault = url.trim_end_matches("/jwks");
+    let key = VaultTransitKey::new(vault, "jwt", Algorithm::ES256, token)
+        .unwrap()
+        .with_mount("/secrets/transit/")
+        .with_namespace("team-a");
+    assert_eq!(key.mount, "secrets/transit");
+    let signed = key.sign(&Algorithm::ES256, b"input").unwrap();
+    assert!(ec.verify(&Algorithm::ES256, b"input", &signed).unwrap());
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let ed = Ed25519Key::generate().unwrap();
+    let signature = ed.sign(&Algorithm::EdDSA, b"input").unwrap();
+    *body.lock().unwrap() = reply(format!("vault:v1:{}", STANDARD.encode(&signature)));
+    let key = VaultTransitKey::new(vault, "ed", Algorithm::EdDSA, token).unwrap();
+    assert_eq!(key.sign(&Algorithm::EdDSA, b"input").unwrap(), signature);
+
+    *body.lock().unwrap() = reply("not-a-vault-signature".to_string());
+    assert!(key.sign(&Algorithm::EdDSA, b"input").is_err());
+
+    let page = Arc::new(Mutex::new("<html>Bad Gateway</html>".to_string()));
+    let (url, _) = http::serve_status("502 Bad Gateway", page);
+    let key =
+        VaultTransitKey::new(url.trim_end_matches("/jwks"), "ed", Algorithm::EdDSA, token).unwrap();
+    let err = key.sign(&Algorithm::EdDSA, b"input").unwrap_err();
+    assert!(err.to_string().contains("status 502"), "{}", err);
+}
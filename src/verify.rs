@@ -0,0 +1,855 @@
+use base64::engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use core::fmt;
+use serde::de::DeserializeOwned;
+
+use crate::prelude::*;
+use crate::{
+    Algorithm, Jwt, JwtErr, JwtHeader, TokenSegment, ValidationError, ValidationOptions,
+    VerifyingKey, URL_SAFE_LENIENT,
+};
+
+/// Header and claims of a token whose signature has been checked
+#[derive(Debug)]
+pub struct DecodedJwt<T> {
+    header: JwtHeader,
+    claims: T,
+}
+
+impl<T> DecodedJwt<T> {
+    pub fn header(&self) -> &JwtHeader {
+        &self.header
+    }
+
+    pub fn claims(&self) -> &T {
+        &self.claims
+    }
+
+    pub fn into_claims(self) -> T {
+        self.claims
+    }
+}
+
+/// Tokens can be verified against the key they were signed with, or any other `VerifyingKey`
+/// holding the public half
+///
+/// ### Example
+///
+/// ```
+/// # #[cfg(feature = "openssl")]
+/// # fn main() {
+/// use smpl_jwt::{Algorithm, HmacKey, Jwt};
+///
+/// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let token = Jwt::new("claims", key, Some(Algorithm::HS256)).finalize().unwrap();
+///
+/// let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+/// let decoded = Jwt::verify(&token, &key, &[Algorithm::HS256]).unwrap();
+/// assert_eq!(decoded.header().alg(), "HS256");
+/// assert_eq!(decoded.claims(), "claims");
+/// # }
+/// # #[cfg(not(feature = "openssl"))]
+/// # fn main() {}
+/// ```
+impl Jwt<serde_json::Value> {
+    /// Checks the signature of a compact serialized token and returns its header and claims
+    ///
+    /// The `alg` of the token header has to be one of `algorithms` and fit `key`, whatever else
+    /// the key could verify. Unsigned `alg: "none"` tokens are always rejected. Tokens past their
+    /// `exp` or before their `nbf` are rejected as well.
+    pub fn verify<V: VerifyingKey + ?Sized>(
+        token: &str,
+        key: &V,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        Self::verify_with(token, key, &ValidationOptions::new(algorithms))
+    }
+
+    /// Checks the signature like `verify`, then the header and claims against `options`
+    ///
+    /// Tokens whose `alg` is not among the algorithms `options` allows are rejected before their
+    /// signature is looked at.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt, ValidationOptions};
+    ///
+    /// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let token = Jwt::new("claims", key(), None).with_typ("at+jwt").finalize().unwrap();
+    ///
+    /// let options = ValidationOptions::new(&[Algorithm::HS256]).with_typ("application/at+jwt");
+    /// assert!(Jwt::verify_with(&token, &key(), &options).is_ok());
+    /// let options = ValidationOptions::new(&[Algorithm::HS256]).with_typ("secevent+jwt");
+    /// assert!(Jwt::verify_with(&token, &key(), &options).is_err());
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn verify_with<V: VerifyingKey + ?Sized>(
+        token: &str,
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let decoded = Self::verify_signature(token, key, options)?;
+        options.validate(&decoded.header, &decoded.claims)?;
+        Ok(decoded)
+    }
+
+    /// Same checks as `verify`, deserializing the claims into `T`
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    ///
+    /// # #[cfg(feature = "openssl")]
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt};
+    ///
+    /// # #[cfg(feature = "openssl")]
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Claims {
+    ///   sub: String,
+    /// }
+    ///
+    /// # #[cfg(feature = "openssl")]
+    /// fn main() {
+    ///   let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    ///   let token = Jwt::new(Claims { sub: "me".to_string() }, key, Some(Algorithm::HS256))
+    ///     .finalize()
+    ///     .unwrap();
+    ///
+    ///   let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    ///   let claims: Claims = Jwt::verify_into(&token, &key, &[Algorithm::HS256]).unwrap();
+    ///   assert_eq!(claims.sub, "me");
+    /// }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn verify_into<T: DeserializeOwned, V: VerifyingKey + ?Sized>(
+        token: &str,
+        key: &V,
+        algorithms: &[Algorithm],
+    ) -> Result<T, JwtErr> {
+        Self::verify_into_with(token, key, &ValidationOptions::new(algorithms))
+    }
+
+    /// Same checks as `verify_with`, deserializing the claims into `T`
+    pub fn verify_into_with<T: DeserializeOwned, V: VerifyingKey + ?Sized>(
+        token: &str,
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<T, JwtErr> {
+        Ok(serde_json::from_value(
+            Self::verify_with(token, key, options)?.claims,
+        )?)
+    }
+
+    /// Splits and decodes a token WITHOUT checking its signature or claims
+    ///
+    /// Only meant for looking at e.g. `kid` or `iss` to pick the key to pass to `verify`, nothing
+    /// returned here can be trusted. Malformed input is reported as an error.
+    ///
+    /// ```
+    /// use smpl_jwt::Jwt;
+    ///
+    /// let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJpc3MiOiJtZSJ9.c2ln";
+    /// let (header, claims) = Jwt::decode_unverified(token).unwrap();
+    /// assert_eq!(header.alg(), "HS256");
+    /// assert_eq!(claims["iss"], "me");
+    /// ```
+    pub fn decode_unverified(token: &str) -> Result<(JwtHeader, serde_json::Value), JwtErr> {
+        let (header, payload, _) = split(token)?;
+        let header: JwtHeader = decode_segment(header)?;
+        let claims = decode_payload(&header, payload)?;
+        Ok((header, claims))
+    }
+
+    /// Checks a token with a detached payload, `header..signature` as RFC 7515 Appendix F describes
+    ///
+    /// `payload` are the octets left out of the token, e.g. what `Jwt::detached_payload` returns.
+    /// Tokens carrying a payload of their own are rejected. Claims are validated as by `verify`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openssl")]
+    /// # fn main() {
+    /// use smpl_jwt::{Algorithm, HmacKey, Jwt};
+    ///
+    /// let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    /// let jwt = Jwt::new(serde_json::json!({"doc": "large"}), key(), None);
+    /// let token = jwt.finalize_detached().unwrap();
+    /// let payload = jwt.detached_payload().unwrap();
+    ///
+    /// let decoded = Jwt::verify_detached(&token, &payload, &key(), &[Algorithm::HS256]).unwrap();
+    /// assert_eq!(decoded.claims()["doc"], "large");
+    /// assert!(Jwt::verify_detached(&token, b"{}", &key(), &[Algorithm::HS256]).is_err());
+    /// # }
+    /// # #[cfg(not(feature = "openssl"))]
+    /// # fn main() {}
+    /// ```
+    pub fn verify_detached<V: VerifyingKey + ?Sized>(
+        token: &str,
+        payload: &[u8],
+        key: &V,
+        algorithms: &[Algorithm],
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let options = ValidationOptions::new(algorithms);
+        Self::verify_detached_with(token, payload, key, &options)
+    }
+
+    /// Same checks as `verify_detached`, then the header and claims against `options`
+    pub fn verify_detached_with<V: VerifyingKey + ?Sized>(
+        token: &str,
+        payload: &[u8],
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (encoded_header, detached, signature) = split(token)?;
+        if !detached.is_empty() {
+            return Err(JwtErr::from("Token has a payload, it is not detached"));
+        }
+        let header: JwtHeader = decode_segment(encoded_header)?;
+        let input = |payload: &[u8]| [encoded_header.as_bytes(), b".", payload].concat();
+        let inputs = if header.b64() {
+            // Legacy issuers, `with_legacy_padding` among them, pad the payload
+            vec![
+                input(URL_SAFE_NO_PAD.encode(payload).as_bytes()),
+                input(URL_SAFE.encode(payload).as_bytes()),
+            ]
+        } else {
+            vec![input(payload)]
+        };
+        check_signature(&header, &inputs, signature, key, options)?;
+        let claims = payload_claims(&header, payload.to_vec())?;
+        let decoded = DecodedJwt { header, claims };
+        options.validate(&decoded.header, &decoded.claims)?;
+        Ok(decoded)
+    }
+
+    fn verify_signature<V: VerifyingKey + ?Sized>(
+        token: &str,
+        key: &V,
+        options: &ValidationOptions,
+    ) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+        let (header, payload, signature) = split(token)?;
+        verify_segments(header, payload, signature, key, options)
+    }
+}
+
+/// Checks the signature over the protected `header` and `payload` segments and decodes them
+pub(crate) fn verify_segments<V: VerifyingKey + ?Sized>(
+    encoded_header: &str,
+    payload: &str,
+    signature: &str,
+    key: &V,
+    options: &ValidationOptions,
+) -> Result<DecodedJwt<serde_json::Value>, JwtErr> {
+    let input = [encoded_header, payload].join(".");
+    let header: JwtHeader = decode_segment(encoded_header)?;
+    check_signature(&header, &[input], signature, key, options)?;
+    let claims = decode_payload(&header, payload)?;
+    Ok(DecodedJwt { header, claims })
+}
+
+/// Checks `signature` against the first fitting of `inputs` with the algorithm of `header`
+fn check_signature<V: VerifyingKey + ?Sized, I: AsRef<[u8]>>(
+    header: &JwtHeader,
+    inputs: &[I],
+    signature: &str,
+    key: &V,
+    options: &ValidationOptions,
+) -> Result<(), JwtErr> {
+    let algo = match header.algorithm() {
+        Algorithm::None => return Err(JwtErr::from("Unsecured tokens are not accepted")),
+        algo => algo,
+    };
+    options.check_algorithm(&algo)?;
+    // RFC 7797 3 and 6, verifiers unaware of b64 would otherwise read the input differently
+    match header.param("b64") {
+        None | Some(serde_json::Value::Bool(true)) => {}
+        Some(serde_json::Value::Bool(false))
+            if header
+                .crit()
+                .is_some_and(|crit| crit.iter().any(|name| name == "b64")) => {}
+        Some(serde_json::Value::Bool(false)) => {
+            return Err(JwtErr::from("b64 header has to be listed in crit"))
+        }
+        Some(_) => return Err(JwtErr::from("b64 header has to be a boolean")),
+    }
+
+    let signature = URL_SAFE_LENIENT
+        .decode(signature)
+        .map_err(malformed(TokenSegment::Signature))?;
+    for input in inputs {
+        if key.verify(&algo, input.as_ref(), &signature)? {
+            return Ok(());
+        }
+    }
+    Err(JwtErr::from(ValidationError::InvalidSignature))
+}
+
+/// Splits a compact serialized token into its header, payload and signature segments
+fn split(token: &str) -> Result<(&str, &str, &str), JwtErr> {
+    let mut segments = token.splitn(4, '.');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some(header), Some(payload), Some(signature), None) => Ok((header, payload, signature)),
+        _ => Err(JwtErr::from(ValidationError::MalformedToken {
+            segment: None,
+        })),
+    }
+}
+
+/// Decodes a JSON header, failing with `MalformedToken` for the header segment
+pub(crate) fn decode_segment<T: DeserializeOwned>(segment: &str) -> Result<T, JwtErr> {
+    let json = URL_SAFE_LENIENT
+        .decode(segment)
+        .map_err(malformed(TokenSegment::Header))?;
+    serde_json::from_slice(&json).map_err(malformed(TokenSegment::Header))
+}
+
+/// `MalformedToken` for `segment`, described like the decoding error it is made from
+pub(crate) fn malformed<E: fmt::Display>(segment: TokenSegment) -> impl FnOnce(E) -> JwtErr {
+    move |err| {
+        JwtErr::described(
+            ValidationError::MalformedToken {
+                segment: Some(segment),
+            },
+            &err.to_string(),
+        )
+    }
+}
+
+/// Claims in the payload segment, which holds the payload as is for `b64: false` tokens
+fn decode_payload(header: &JwtHeader, payload: &str) -> Result<serde_json::Value, JwtErr> {
+    let octets = if header.b64() {
+        URL_SAFE_LENIENT
+            .decode(payload)
+            .map_err(malformed(TokenSegment::Payload))?
+    } else {
+        payload.as_bytes().to_vec()
+    };
+    payload_claims(header, octets)
+}
+
+/// RFC 7519 5.2, the payload of a nested token is the inner token itself rather than JSON
+fn payload_claims(header: &JwtHeader, payload: Vec<u8>) -> Result<serde_json::Value, JwtErr> {
+    match header.cty() {
+        Some(cty) if cty.eq_ignore_ascii_case("JWT") => Ok(serde_json::Value::String(
+            String::from_utf8(payload)
+                .map_err(|_| malformed(TokenSegment::Payload)("Nested token is not valid UTF-8"))?,
+        )),
+        _ => serde_json::from_slice(&payload).map_err(malformed(TokenSegment::Payload)),
+    }
+}
+
+#[cfg(all(test, feature = "openssl"))]
+fn tamper(token: &str) -> String {
+    let mut segments: Vec<&str> = token.split('.').collect();
+    let payload = base64::engine::general_purpose::URL_SAFE.encode(b"\"forged\"");
+    segments[1] = &payload;
+    segments.join(".")
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_roundtrip() {
+    use crate::{EcKey, Ed25519Key, Ed448Key, HmacKey, RSAKey, SigningKey};
+
+    fn roundtrip<K: SigningKey + VerifyingKey>(key: K, algo: Algorithm) {
+        let alg = algo.to_string();
+        let jwt = Jwt::new(serde_json::json!({"sub": "me"}), key, Some(algo));
+        let token = jwt.finalize().unwrap();
+        let decoded = Jwt::verify(&token, &jwt.pkey, &[algo]).unwrap();
+        assert_eq!(decoded.header().alg(), alg);
+        assert_eq!(decoded.header().typ(), Some("JWT"));
+        assert_eq!(decoded.claims()["sub"], "me");
+
+        assert!(Jwt::verify(&tamper(&token), &jwt.pkey, &[algo]).is_err());
+    }
+
+    roundtrip(
+        HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap(),
+        Algorithm::HS256,
+    );
+    roundtrip(HmacKey::from_bytes(&[7; 64]).unwrap(), Algorithm::HS384);
+    roundtrip(HmacKey::from_bytes(&[7; 64]).unwrap(), Algorithm::HS512);
+    for algo in [
+        Algorithm::RS256,
+        Algorithm::RS384,
+        Algorithm::RS512,
+        Algorithm::PS256,
+        Algorithm::PS384,
+        Algorithm::PS512,
+    ] {
+        roundtrip(RSAKey::from_pem("random_rsa_for_testing").unwrap(), algo);
+    }
+    roundtrip(
+        EcKey::from_pem("random_ec_for_testing").unwrap(),
+        Algorithm::ES256,
+    );
+    roundtrip(
+        EcKey::from_pem("random_ec384_for_testing").unwrap(),
+        Algorithm::ES384,
+    );
+    roundtrip(
+        EcKey::from_pem("random_ec521_for_testing").unwrap(),
+        Algorithm::ES512,
+    );
+    roundtrip(
+        EcKey::from_pem("random_secp256k1_for_testing").unwrap(),
+        Algorithm::ES256K,
+    );
+    roundtrip(
+        Ed25519Key::from_pem("random_ed25519_for_testing").unwrap(),
+        Algorithm::EdDSA,
+    );
+    roundtrip(
+        Ed448Key::from_pem("random_ed448_for_testing").unwrap(),
+        Algorithm::EdDSA,
+    );
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_with_any_key() {
+    use crate::{EcKey, HmacKey, RSAKey};
+
+    let rsa_key = RSAKey::from_pem("random_rsa_for_testing").unwrap();
+    let token = Jwt::new("body", rsa_key, None).finalize().unwrap();
+
+    let keys: Vec<Box<dyn VerifyingKey>> = vec![
+        Box::new(HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap()),
+        Box::new(
+            EcKey::from_pem("random_ec_for_testing")
+                .unwrap()
+                .public_key()
+                .unwrap(),
+        ),
+        Box::new(
+            RSAKey::from_pem("random_rsa_for_testing")
+                .unwrap()
+                .public_key()
+                .unwrap(),
+        ),
+    ];
+    let verified: Vec<bool> = keys
+        .iter()
+        .map(|key| Jwt::verify(&token, key.as_ref(), &[Algorithm::RS256]).is_ok())
+        .collect();
+    assert_eq!(verified, [false, false, true]);
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_rejects_wrong_key() {
+    use crate::HmacKey;
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new("body", key, Some(Algorithm::HS256))
+        .finalize()
+        .unwrap();
+    let other = HmacKey::from_bytes(b"another-secret-of-thirty-two-bytes").unwrap();
+    assert!(Jwt::verify(&token, &other, &[Algorithm::HS256]).is_err());
+
+    let ec_key = crate::EcKey::from_pem("random_ec_for_testing").unwrap();
+    assert!(Jwt::verify(&token, &ec_key, &[Algorithm::HS256]).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_rejects_malformed() {
+    use crate::HmacKey;
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new("body", key, Some(Algorithm::HS256))
+        .finalize()
+        .unwrap();
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+
+    for malformed in [
+        "",
+        "...",
+        "a.b",
+        "a.b.c.d",
+        "!!!.eyJ9.sig",
+        &format!("{}.extra", token),
+        &token[..token.len() - 4],
+    ] {
+        assert!(Jwt::verify(malformed, &key, &[Algorithm::HS256]).is_err());
+    }
+
+    // alg none must never pass, even with a valid looking payload
+    let unsecured = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.ImJvZHki.";
+    assert!(Jwt::verify(unsecured, &key, &[Algorithm::HS256]).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_into() {
+    use crate::HmacKey;
+    use time::OffsetDateTime;
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: i64,
+    }
+
+    let exp = OffsetDateTime::now_utc().unix_timestamp() + 60;
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(
+        Claims {
+            sub: "me".to_string(),
+            exp,
+        },
+        key,
+        Some(Algorithm::HS256),
+    )
+    .finalize()
+    .unwrap();
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let claims: Claims = Jwt::verify_into(&token, &key, &[Algorithm::HS256]).unwrap();
+    assert_eq!(claims.sub, "me");
+    assert_eq!(claims.exp, exp);
+
+    #[derive(Deserialize, Debug)]
+    struct Other {
+        #[allow(dead_code)]
+        missing: String,
+    }
+    assert!(Jwt::verify_into::<Other, _>(&token, &key, &[Algorithm::HS256]).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_decode_unverified() {
+    use crate::HmacKey;
+
+    let key = HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let token = Jwt::new(
+        serde_json::json!({"iss": "me"}),
+        key,
+        Some(Algorithm::HS256),
+    )
+    .finalize()
+    .unwrap();
+
+    let (header, claims) = Jwt::decode_unverified(&tamper(&token)).unwrap();
+    assert_eq!(header.alg(), "HS256");
+    assert_eq!(claims, "forged");
+
+    let (_, claims) = Jwt::decode_unverified(&token).unwrap();
+    assert_eq!(claims["iss"], "me");
+
+    for malformed in [
+        "",
+        "a.b",
+        "a.b.c.d",
+        "%%%.e30.",
+        "e30.%%%.",
+        "e30.bm90IGpzb24.",
+        "\u{e9}.e30.",
+    ] {
+        assert!(Jwt::decode_unverified(malformed).is_err());
+    }
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_crit_header() {
+    use crate::HmacKey;
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let sign = |jwt: Jwt<&str, HmacKey>| jwt.finalize().unwrap();
+
+    let token = sign(
+        Jwt::new("claims", key(), None)
+            .with_header_param("ext", "value".into())
+            .with_crit(&["ext"]),
+    );
+    let (header, _) = Jwt::decode_unverified(&token).unwrap();
+    assert_eq!(header.crit().unwrap(), ["ext".to_string()]);
+    assert_eq!(header.param("ext").unwrap(), "value");
+    assert!(header.param("alg").is_none());
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+    let understood =
+        |names: &[&str]| ValidationOptions::new(&[Algorithm::HS256]).with_understood_crit(names);
+    assert!(Jwt::verify_with(&token, &key(), &understood(&["other"])).is_err());
+    let decoded = Jwt::verify_with(&token, &key(), &understood(&["ext"])).unwrap();
+    assert_eq!(decoded.header().param("ext").unwrap(), "value");
+
+    // Listed but absent, registered and empty lists are malformed
+    let absent = sign(Jwt::new("claims", key(), None).with_crit(&["ext"]));
+    assert!(Jwt::verify_with(&absent, &key(), &understood(&["ext"])).is_err());
+    let registered = sign(
+        Jwt::new("claims", key(), None)
+            .with_crit(&["kid"])
+            .with_kid("1"),
+    );
+    assert!(Jwt::verify_with(&registered, &key(), &understood(&["kid"])).is_err());
+    let empty = sign(Jwt::new("claims", key(), None).with_crit(&[]));
+    assert!(Jwt::verify_with(&empty, &key(), &understood(&[])).is_err());
+
+    // Extension members without crit may simply be ignored
+    let token = sign(Jwt::new("claims", key(), None).with_header_param("ext", 1.into()));
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_ok());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_typ_header() {
+    use crate::HmacKey;
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let typ = |typ: &str| ValidationOptions::new(&[Algorithm::HS256]).with_typ(typ);
+    let token = Jwt::new("claims", key(), None).finalize().unwrap();
+    assert!(Jwt::verify_with(&token, &key(), &typ("JWT")).is_ok());
+    assert!(Jwt::verify_with(&token, &key(), &typ("at+jwt")).is_err());
+
+    let token = Jwt::new("claims", key(), None)
+        .with_typ("secevent+jwt")
+        .finalize()
+        .unwrap();
+    let decoded = Jwt::verify_with(&token, &key(), &typ("SECEVENT+JWT")).unwrap();
+    assert_eq!(decoded.header().typ(), Some("secevent+jwt"));
+    assert!(decoded.header().typ_matches("application/secevent+jwt"));
+    assert!(!decoded.header().typ_matches("jwt"));
+    assert!(Jwt::verify_with(&token, &key(), &typ("JWT")).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_detached_payload() {
+    use crate::{EcKey, HmacKey};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jwt = Jwt::new(serde_json::json!({"sub": "me"}), key(), None);
+    let token = jwt.finalize_detached().unwrap();
+    let payload = jwt.detached_payload().unwrap();
+    assert_eq!(payload, br#"{"sub":"me"}"#);
+    let segments: Vec<&str> = token.split('.').collect();
+    assert_eq!(segments.len(), 3);
+    assert!(segments[1].is_empty());
+    assert_eq!(
+        Jwt::verify_detached(&token, &payload, &key(), &[Algorithm::HS256])
+            .unwrap()
+            .claims()["sub"],
+        "me"
+    );
+    assert!(
+        Jwt::verify_detached(&token, br#"{"sub":"you"}"#, &key(), &[Algorithm::HS256]).is_err()
+    );
+    // Neither form is accepted in place of the other
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+    let attached = jwt.finalize().unwrap();
+    assert!(Jwt::verify_detached(&attached, &payload, &key(), &[Algorithm::HS256]).is_err());
+
+    // Unpadded payload encoding as produced elsewhere, signed by hand
+    let ec = EcKey::from_pem("random_ec_for_testing").unwrap();
+    let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"ES256"}"#);
+    let input = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(b"\"doc\""));
+    let signature = crate::SigningKey::sign(&ec, &Algorithm::ES256, input.as_bytes()).unwrap();
+    let token = format!("{}..{}", header, URL_SAFE_NO_PAD.encode(signature));
+    let options = ValidationOptions::new(&[Algorithm::ES256]);
+    let decoded = Jwt::verify_detached_with(&token, b"\"doc\"", &ec, &options).unwrap();
+    assert_eq!(decoded.claims(), "doc");
+    let options = ValidationOptions::new(&[Algorithm::RS256]);
+    assert!(Jwt::verify_detached_with(&token, b"\"doc\"", &ec, &options).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_unencoded_payload() {
+    use crate::HmacKey;
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let jwt = Jwt::new(serde_json::json!({"sub": "me"}), key(), None)
+        .with_header_param("ext", 1.into())
+        .with_crit(&["ext"])
+        .with_unencoded_payload();
+    let token = jwt.finalize().unwrap();
+    let (header, _) = Jwt::decode_unverified(&token).unwrap();
+    assert!(!header.b64());
+    assert_eq!(header.crit().unwrap(), ["ext", "b64"]);
+    assert_eq!(token.split('.').nth(1), Some(r#"{"sub":"me"}"#));
+    let understood = ValidationOptions::new(&[Algorithm::HS256]).with_understood_crit(&["ext"]);
+    let decoded = Jwt::verify_with(&token, &key(), &understood).unwrap();
+    assert_eq!(decoded.claims()["sub"], "me");
+    // The signature covers the raw payload, the encoded one does not verify
+    let encoded = token.replace(r#"{"sub":"me"}"#, &URL_SAFE.encode(br#"{"sub":"me"}"#));
+    assert!(Jwt::verify_with(&encoded, &key(), &understood).is_err());
+
+    // `with_crit` afterwards keeps `b64` listed
+    let token = Jwt::new(serde_json::json!({"sub": "me"}), key(), None)
+        .with_header_param("ext", 1.into())
+        .with_unencoded_payload()
+        .with_crit(&["ext"])
+        .finalize()
+        .unwrap();
+    let (header, _) = Jwt::decode_unverified(&token).unwrap();
+    assert_eq!(header.crit().unwrap(), ["ext", "b64"]);
+    assert!(Jwt::verify_with(&token, &key(), &understood).is_ok());
+    let token = Jwt::new("claims", key(), None)
+        .with_unencoded_payload()
+        .with_crit(&["b64"])
+        .finalize()
+        .unwrap();
+    let (header, _) = Jwt::decode_unverified(&token).unwrap();
+    assert_eq!(header.crit().unwrap(), ["b64"]);
+
+    // Payloads with a '.' only go detached
+    let jwt = Jwt::new("1.5", key(), None).with_unencoded_payload();
+    assert!(jwt.finalize().is_err());
+    let token = jwt.finalize_detached().unwrap();
+    let decoded = Jwt::verify_detached(&token, br#""1.5""#, &key(), &[Algorithm::HS256]).unwrap();
+    assert_eq!(decoded.claims(), "1.5");
+    assert!(Jwt::verify_detached(&token, br#""1.6""#, &key(), &[Algorithm::HS256]).is_err());
+
+    // b64 false is only honoured when listed in crit
+    let token = Jwt::new("claims", key(), None)
+        .with_header_param("b64", false.into())
+        .finalize()
+        .unwrap();
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+    let token = Jwt::new("claims", key(), None)
+        .with_header_param("b64", "no".into())
+        .with_crit(&["b64"])
+        .finalize()
+        .unwrap();
+    assert!(Jwt::verify(&token, &key(), &[Algorithm::HS256]).is_err());
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_verify_error_variants() {
+    use crate::{HmacKey, JwkSet, Keyring};
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+
+    let key = || HmacKey::from_bytes(b"secret-of-at-least-thirty-two-bytes").unwrap();
+    let failure = |token: &str| {
+        Jwt::verify(token, &key(), &[Algorithm::HS256])
+            .err()
+            .unwrap()
+            .validation_error()
+            .cloned()
+    };
+    let token = Jwt::new("body", key(), None).finalize().unwrap();
+    assert_eq!(
+        failure(&tamper(&token)),
+        Some(ValidationError::InvalidSignature)
+    );
+    let malformed = |segment| Some(ValidationError::MalformedToken { segment });
+    assert_eq!(failure("a.b"), malformed(None));
+    assert_eq!(
+        failure("!!!.e30.c2ln"),
+        malformed(Some(TokenSegment::Header))
+    );
+    assert_eq!(
+        failure("e30.e30.c2ln"),
+        malformed(Some(TokenSegment::Header))
+    );
+    let (input, _) = token.rsplit_once('.').unwrap();
+    assert_eq!(
+        failure(&format!("{}.*", input)),
+        malformed(Some(TokenSegment::Signature))
+    );
+    let err = Jwt::parse(&format!("{}.*", input)).err().unwrap();
+    assert_eq!(
+        err.validation_error().cloned(),
+        malformed(Some(TokenSegment::Signature))
+    );
+    assert!(err
+        .to_string()
+        .starts_with("The signature segment is not valid"));
+
+    let mut keyring = Keyring::new(Duration::from_secs(60));
+    keyring.add("k1", key(), SystemTime::UNIX_EPOCH);
+    let token = Jwt::new("body", key(), None)
+        .with_kid("k2")
+        .finalize()
+        .unwrap();
+    let err = keyring.verify(&token, &[Algorithm::HS256]).err().unwrap();
+    assert_eq!(
+        err.validation_error(),
+        Some(&ValidationError::UnknownKid("k2".to_string()))
+    );
+    assert_eq!(err.to_string(), "No key with kid k2 in the keyring");
+    let set = JwkSet::from_str(r#"{"keys": []}"#).unwrap();
+    assert!(matches!(
+        set.verify(&token, &[Algorithm::HS256]).err().unwrap().validation_error(),
+        Some(ValidationError::UnknownKid(kid)) if kid == "k2"
+    ));
+}
+
+#[test]
+fn test_verify_without_openssl() {
+    use crate::{Clock, SigningKey, TokenSigner};
+
+    // FNV-1a seeded with the key, enough to tell keys and inputs apart without any crypto backend
+    struct ToyKey(u64);
+
+    impl SigningKey for ToyKey {
+        fn sign(&self, _algo: &Algorithm, input: &[u8]) -> Result<Vec<u8>, JwtErr> {
+            let hash = input
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325 ^ self.0, |hash, byte| {
+                    (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+                });
+            Ok(hash.to_be_bytes().to_vec())
+        }
+
+        fn default_algorithm(&self) -> Algorithm {
+            Algorithm::HS256
+        }
+    }
+
+    impl VerifyingKey for ToyKey {
+        fn verify(&self, algo: &Algorithm, input: &[u8], signature: &[u8]) -> Result<bool, JwtErr> {
+            Ok(self.sign(algo, input)? == signature)
+        }
+    }
+
+    #[derive(Debug)]
+    struct At(i64);
+
+    impl Clock for At {
+        fn unix_now(&self) -> i64 {
+            self.0
+        }
+    }
+
+    let claims = serde_json::json!({"sub": "me", "exp": 1_000_060});
+    let token = Jwt::new(claims.clone(), ToyKey(7), None)
+        .finalize()
+        .unwrap();
+    assert_eq!(
+        TokenSigner::new(ToyKey(7), None)
+            .unwrap()
+            .sign(&claims)
+            .unwrap(),
+        token
+    );
+    let at = |now| ValidationOptions::new(&[Algorithm::HS256]).with_clock(At(now));
+    let decoded = Jwt::verify_with(&token, &ToyKey(7), &at(1_000_000)).unwrap();
+    assert_eq!(decoded.header().alg(), "HS256");
+    assert_eq!(decoded.claims()["sub"], "me");
+
+    assert!(matches!(
+        Jwt::verify_with(&token, &ToyKey(7), &at(1_000_060))
+            .err()
+            .unwrap()
+            .validation_error(),
+        Some(ValidationError::Expired { .. })
+    ));
+    assert!(Jwt::verify_with(&token, &ToyKey(8), &at(1_000_000)).is_err());
+    let options = ValidationOptions::new(&[Algorithm::RS256]).with_clock(At(1_000_000));
+    assert!(Jwt::verify_with(&token, &ToyKey(7), &options).is_err());
+}